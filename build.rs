@@ -23,21 +23,16 @@ use std::fs::File;
 use std::path::Path;
 
 
-#[cfg(target_os = "macos")]
-fn register_gl_api(file: &mut File) {
-    Registry::new(Api::Gl, (3, 3), Profile::Core, Fallbacks::All, [])
-        .write_bindings(GlobalGenerator, file)
-        .unwrap();
-}
-
-#[cfg(target_os = "windows")]
-fn register_gl_api(file: &mut File) {
-    Registry::new(Api::Gl, (4, 6), Profile::Core, Fallbacks::All, [])
+/// Generate OpenGL ES 3.0 bindings. This is used for the `gles` feature.
+fn register_gles_api(file: &mut File) {
+    Registry::new(Api::Gles2, (3, 0), Profile::Core, Fallbacks::All, [])
         .write_bindings(GlobalGenerator, file)
         .unwrap();
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+/// Generate desktop OpenGL bindings. The bindings cover the newest version of
+/// OpenGL on every platform. Start-up negotiates the actual context version, and
+/// the renderer only calls functions from the feature tiers the context reports.
 fn register_gl_api(file: &mut File) {
     Registry::new(Api::Gl, (4, 6), Profile::Core, Fallbacks::All, [])
         .write_bindings(GlobalGenerator, file)
@@ -48,5 +43,10 @@ fn main() {
     let dest = env::var("OUT_DIR").unwrap();
     let mut file = File::create(&Path::new(&dest).join("gl_bindings.rs")).unwrap();
 
-    register_gl_api(&mut file);
+    // Cargo exposes the enabled features to build scripts through the environment.
+    if env::var("CARGO_FEATURE_GLES").is_ok() {
+        register_gles_api(&mut file);
+    } else {
+        register_gl_api(&mut file);
+    }
 }
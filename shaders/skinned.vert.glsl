@@ -0,0 +1,27 @@
+#version 330 core
+
+// This must match MAX_JOINTS in animation.rs.
+#define MAX_JOINTS 128
+
+layout (location = 0) in vec3 v_pos;
+layout (location = 1) in vec2 v_tex;
+in uvec4 v_joints;
+in vec4 v_weights;
+uniform mat4 v_scale_mat;
+uniform mat4 v_trans_mat;
+
+layout (std140) uniform JointPalette {
+    mat4 u_joints[MAX_JOINTS];
+};
+
+out vec2 ov_tex_coord;
+
+
+void main() {
+    mat4 skin = v_weights.x * u_joints[v_joints.x]
+        + v_weights.y * u_joints[v_joints.y]
+        + v_weights.z * u_joints[v_joints.z]
+        + v_weights.w * u_joints[v_joints.w];
+    ov_tex_coord = v_tex;
+    gl_Position = v_trans_mat * v_scale_mat * skin * vec4(v_pos, 1.0);
+}
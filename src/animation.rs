@@ -0,0 +1,655 @@
+/*
+ *  GB-Prototype is a renderer prototype demo.
+ *  Copyright (C) 2018,2019,2020  Christopher Blanchard
+ *
+ *  This program is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  This program is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+#![allow(dead_code)]
+//! Skeletal animation. A skeleton is a hierarchy of joints, an animation clip holds
+//! keyframes for the joints, and sampling a clip gives a pose. The joint palette of
+//! a pose holds the matrices the skinning shader blends vertices with.
+use crate::renderer::ShaderSource;
+
+use std::fmt;
+use std::slice;
+
+
+/// The most joints a skeleton can have. This is the size of the joint palette
+/// uniform block in the skinning shader.
+pub const MAX_JOINTS: usize = 128;
+/// The name of the joint palette uniform block in the skinning shader.
+pub const JOINT_PALETTE_BLOCK: &str = "JointPalette";
+
+/// A column major 4x4 matrix.
+pub type Mat4 = [[f32; 4]; 4];
+
+pub const MAT4_IDENTITY: Mat4 = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+/// Multiply two column major 4x4 matrices.
+pub fn mat4_mul(a: &Mat4, b: &Mat4) -> Mat4 {
+    let mut result = [[0.0; 4]; 4];
+    for column in 0..4 {
+        for row in 0..4 {
+            result[column][row] = (0..4).map(|k| a[k][row] * b[column][k]).sum();
+        }
+    }
+
+    result
+}
+
+/// Compose a translation, a rotation quaternion `[x, y, z, w]` and a scale into a
+/// column major matrix that applies them in the order scale, rotate, translate.
+pub fn mat4_from_trs(t: [f32; 3], r: [f32; 4], s: [f32; 3]) -> Mat4 {
+    let (x, y, z, w) = (r[0], r[1], r[2], r[3]);
+    [
+        [(1.0 - 2.0 * (y * y + z * z)) * s[0], (2.0 * (x * y + z * w)) * s[0], (2.0 * (x * z - y * w)) * s[0], 0.0],
+        [(2.0 * (x * y - z * w)) * s[1], (1.0 - 2.0 * (x * x + z * z)) * s[1], (2.0 * (y * z + x * w)) * s[1], 0.0],
+        [(2.0 * (x * z + y * w)) * s[2], (2.0 * (y * z - x * w)) * s[2], (1.0 - 2.0 * (x * x + y * y)) * s[2], 0.0],
+        [t[0], t[1], t[2], 1.0],
+    ]
+}
+
+#[inline]
+fn normalize_quaternion(q: [f32; 4]) -> [f32; 4] {
+    let length = f32::sqrt(q[0] * q[0] + q[1] * q[1] + q[2] * q[2] + q[3] * q[3]);
+    if length > 0_f32 {
+        [q[0] / length, q[1] / length, q[2] / length, q[3] / length]
+    } else {
+        [0_f32, 0_f32, 0_f32, 1_f32]
+    }
+}
+
+/// Spherically interpolate between two unit quaternions along the shorter arc.
+pub fn slerp(a: [f32; 4], b: [f32; 4], t: f32) -> [f32; 4] {
+    let mut cos_theta = a[0] * b[0] + a[1] * b[1] + a[2] * b[2] + a[3] * b[3];
+    // q and -q are the same rotation. Flip one to take the shorter way round.
+    let b = if cos_theta < 0_f32 {
+        cos_theta = -cos_theta;
+        [-b[0], -b[1], -b[2], -b[3]]
+    } else {
+        b
+    };
+    // Nearly parallel quaternions divide by almost zero below, and a linear
+    // interpolation is just as good there.
+    let (wa, wb) = if cos_theta > 0.9995 {
+        (1_f32 - t, t)
+    } else {
+        let theta = f32::acos(cos_theta);
+        let sin_theta = f32::sin(theta);
+        (f32::sin((1_f32 - t) * theta) / sin_theta, f32::sin(t * theta) / sin_theta)
+    };
+
+    normalize_quaternion([
+        wa * a[0] + wb * b[0], wa * a[1] + wb * b[1], wa * a[2] + wb * b[2], wa * a[3] + wb * b[3],
+    ])
+}
+
+/// A transform as a translation, a rotation quaternion `[x, y, z, w]` and a scale.
+/// This is the form animations interpolate.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: [f32; 3],
+    pub rotation: [f32; 4],
+    pub scale: [f32; 3],
+}
+
+impl Transform {
+    pub fn identity() -> Transform {
+        Transform {
+            translation: [0_f32; 3],
+            rotation: [0_f32, 0_f32, 0_f32, 1_f32],
+            scale: [1_f32; 3],
+        }
+    }
+
+    /// Split a matrix without shear or perspective into a transform.
+    pub fn from_matrix(m: &Mat4) -> Transform {
+        let length = |c: &[f32; 4]| f32::sqrt(c[0] * c[0] + c[1] * c[1] + c[2] * c[2]);
+        let mut scale = [length(&m[0]), length(&m[1]), length(&m[2])];
+        // A mirroring matrix has a negative determinant. Put the mirror in the scale.
+        let determinant = m[0][0] * (m[1][1] * m[2][2] - m[2][1] * m[1][2])
+            - m[1][0] * (m[0][1] * m[2][2] - m[2][1] * m[0][2])
+            + m[2][0] * (m[0][1] * m[1][2] - m[1][1] * m[0][2]);
+        if determinant < 0_f32 {
+            scale[0] = -scale[0];
+        }
+        // The element at row `r` and column `c` of the rotation.
+        let r = |row: usize, column: usize| {
+            if scale[column] == 0_f32 { 0_f32 } else { m[column][row] / scale[column] }
+        };
+
+        let trace = r(0, 0) + r(1, 1) + r(2, 2);
+        let rotation = if trace > 0_f32 {
+            let s = f32::sqrt(trace + 1_f32) * 2_f32;
+            [(r(2, 1) - r(1, 2)) / s, (r(0, 2) - r(2, 0)) / s, (r(1, 0) - r(0, 1)) / s, 0.25 * s]
+        } else if r(0, 0) > r(1, 1) && r(0, 0) > r(2, 2) {
+            let s = f32::sqrt(1_f32 + r(0, 0) - r(1, 1) - r(2, 2)) * 2_f32;
+            [0.25 * s, (r(0, 1) + r(1, 0)) / s, (r(0, 2) + r(2, 0)) / s, (r(2, 1) - r(1, 2)) / s]
+        } else if r(1, 1) > r(2, 2) {
+            let s = f32::sqrt(1_f32 + r(1, 1) - r(0, 0) - r(2, 2)) * 2_f32;
+            [(r(0, 1) + r(1, 0)) / s, 0.25 * s, (r(1, 2) + r(2, 1)) / s, (r(0, 2) - r(2, 0)) / s]
+        } else {
+            let s = f32::sqrt(1_f32 + r(2, 2) - r(0, 0) - r(1, 1)) * 2_f32;
+            [(r(0, 2) + r(2, 0)) / s, (r(1, 2) + r(2, 1)) / s, 0.25 * s, (r(1, 0) - r(0, 1)) / s]
+        };
+
+        Transform {
+            translation: [m[3][0], m[3][1], m[3][2]],
+            rotation: normalize_quaternion(rotation),
+            scale: scale,
+        }
+    }
+
+    #[inline]
+    pub fn to_matrix(&self) -> Mat4 {
+        mat4_from_trs(self.translation, self.rotation, self.scale)
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Transform {
+        Transform::identity()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SkeletonError {
+    /// A skeleton has more joints than the skinning shader takes.
+    TooManyJoints(usize),
+    /// A joint has a parent that does not exist, or is its own ancestor.
+    InvalidParent(usize),
+    /// A skeleton was asked for from a skin that does not exist.
+    SkinNotFound(usize),
+}
+
+impl fmt::Display for SkeletonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &SkeletonError::TooManyJoints(count) => {
+                write!(f, "The skeleton has {} joints. At most {} are supported", count, MAX_JOINTS)
+            }
+            &SkeletonError::InvalidParent(joint) => {
+                write!(f, "Joint {} has an invalid parent", joint)
+            }
+            &SkeletonError::SkinNotFound(skin) => {
+                write!(f, "There is no skin {} to build a skeleton from", skin)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SkeletonError {}
+
+/// A joint of a skeleton.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Joint {
+    pub name: String,
+    pub parent: Option<usize>,
+    /// The transform relative to the parent joint when no animation moves it.
+    pub rest: Transform,
+    /// The transform from the space of the mesh to the space of the joint in the
+    /// bind pose.
+    pub inverse_bind: Mat4,
+}
+
+/// A hierarchy of joints. The joint indices are the indices in the joint
+/// attributes of the meshes bound to the skeleton.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Skeleton {
+    joints: Vec<Joint>,
+    /// The joints ordered so that every parent comes before its children.
+    order: Vec<usize>,
+    /// The transform applied above the joints without a parent, e.g. the transform
+    /// of the node holding an imported skeleton.
+    pub root_transform: Mat4,
+}
+
+impl Skeleton {
+    pub fn new(joints: Vec<Joint>) -> Result<Skeleton, SkeletonError> {
+        if joints.len() > MAX_JOINTS {
+            return Err(SkeletonError::TooManyJoints(joints.len()));
+        }
+        let mut depths: Vec<usize> = Vec::with_capacity(joints.len());
+        for (i, joint) in joints.iter().enumerate() {
+            // A chain longer than the joint count has a cycle in it.
+            let mut depth = 0;
+            let mut parent = joint.parent;
+            while let Some(index) = parent {
+                if index >= joints.len() || depth >= joints.len() {
+                    return Err(SkeletonError::InvalidParent(i));
+                }
+                depth += 1;
+                parent = joints[index].parent;
+            }
+            depths.push(depth);
+        }
+        let mut order: Vec<usize> = (0..joints.len()).collect();
+        order.sort_by_key(|&joint| depths[joint]);
+
+        Ok(Skeleton {
+            joints: joints,
+            order: order,
+            root_transform: MAT4_IDENTITY,
+        })
+    }
+
+    #[inline]
+    pub fn joints(&self) -> &[Joint] {
+        &self.joints
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.joints.len()
+    }
+
+    /// Find a joint by name.
+    pub fn joint(&self, name: &str) -> Option<usize> {
+        self.joints.iter().position(|joint| joint.name == name)
+    }
+
+    /// The pose with every joint at rest.
+    pub fn rest_pose(&self) -> Vec<Transform> {
+        self.joints.iter().map(|joint| joint.rest).collect()
+    }
+
+    /// Turn a pose, one transform per joint relative to its parent, into the
+    /// transform of each joint relative to the skeleton.
+    pub fn global_transforms(&self, pose: &[Transform]) -> Vec<Mat4> {
+        let mut globals = vec![MAT4_IDENTITY; self.joints.len()];
+        for &joint in self.order.iter() {
+            let local = pose.get(joint).unwrap_or(&self.joints[joint].rest).to_matrix();
+            let parent = match self.joints[joint].parent {
+                Some(parent) => globals[parent],
+                None => self.root_transform,
+            };
+            globals[joint] = mat4_mul(&parent, &local);
+        }
+
+        globals
+    }
+
+    /// The skinning matrices of a pose.
+    pub fn joint_palette(&self, pose: &[Transform]) -> JointPalette {
+        let globals = self.global_transforms(pose);
+        let matrices = globals.iter().zip(self.joints.iter())
+            .map(|(global, joint)| mat4_mul(global, &joint.inverse_bind))
+            .collect();
+
+        JointPalette { matrices: matrices }
+    }
+}
+
+/// The skinning matrices of a pose, one per joint. Each takes a vertex from the
+/// space of the mesh in the bind pose to its place in the pose.
+#[derive(Clone, Debug, PartialEq)]
+pub struct JointPalette {
+    pub matrices: Vec<Mat4>,
+}
+
+impl JointPalette {
+    /// Present the matrices as bytes for a uniform buffer. The bytes are padded with
+    /// zeros to the `MAX_JOINTS` matrices of the uniform block, since a buffer bound
+    /// to a block must be at least as large as the block. An array of `mat4` has the
+    /// same layout in std140 and std430, so this also suits a storage buffer.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let count = usize::min(self.matrices.len(), MAX_JOINTS);
+        let matrices = unsafe {
+            slice::from_raw_parts(self.matrices.as_ptr() as *const u8, count * 64)
+        };
+        let mut bytes = vec![0_u8; MAX_JOINTS * 64];
+        bytes[..matrices.len()].copy_from_slice(matrices);
+
+        bytes
+    }
+}
+
+/// How to get from one keyframe to the next.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Hold each keyframe until the next one.
+    Step,
+    /// Interpolate linearly, or spherically for rotations.
+    Linear,
+    /// A cubic Hermite spline. Every keyframe has an in tangent, a value and an out
+    /// tangent, in that order.
+    CubicSpline,
+}
+
+/// The keyframe values of a channel, and the property of a joint they animate.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ChannelValues {
+    Translation(Vec<[f32; 3]>),
+    /// Unit quaternions `[x, y, z, w]`.
+    Rotation(Vec<[f32; 4]>),
+    Scale(Vec<[f32; 3]>),
+}
+
+/// The keyframes of one property of one joint.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Channel {
+    /// The index of the joint the channel animates.
+    pub target: usize,
+    /// The time of each keyframe in seconds, in increasing order.
+    pub times: Vec<f32>,
+    pub values: ChannelValues,
+    pub interpolation: Interpolation,
+}
+
+/// Find the keyframes around a time. Returns their indices and how far the time is
+/// between them as a fraction, and the time between them in seconds.
+fn find_keyframes(times: &[f32], time: f32) -> (usize, usize, f32, f32) {
+    let last = times.len() - 1;
+    if time <= times[0] {
+        return (0, 0, 0_f32, 0_f32);
+    }
+    if time >= times[last] {
+        return (last, last, 0_f32, 0_f32);
+    }
+    let next = times.iter().position(|&t| t > time).unwrap_or(last);
+    let previous = next - 1;
+    let delta = times[next] - times[previous];
+    let fraction = if delta > 0_f32 { (time - times[previous]) / delta } else { 0_f32 };
+
+    (previous, next, fraction, delta)
+}
+
+/// Evaluate a cubic Hermite spline between values `v0` and `v1` with out tangent
+/// `m0` and in tangent `m1`, scaled by the time between the keyframes.
+#[inline]
+fn hermite(v0: f32, m0: f32, v1: f32, m1: f32, t: f32, delta: f32) -> f32 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    (2_f32 * t3 - 3_f32 * t2 + 1_f32) * v0
+        + (t3 - 2_f32 * t2 + t) * delta * m0
+        + (-2_f32 * t3 + 3_f32 * t2) * v1
+        + (t3 - t2) * delta * m1
+}
+
+/// Sample keyframes of any width. `blend` interpolates two values linearly.
+fn sample_keyframes<T, F>(times: &[f32], values: &[T], interpolation: Interpolation, time: f32, blend: F) -> Option<T>
+    where T: Copy + AsRef<[f32]> + AsMut<[f32]>, F: Fn(T, T, f32) -> T {
+
+    if times.is_empty() {
+        return None;
+    }
+    let (previous, next, fraction, delta) = find_keyframes(times, time);
+    match interpolation {
+        Interpolation::Step => values.get(previous).cloned(),
+        Interpolation::Linear => Some(blend(*values.get(previous)?, *values.get(next)?, fraction)),
+        Interpolation::CubicSpline => {
+            let v0 = *values.get(previous * 3 + 1)?;
+            let m0 = *values.get(previous * 3 + 2)?;
+            let v1 = *values.get(next * 3 + 1)?;
+            let m1 = *values.get(next * 3)?;
+            let mut result = v0;
+            for (i, component) in result.as_mut().iter_mut().enumerate() {
+                *component = hermite(
+                    v0.as_ref()[i], m0.as_ref()[i], v1.as_ref()[i], m1.as_ref()[i], fraction, delta
+                );
+            }
+
+            Some(result)
+        }
+    }
+}
+
+fn lerp3(a: [f32; 3], b: [f32; 3], t: f32) -> [f32; 3] {
+    [a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t, a[2] + (b[2] - a[2]) * t]
+}
+
+impl Channel {
+    /// Set the property the channel animates on a joint transform to its value at
+    /// a time. Times outside the keyframes take the first or the last value.
+    pub fn apply(&self, time: f32, transform: &mut Transform) {
+        let interpolation = self.interpolation;
+        match self.values {
+            ChannelValues::Translation(ref values) => {
+                if let Some(value) = sample_keyframes(&self.times, values, interpolation, time, lerp3) {
+                    transform.translation = value;
+                }
+            }
+            ChannelValues::Rotation(ref values) => {
+                if let Some(value) = sample_keyframes(&self.times, values, interpolation, time, slerp) {
+                    transform.rotation = normalize_quaternion(value);
+                }
+            }
+            ChannelValues::Scale(ref values) => {
+                if let Some(value) = sample_keyframes(&self.times, values, interpolation, time, lerp3) {
+                    transform.scale = value;
+                }
+            }
+        }
+    }
+
+    /// The time of the last keyframe.
+    #[inline]
+    pub fn end_time(&self) -> f32 {
+        self.times.last().cloned().unwrap_or(0_f32)
+    }
+}
+
+/// A named animation of the joints of a skeleton.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnimationClip {
+    pub name: String,
+    pub channels: Vec<Channel>,
+    /// The length of the clip in seconds.
+    pub duration: f32,
+}
+
+impl AnimationClip {
+    /// Create a clip that lasts until its last keyframe.
+    pub fn new(name: &str, channels: Vec<Channel>) -> AnimationClip {
+        let duration = channels.iter().map(|channel| channel.end_time()).fold(0_f32, f32::max);
+
+        AnimationClip {
+            name: String::from(name),
+            channels: channels,
+            duration: duration,
+        }
+    }
+
+    /// Pose the joints of a pose at a time in seconds. Joints the clip does not
+    /// animate keep their transforms. A looping clip wraps the time around its
+    /// duration.
+    pub fn sample_into(&self, time: f32, looping: bool, pose: &mut [Transform]) {
+        let time = if looping && self.duration > 0_f32 {
+            time.rem_euclid(self.duration)
+        } else {
+            time
+        };
+        for channel in self.channels.iter() {
+            if let Some(transform) = pose.get_mut(channel.target) {
+                channel.apply(time, transform);
+            }
+        }
+    }
+
+    /// The pose of a skeleton at a time in seconds, starting from its rest pose.
+    pub fn sample(&self, skeleton: &Skeleton, time: f32, looping: bool) -> Vec<Transform> {
+        let mut pose = skeleton.rest_pose();
+        self.sample_into(time, looping, &mut pose);
+
+        pose
+    }
+}
+
+/// The skinning variant of the triangle shader. It blends each vertex by up to
+/// four joints of the palette in the `JointPalette` uniform block.
+pub fn skinned_shader_source() -> ShaderSource {
+    ShaderSource {
+        vert_name: "skinned.vert.glsl",
+        vert_source: include_str!("../shaders/skinned.vert.glsl"),
+        frag_name: "triangle.frag.glsl",
+        frag_source: include_str!("../shaders/triangle.frag.glsl"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    fn assert_close(a: &[f32], b: &[f32]) {
+        assert_eq!(a.len(), b.len());
+        for i in 0..a.len() {
+            assert!((a[i] - b[i]).abs() < 1e-5, "{:?} != {:?}", a, b);
+        }
+    }
+
+    /// A rotation by `angle` radians about the Z axis.
+    fn rotation_z(angle: f32) -> [f32; 4] {
+        let (sin, cos) = f32::sin_cos(angle / 2_f32);
+        [0_f32, 0_f32, sin, cos]
+    }
+
+    fn joint(name: &str, parent: Option<usize>) -> Joint {
+        Joint {
+            name: String::from(name),
+            parent: parent,
+            rest: Transform::identity(),
+            inverse_bind: MAT4_IDENTITY,
+        }
+    }
+
+    #[test]
+    fn test_slerp() {
+        let (a, b) = (rotation_z(0_f32), rotation_z(::std::f32::consts::FRAC_PI_2));
+
+        assert_close(&slerp(a, b, 0_f32), &a);
+        assert_close(&slerp(a, b, 1_f32), &b);
+        assert_close(&slerp(a, b, 0.5), &rotation_z(::std::f32::consts::FRAC_PI_4));
+        assert_close(&slerp(a, b, 0.25), &rotation_z(::std::f32::consts::FRAC_PI_8));
+    }
+
+    #[test]
+    fn test_slerp_takes_the_shorter_arc() {
+        let a = rotation_z(0_f32);
+        let b = rotation_z(::std::f32::consts::FRAC_PI_2);
+        let negated = [-b[0], -b[1], -b[2], -b[3]];
+
+        assert_close(&slerp(a, negated, 0.5), &slerp(a, b, 0.5));
+    }
+
+    #[test]
+    fn test_slerp_nearly_parallel() {
+        let (a, b) = (rotation_z(0_f32), rotation_z(0.001));
+        let q = slerp(a, b, 0.5);
+        let length = f32::sqrt(q.iter().map(|x| x * x).sum());
+
+        assert!((length - 1_f32).abs() < 1e-6);
+        assert_close(&q, &rotation_z(0.0005));
+    }
+
+    #[test]
+    fn test_sample_step_and_linear() {
+        let times = [0_f32, 1_f32, 3_f32];
+        let values = [[0_f32; 3], [2_f32, 4_f32, 6_f32], [4_f32, 4_f32, 4_f32]];
+        let sample = |interpolation, time| sample_keyframes(&times, &values, interpolation, time, lerp3).unwrap();
+
+        assert_close(&sample(Interpolation::Step, 0.9), &[0_f32; 3]);
+        assert_close(&sample(Interpolation::Step, 1_f32), &values[1]);
+        assert_close(&sample(Interpolation::Linear, 0.5), &[1_f32, 2_f32, 3_f32]);
+        assert_close(&sample(Interpolation::Linear, 2_f32), &[3_f32, 4_f32, 5_f32]);
+        assert_close(&sample(Interpolation::Linear, -1_f32), &values[0]);
+        assert_close(&sample(Interpolation::Linear, 5_f32), &values[2]);
+        assert_eq!(sample_keyframes(&[], &values, Interpolation::Linear, 0_f32, lerp3), None);
+    }
+
+    #[test]
+    fn test_sample_cubic_spline_tangents() {
+        // Each keyframe stores an in tangent, a value and an out tangent. The in
+        // tangent of the first keyframe and the out tangent of the last one are
+        // never used, so they hold values that would show up if they were.
+        let times = [0_f32, 2_f32];
+        let values = [
+            [1000_f32; 3], [0_f32; 3], [4_f32, 0_f32, 0_f32],
+            [8_f32, 0_f32, 0_f32], [10_f32, 10_f32, 10_f32], [-1000_f32; 3],
+        ];
+        let sample = |time| sample_keyframes(&times, &values, Interpolation::CubicSpline, time, lerp3).unwrap();
+
+        assert_close(&sample(0_f32), &[0_f32; 3]);
+        assert_close(&sample(2_f32), &[10_f32; 3]);
+        assert_close(&sample(-1_f32), &[0_f32; 3]);
+        assert_close(&sample(3_f32), &[10_f32; 3]);
+        // Halfway, the Hermite basis weighs the out tangent of the first keyframe
+        // by delta / 8 and the in tangent of the second by -delta / 8.
+        assert_close(&sample(1_f32), &[5_f32 + 1_f32 - 2_f32, 5_f32, 5_f32]);
+        // Missing values give nothing rather than reading past the end.
+        assert_eq!(sample_keyframes(&times, &values[..4], Interpolation::CubicSpline, 1_f32, lerp3), None);
+    }
+
+    #[test]
+    fn test_transform_matrix_round_trip() {
+        let rotation = normalize_quaternion([0.2, -0.4, 0.1, 0.9]);
+        let m = mat4_from_trs([1_f32, 2_f32, 3_f32], rotation, [2_f32, 3_f32, 4_f32]);
+        let transform = Transform::from_matrix(&m);
+
+        assert_close(&transform.translation, &[1_f32, 2_f32, 3_f32]);
+        assert_close(&transform.scale, &[2_f32, 3_f32, 4_f32]);
+        let sign = if transform.rotation[3] * rotation[3] < 0_f32 { -1_f32 } else { 1_f32 };
+        assert_close(&transform.rotation.iter().map(|x| x * sign).collect::<Vec<f32>>(), &rotation);
+    }
+
+    #[test]
+    fn test_transform_from_mirroring_matrix() {
+        let rotation = rotation_z(0.7);
+        let m = mat4_from_trs([1_f32, 0_f32, -1_f32], rotation, [2_f32, -3_f32, 4_f32]);
+        let transform = Transform::from_matrix(&m);
+
+        // The mirror moves to the X axis, but the matrix comes back the same.
+        assert!(transform.scale[0] < 0_f32);
+        assert_close(&transform.scale.iter().map(|x| x.abs()).collect::<Vec<f32>>(), &[2_f32, 3_f32, 4_f32]);
+        let round_trip = transform.to_matrix();
+        for column in 0..4 {
+            assert_close(&round_trip[column], &m[column]);
+        }
+    }
+
+    #[test]
+    fn test_skeleton_orders_parents_first() {
+        let joints = vec![joint("hand", Some(2)), joint("root", None), joint("arm", Some(1))];
+        let skeleton = Skeleton::new(joints).unwrap();
+
+        assert_eq!(skeleton.order, vec![1, 2, 0]);
+        assert_eq!(skeleton.joint("arm"), Some(2));
+    }
+
+    #[test]
+    fn test_skeleton_rejects_cyclic_parents() {
+        let cycle = vec![joint("a", Some(1)), joint("b", Some(0))];
+        assert_eq!(Skeleton::new(cycle), Err(SkeletonError::InvalidParent(0)));
+
+        let own_parent = vec![joint("root", None), joint("a", Some(1))];
+        assert_eq!(Skeleton::new(own_parent), Err(SkeletonError::InvalidParent(1)));
+
+        let cycle_below_root = vec![joint("root", None), joint("a", Some(2)), joint("b", Some(1)), joint("c", Some(0))];
+        assert_eq!(Skeleton::new(cycle_below_root), Err(SkeletonError::InvalidParent(1)));
+
+        let missing = vec![joint("root", None), joint("a", Some(7))];
+        assert_eq!(Skeleton::new(missing), Err(SkeletonError::InvalidParent(1)));
+    }
+
+    #[test]
+    fn test_skeleton_rejects_too_many_joints() {
+        let joints: Vec<Joint> = (0..MAX_JOINTS + 1).map(|i| joint(&format!("joint{}", i), None)).collect();
+        assert_eq!(Skeleton::new(joints), Err(SkeletonError::TooManyJoints(MAX_JOINTS + 1)));
+    }
+}
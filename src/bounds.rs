@@ -0,0 +1,357 @@
+/*
+ *  GB-Prototype is a renderer prototype demo.
+ *  Copyright (C) 2018,2019,2020  Christopher Blanchard
+ *
+ *  This program is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  This program is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+#![allow(dead_code)]
+//! Bounding volumes and view frustums for culling.
+use cgmath::{Array, Matrix4};
+
+use std::slice;
+
+
+/// Read the elements of a matrix in column major order, the order GL takes them in.
+#[inline]
+pub fn matrix_elements(m: &Matrix4) -> [f32; 16] {
+    let mut elements = [0_f32; 16];
+    elements.copy_from_slice(unsafe { slice::from_raw_parts(m.as_ptr(), 16) });
+
+    elements
+}
+
+/// Transform a point by a column major matrix with an affine last row.
+#[inline]
+fn transform_point(m: &[f32; 16], p: [f32; 3]) -> [f32; 3] {
+    [
+        m[0] * p[0] + m[4] * p[1] + m[8] * p[2] + m[12],
+        m[1] * p[0] + m[5] * p[1] + m[9] * p[2] + m[13],
+        m[2] * p[0] + m[6] * p[1] + m[10] * p[2] + m[14],
+    ]
+}
+
+/// An axis aligned bounding box.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+impl Aabb {
+    /// The smallest box containing the points. The box of no points is the point
+    /// at the origin.
+    pub fn from_points(points: &[[f32; 3]]) -> Aabb {
+        if points.is_empty() {
+            return Aabb { min: [0_f32; 3], max: [0_f32; 3] };
+        }
+
+        let mut min = [f32::INFINITY; 3];
+        let mut max = [f32::NEG_INFINITY; 3];
+        for point in points.iter() {
+            for i in 0..3 {
+                min[i] = f32::min(min[i], point[i]);
+                max[i] = f32::max(max[i], point[i]);
+            }
+        }
+
+        Aabb { min: min, max: max }
+    }
+
+    #[inline]
+    pub fn center(&self) -> [f32; 3] {
+        [
+            (self.min[0] + self.max[0]) / 2_f32,
+            (self.min[1] + self.max[1]) / 2_f32,
+            (self.min[2] + self.max[2]) / 2_f32,
+        ]
+    }
+
+    /// Half the size of the box along each axis.
+    #[inline]
+    pub fn extents(&self) -> [f32; 3] {
+        [
+            (self.max[0] - self.min[0]) / 2_f32,
+            (self.max[1] - self.min[1]) / 2_f32,
+            (self.max[2] - self.min[2]) / 2_f32,
+        ]
+    }
+
+    /// The axis aligned box containing this box after a transform. The result
+    /// is larger than the box for rotations.
+    pub fn transform(&self, m: &Matrix4) -> Aabb {
+        let m = matrix_elements(m);
+        let mut min = [m[12], m[13], m[14]];
+        let mut max = min;
+        for row in 0..3 {
+            for column in 0..3 {
+                let e = m[4 * column + row];
+                let a = e * self.min[column];
+                let b = e * self.max[column];
+                min[row] += f32::min(a, b);
+                max[row] += f32::max(a, b);
+            }
+        }
+
+        Aabb { min: min, max: max }
+    }
+}
+
+/// A bounding sphere.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BoundingSphere {
+    pub center: [f32; 3],
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    /// A sphere around the points, centered on their bounding box.
+    pub fn from_points(points: &[[f32; 3]]) -> BoundingSphere {
+        let center = Aabb::from_points(points).center();
+        let radius_squared = points.iter().map(|p| {
+            let d = [p[0] - center[0], p[1] - center[1], p[2] - center[2]];
+            d[0] * d[0] + d[1] * d[1] + d[2] * d[2]
+        }).fold(0_f32, f32::max);
+
+        BoundingSphere { center: center, radius: f32::sqrt(radius_squared) }
+    }
+
+    /// The sphere containing this sphere after a transform. A non-uniform scale
+    /// grows the radius by the largest scale factor.
+    pub fn transform(&self, m: &Matrix4) -> BoundingSphere {
+        let m = matrix_elements(m);
+        let scale_squared = (0..3).map(|column| {
+            let c = &m[4 * column..4 * column + 3];
+            c[0] * c[0] + c[1] * c[1] + c[2] * c[2]
+        }).fold(0_f32, f32::max);
+
+        BoundingSphere {
+            center: transform_point(&m, self.center),
+            radius: self.radius * f32::sqrt(scale_squared),
+        }
+    }
+}
+
+/// The bounding volumes of a mesh. The sphere is the cheaper test, and the box
+/// is the tighter one.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Bounds {
+    pub aabb: Aabb,
+    pub sphere: BoundingSphere,
+}
+
+impl Bounds {
+    pub fn from_points(points: &[[f32; 3]]) -> Bounds {
+        Bounds {
+            aabb: Aabb::from_points(points),
+            sphere: BoundingSphere::from_points(points),
+        }
+    }
+
+    /// Move the bounds from model space into the space of a transform, e.g. world
+    /// space with a model matrix.
+    pub fn transform(&self, m: &Matrix4) -> Bounds {
+        Bounds {
+            aabb: self.aabb.transform(m),
+            sphere: self.sphere.transform(m),
+        }
+    }
+}
+
+/// A view frustum as six planes facing inwards. Each plane is `[a, b, c, d]`
+/// with a unit normal, so a point `p` is in front of it when `a*x + b*y + c*z + d >= 0`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Frustum {
+    pub planes: [[f32; 4]; 6],
+}
+
+impl Frustum {
+    /// Extract the frustum from a view projection matrix. Its planes are the
+    /// sides of the GL clip volume, `-w <= x, y, z <= w`, in world space.
+    pub fn from_view_projection(view_projection: &Matrix4) -> Frustum {
+        let m = matrix_elements(view_projection);
+        let row = |r: usize| [m[r], m[4 + r], m[8 + r], m[12 + r]];
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+        let plane = |sign: f32, r: [f32; 4]| {
+            let p = [w[0] + sign * r[0], w[1] + sign * r[1], w[2] + sign * r[2], w[3] + sign * r[3]];
+            let length = f32::sqrt(p[0] * p[0] + p[1] * p[1] + p[2] * p[2]);
+            if length > 0_f32 {
+                [p[0] / length, p[1] / length, p[2] / length, p[3] / length]
+            } else {
+                p
+            }
+        };
+
+        Frustum {
+            planes: [
+                plane(1_f32, x), plane(-1_f32, x),
+                plane(1_f32, y), plane(-1_f32, y),
+                plane(1_f32, z), plane(-1_f32, z),
+            ],
+        }
+    }
+
+    #[inline]
+    fn distance(plane: &[f32; 4], p: [f32; 3]) -> f32 {
+        plane[0] * p[0] + plane[1] * p[1] + plane[2] * p[2] + plane[3]
+    }
+
+    /// Determine whether a sphere is at least partly inside the frustum.
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        self.planes.iter().all(|plane| Frustum::distance(plane, sphere.center) >= -sphere.radius)
+    }
+
+    /// Determine whether a box is at least partly inside the frustum. Boxes near a
+    /// corner of the frustum can pass without being inside.
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            // The corner of the box furthest along the normal of the plane.
+            let corner = [
+                if plane[0] >= 0_f32 { aabb.max[0] } else { aabb.min[0] },
+                if plane[1] >= 0_f32 { aabb.max[1] } else { aabb.min[1] },
+                if plane[2] >= 0_f32 { aabb.max[2] } else { aabb.min[2] },
+            ];
+            Frustum::distance(plane, corner) >= 0_f32
+        })
+    }
+
+    /// Determine whether bounds are at least partly inside the frustum, testing the
+    /// sphere first.
+    #[inline]
+    pub fn intersects(&self, bounds: &Bounds) -> bool {
+        self.intersects_sphere(&bounds.sphere) && self.intersects_aabb(&bounds.aabb)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    /// Build a matrix from its elements in column major order.
+    fn matrix(e: [f32; 16]) -> Matrix4 {
+        Matrix4::new(
+            e[0], e[1], e[2], e[3], e[4], e[5], e[6], e[7],
+            e[8], e[9], e[10], e[11], e[12], e[13], e[14], e[15]
+        )
+    }
+
+    /// A GL perspective projection with a 90 degree field of view, a square aspect
+    /// ratio, and the near and far planes at 1 and 100, looking down -Z.
+    fn perspective() -> Matrix4 {
+        let (near, far) = (1_f32, 100_f32);
+        matrix([
+            1_f32, 0_f32, 0_f32, 0_f32,
+            0_f32, 1_f32, 0_f32, 0_f32,
+            0_f32, 0_f32, (far + near) / (near - far), -1_f32,
+            0_f32, 0_f32, 2_f32 * far * near / (near - far), 0_f32,
+        ])
+    }
+
+    /// A rotation about the Z axis followed by a translation.
+    fn rotate_z_translate(angle: f32, translation: [f32; 3]) -> Matrix4 {
+        let (sin, cos) = f32::sin_cos(angle);
+        matrix([
+            cos, sin, 0_f32, 0_f32,
+            -sin, cos, 0_f32, 0_f32,
+            0_f32, 0_f32, 1_f32, 0_f32,
+            translation[0], translation[1], translation[2], 1_f32,
+        ])
+    }
+
+    fn point(p: [f32; 3]) -> BoundingSphere {
+        BoundingSphere { center: p, radius: 0_f32 }
+    }
+
+    fn assert_close(a: [f32; 3], b: [f32; 3]) {
+        for i in 0..3 {
+            assert!((a[i] - b[i]).abs() < 1e-5, "{:?} != {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_frustum_planes_are_normalized() {
+        let frustum = Frustum::from_view_projection(&perspective());
+        for plane in frustum.planes.iter() {
+            let length = f32::sqrt(plane[0] * plane[0] + plane[1] * plane[1] + plane[2] * plane[2]);
+            assert!((length - 1_f32).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_point_in_front_of_and_behind_the_camera() {
+        let frustum = Frustum::from_view_projection(&perspective());
+
+        assert!(frustum.intersects_sphere(&point([0_f32, 0_f32, -10_f32])));
+        assert!(frustum.intersects_sphere(&point([9_f32, -9_f32, -10_f32])));
+        assert!(!frustum.intersects_sphere(&point([0_f32, 0_f32, 10_f32])));
+        assert!(!frustum.intersects_sphere(&point([0_f32, 0_f32, -0.5])));
+        assert!(!frustum.intersects_sphere(&point([0_f32, 0_f32, -101_f32])));
+        assert!(!frustum.intersects_sphere(&point([11_f32, 0_f32, -10_f32])));
+    }
+
+    #[test]
+    fn test_sphere_reaching_into_the_frustum() {
+        let frustum = Frustum::from_view_projection(&perspective());
+        let behind = BoundingSphere { center: [0_f32, 0_f32, 10_f32], radius: 1_f32 };
+        let large = BoundingSphere { center: [0_f32, 0_f32, 10_f32], radius: 20_f32 };
+
+        assert!(!frustum.intersects_sphere(&behind));
+        assert!(frustum.intersects_sphere(&large));
+    }
+
+    #[test]
+    fn test_box_straddling_a_plane() {
+        let frustum = Frustum::from_view_projection(&perspective());
+        // The right plane passes through x = 10 at z = -10.
+        let straddling = Aabb { min: [9_f32, -1_f32, -11_f32], max: [11_f32, 1_f32, -9_f32] };
+        let outside = Aabb { min: [11.5, -1_f32, -10.5], max: [13_f32, 1_f32, -9.5] };
+        // The near plane is at z = -1.
+        let across_near = Aabb { min: [-1_f32, -1_f32, -1.5], max: [1_f32, 1_f32, -0.5] };
+
+        assert!(frustum.intersects_aabb(&straddling));
+        assert!(!frustum.intersects_aabb(&outside));
+        assert!(frustum.intersects_aabb(&across_near));
+        assert!(frustum.intersects(&Bounds::from_points(&[straddling.min, straddling.max])));
+        assert!(!frustum.intersects(&Bounds::from_points(&[outside.min, outside.max])));
+    }
+
+    #[test]
+    fn test_rotated_aabb() {
+        let aabb = Aabb { min: [-1_f32; 3], max: [1_f32; 3] };
+        let m = rotate_z_translate(::std::f32::consts::FRAC_PI_4, [5_f32, 0_f32, -2_f32]);
+        let rotated = aabb.transform(&m);
+        let half_diagonal = f32::sqrt(2_f32);
+
+        assert_close(rotated.min, [5_f32 - half_diagonal, -half_diagonal, -3_f32]);
+        assert_close(rotated.max, [5_f32 + half_diagonal, half_diagonal, -1_f32]);
+        // Every rotated corner lies inside the new box.
+        for &x in [-1_f32, 1_f32].iter() {
+            for &y in [-1_f32, 1_f32].iter() {
+                let corner = transform_point(&matrix_elements(&m), [x, y, 1_f32]);
+                for (i, &c) in corner.iter().enumerate() {
+                    assert!(c >= rotated.min[i] - 1e-5 && c <= rotated.max[i] + 1e-5);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_sphere_transform() {
+        let sphere = BoundingSphere { center: [1_f32, 0_f32, 0_f32], radius: 2_f32 };
+        let moved = sphere.transform(&rotate_z_translate(::std::f32::consts::FRAC_PI_2, [0_f32, 0_f32, 3_f32]));
+
+        assert_close(moved.center, [0_f32, 1_f32, 3_f32]);
+        assert!((moved.radius - 2_f32).abs() < 1e-5);
+    }
+}
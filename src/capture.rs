@@ -0,0 +1,342 @@
+/*
+ *  GB-Prototype is a renderer prototype demo.
+ *  Copyright (C) 2018,2019,2020  Christopher Blanchard
+ *
+ *  This program is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  This program is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+#![allow(dead_code)]
+use crate::gl;
+use crate::gl::types::{GLenum, GLint, GLuint, GLvoid};
+use crate::gl_backend::GLState;
+
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use log::{info, error};
+
+
+#[derive(Clone, Debug)]
+pub enum CaptureError {
+    CouldNotCreateFile(String),
+    CouldNotWriteImage(String),
+    CouldNotReadImage(String),
+    UnsupportedImageFormat(String),
+}
+
+impl fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &CaptureError::CouldNotCreateFile(ref file_name) => {
+                write!(f, "Could not create the image file for writing: {}", file_name)
+            }
+            &CaptureError::CouldNotWriteImage(ref file_name) => {
+                write!(f, "The image file was created, but there was an error in writing it: {}", file_name)
+            }
+            &CaptureError::CouldNotReadImage(ref file_name) => {
+                write!(f, "Could not read the image file: {}", file_name)
+            }
+            &CaptureError::UnsupportedImageFormat(ref file_name) => {
+                write!(f, "The image file is not an 8-bit RGBA PNG: {}", file_name)
+            }
+        }
+    }
+}
+
+/// The framebuffer to read a captured frame from.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CaptureSource {
+    /// The framebuffer the context renders into: the back buffer of a window,
+    /// or the offscreen framebuffer of a headless context.
+    RenderTarget,
+    /// The first color attachment of an arbitrary framebuffer object.
+    Framebuffer { fbo: GLuint, width: u32, height: u32 },
+}
+
+/// An 8-bit RGBA image read back from the GPU. The rows are stored top to bottom.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrameCapture {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl FrameCapture {
+    /// Write the captured frame to an 8-bit RGBA PNG file.
+    pub fn write_png<P: AsRef<Path>>(&self, path: P) -> Result<(), CaptureError> {
+        let disp = path.as_ref().display().to_string();
+        let file = match File::create(&path) {
+            Ok(val) => val,
+            Err(_) => return Err(CaptureError::CouldNotCreateFile(disp)),
+        };
+        let mut encoder = png::Encoder::new(BufWriter::new(file), self.width, self.height);
+        encoder.set_color(png::ColorType::RGBA);
+        encoder.set_depth(png::BitDepth::Eight);
+        let result = encoder.write_header().and_then(|mut writer| {
+            writer.write_image_data(&self.pixels)
+        });
+        if result.is_err() {
+            return Err(CaptureError::CouldNotWriteImage(disp));
+        }
+        info!("Wrote {} x {} frame capture to {}", self.width, self.height, disp);
+
+        Ok(())
+    }
+
+    /// Read an 8-bit RGBA PNG file, such as one written by `write_png`.
+    pub fn read_png<P: AsRef<Path>>(path: P) -> Result<FrameCapture, CaptureError> {
+        let disp = path.as_ref().display().to_string();
+        let file = match File::open(&path) {
+            Ok(val) => val,
+            Err(_) => return Err(CaptureError::CouldNotReadImage(disp)),
+        };
+        let (info, mut reader) = match png::Decoder::new(file).read_info() {
+            Ok(val) => val,
+            Err(_) => return Err(CaptureError::CouldNotReadImage(disp)),
+        };
+        if info.color_type != png::ColorType::RGBA || info.bit_depth != png::BitDepth::Eight {
+            return Err(CaptureError::UnsupportedImageFormat(disp));
+        }
+        let mut pixels = vec![0; info.buffer_size()];
+        if reader.next_frame(&mut pixels).is_err() {
+            return Err(CaptureError::CouldNotReadImage(disp));
+        }
+
+        Ok(FrameCapture {
+            width: info.width,
+            height: info.height,
+            pixels: pixels,
+        })
+    }
+}
+
+/// A 32-bit floating point RGBA image read back from the GPU. The rows are
+/// stored top to bottom.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FloatFrameCapture {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<f32>,
+}
+
+impl FloatFrameCapture {
+    /// Write the captured frame as raw little endian `f32` RGBA values with no header.
+    pub fn write_raw<P: AsRef<Path>>(&self, path: P) -> Result<(), CaptureError> {
+        let disp = path.as_ref().display().to_string();
+        let file = match File::create(&path) {
+            Ok(val) => val,
+            Err(_) => return Err(CaptureError::CouldNotCreateFile(disp)),
+        };
+        let mut writer = BufWriter::new(file);
+        for value in self.pixels.iter() {
+            if writer.write_all(&value.to_bits().to_le_bytes()).is_err() {
+                return Err(CaptureError::CouldNotWriteImage(disp));
+            }
+        }
+        if writer.flush().is_err() {
+            return Err(CaptureError::CouldNotWriteImage(disp));
+        }
+        info!("Wrote {} x {} raw float frame capture to {}", self.width, self.height, disp);
+
+        Ok(())
+    }
+
+    /// Write the captured frame to a Radiance RGBE (`.hdr`) file. The alpha channel
+    /// is discarded.
+    pub fn write_hdr<P: AsRef<Path>>(&self, path: P) -> Result<(), CaptureError> {
+        let disp = path.as_ref().display().to_string();
+        let file = match File::create(&path) {
+            Ok(val) => val,
+            Err(_) => return Err(CaptureError::CouldNotCreateFile(disp)),
+        };
+        let mut writer = BufWriter::new(file);
+        let header = format!(
+            "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", self.height, self.width
+        );
+        if writer.write_all(header.as_bytes()).is_err() {
+            return Err(CaptureError::CouldNotWriteImage(disp));
+        }
+        for pixel in self.pixels.chunks(4) {
+            let rgbe = float_to_rgbe(pixel[0], pixel[1], pixel[2]);
+            if writer.write_all(&rgbe).is_err() {
+                return Err(CaptureError::CouldNotWriteImage(disp));
+            }
+        }
+        if writer.flush().is_err() {
+            return Err(CaptureError::CouldNotWriteImage(disp));
+        }
+        info!("Wrote {} x {} HDR frame capture to {}", self.width, self.height, disp);
+
+        Ok(())
+    }
+}
+
+/// Encode a linear color in the shared exponent format used by Radiance files.
+fn float_to_rgbe(r: f32, g: f32, b: f32) -> [u8; 4] {
+    let v = f32::max(r, f32::max(g, b));
+    if !v.is_finite() || v <= 1e-32 {
+        return [0, 0, 0, 0];
+    }
+    // Split v into a mantissa in [0.5, 1) and a power of two exponent.
+    let exponent = v.log2().floor() as i32 + 1;
+    let scale = 256_f32 / f32::powi(2_f32, exponent);
+
+    [
+        f32::max(r * scale, 0_f32) as u8,
+        f32::max(g * scale, 0_f32) as u8,
+        f32::max(b * scale, 0_f32) as u8,
+        (exponent + 128) as u8,
+    ]
+}
+
+/// Reverse the order of the rows of an image. OpenGL returns pixels starting from
+/// the bottom row, while image files start from the top row.
+fn flip_rows<T: Copy>(pixels: &mut [T], row_length: usize) {
+    let rows = pixels.len() / row_length;
+    for row in 0..(rows / 2) {
+        let opposite = rows - 1 - row;
+        let (top, bottom) = pixels.split_at_mut(opposite * row_length);
+        top[row * row_length..(row + 1) * row_length].swap_with_slice(&mut bottom[0..row_length]);
+    }
+}
+
+/// Bind the framebuffer for a capture source for reading, and return its dimensions.
+fn bind_capture_source(context: &GLState, source: CaptureSource) -> (u32, u32) {
+    match source {
+        CaptureSource::RenderTarget => match context.offscreen {
+            Some(ref framebuffer) => {
+                unsafe {
+                    gl::BindFramebuffer(gl::READ_FRAMEBUFFER, framebuffer.fbo.name());
+                    gl::ReadBuffer(gl::COLOR_ATTACHMENT0);
+                }
+                (framebuffer.width, framebuffer.height)
+            }
+            None => {
+                unsafe {
+                    gl::BindFramebuffer(gl::READ_FRAMEBUFFER, 0);
+                    gl::ReadBuffer(gl::BACK);
+                }
+                let (width, height) = context.window.get_framebuffer_size();
+                (width as u32, height as u32)
+            }
+        },
+        CaptureSource::Framebuffer { fbo, width, height } => {
+            unsafe {
+                gl::BindFramebuffer(gl::READ_FRAMEBUFFER, fbo);
+                gl::ReadBuffer(if fbo == 0 { gl::BACK } else { gl::COLOR_ATTACHMENT0 });
+            }
+            (width, height)
+        }
+    }
+}
+
+/// Read the pixels of a capture source into `pixels` with the given component type.
+fn read_pixels<T>(context: &GLState, width: u32, height: u32, ty: GLenum, pixels: &mut [T]) {
+    unsafe {
+        gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+        gl::ReadPixels(
+            0, 0, width as GLint, height as GLint, gl::RGBA, ty,
+            pixels.as_mut_ptr() as *mut GLvoid
+        );
+        let fbo = match context.offscreen {
+            Some(ref framebuffer) => framebuffer.fbo.name(),
+            None => 0,
+        };
+        gl::BindFramebuffer(gl::READ_FRAMEBUFFER, fbo);
+    }
+}
+
+/// Read back a frame from the GPU as an 8-bit RGBA image. Capture the default
+/// framebuffer before swapping buffers, since the back buffer is undefined after a swap.
+pub fn capture_frame(context: &GLState, source: CaptureSource) -> FrameCapture {
+    let (width, height) = bind_capture_source(context, source);
+    let mut pixels = vec![0_u8; 4 * width as usize * height as usize];
+    read_pixels(context, width, height, gl::UNSIGNED_BYTE, &mut pixels);
+    flip_rows(&mut pixels, 4 * width as usize);
+
+    FrameCapture {
+        width: width,
+        height: height,
+        pixels: pixels,
+    }
+}
+
+/// Read back a frame from the GPU as a floating point RGBA image. This preserves
+/// the full range of floating point render targets.
+pub fn capture_frame_float(context: &GLState, source: CaptureSource) -> FloatFrameCapture {
+    let (width, height) = bind_capture_source(context, source);
+    let mut pixels = vec![0_f32; 4 * width as usize * height as usize];
+    read_pixels(context, width, height, gl::FLOAT, &mut pixels);
+    flip_rows(&mut pixels, 4 * width as usize);
+
+    FloatFrameCapture {
+        width: width,
+        height: height,
+        pixels: pixels,
+    }
+}
+
+/// Capture the current render target of a context and write it to a PNG file.
+pub fn capture_frame_to_file<P: AsRef<Path>>(context: &GLState, path: P) -> Result<(), CaptureError> {
+    let capture = capture_frame(context, CaptureSource::RenderTarget);
+    capture.write_png(&path).map_err(|e| {
+        error!("{}", e);
+        e
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn test_flip_rows_two_by_three() {
+        let mut pixels = [1, 2, 3, 4, 5, 6];
+        flip_rows(&mut pixels, 2);
+
+        assert_eq!(pixels, [5, 6, 3, 4, 1, 2]);
+    }
+
+    #[test]
+    fn test_flip_rows_keeps_middle_row() {
+        let mut pixels = [[1_u8; 4], [2; 4], [3; 4]];
+        flip_rows(&mut pixels, 1);
+
+        assert_eq!(pixels, [[3; 4], [2; 4], [1; 4]]);
+    }
+
+    #[test]
+    fn test_float_to_rgbe_zero() {
+        assert_eq!(float_to_rgbe(0_f32, 0_f32, 0_f32), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_float_to_rgbe_one() {
+        // 1 = 0.5 * 2^1, so the mantissa is 128 and the biased exponent 129.
+        assert_eq!(float_to_rgbe(1_f32, 1_f32, 1_f32), [128, 128, 128, 129]);
+    }
+
+    #[test]
+    fn test_float_to_rgbe_larger_than_one() {
+        // 4 = 0.5 * 2^3, so every component is scaled by 2^8 / 2^3 = 32.
+        let rgbe = float_to_rgbe(4_f32, 2_f32, 1_f32);
+        assert_eq!(rgbe, [128, 64, 32, 131]);
+
+        let exponent = f32::powi(2_f32, rgbe[3] as i32 - 136);
+        assert_eq!(rgbe[0] as f32 * exponent, 4_f32);
+        assert_eq!(rgbe[2] as f32 * exponent, 1_f32);
+    }
+}
@@ -0,0 +1,321 @@
+/*
+ *  GB-Prototype is a renderer prototype demo.
+ *  Copyright (C) 2018,2019,2020  Christopher Blanchard
+ *
+ *  This program is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  This program is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+#![allow(dead_code)]
+//! Buffers for vertex data that changes after upload. A `DynamicBuffer` takes
+//! partial updates and can be orphaned. A `StreamBuffer` takes fresh data every
+//! frame, e.g. UI or particle geometry, and writes it into a persistently mapped
+//! ring where the context has buffer storage (GL 4.4).
+use crate::error::Error;
+use crate::gl;
+use crate::gl::types::{GLenum, GLintptr, GLsizeiptr, GLsync, GLvoid};
+use crate::gl_backend as glh;
+use crate::gl_backend::{ContextHandle, GLState};
+use crate::gl_objects::BufferObject;
+
+use std::ptr;
+
+
+/// The number of frames a stream buffer ring holds. The CPU writes one frame while
+/// the GPU may still read the other two.
+pub const RING_FRAMES: usize = 3;
+/// How long to wait on a fence in one call, in nanoseconds.
+const FENCE_TIMEOUT_NANOSECONDS: u64 = 1_000_000;
+
+/// Uploads go through the copy write target. Binding a buffer to the element array
+/// target would change the currently bound vertex array.
+const UPLOAD_TARGET: GLenum = gl::COPY_WRITE_BUFFER;
+
+/// Check the GL error queue after allocating storage for a buffer.
+fn check_allocation(resource: &str) -> Result<(), Error> {
+    if glh::take_gl_error() == Some(gl::OUT_OF_MEMORY) {
+        return Err(Error::OutOfMemory(String::from(resource)));
+    }
+
+    Ok(())
+}
+
+/// Make sure that a write of `len` bytes at `offset` fits in `capacity` bytes.
+#[inline]
+fn check_range(offset: usize, len: usize, capacity: usize) -> Result<(), Error> {
+    match offset.checked_add(len) {
+        Some(end) if end <= capacity => Ok(()),
+        _ => Err(Error::BufferOverflow(offset, len, capacity)),
+    }
+}
+
+/// A buffer whose contents are replaced or patched after upload.
+#[derive(Debug)]
+pub struct DynamicBuffer {
+    object: BufferObject,
+    capacity: usize,
+    usage: GLenum,
+}
+
+impl DynamicBuffer {
+    /// Allocate a buffer of `capacity` bytes with a usage hint such as
+    /// `gl::DYNAMIC_DRAW` or `gl::STREAM_DRAW`.
+    pub fn new(context: &GLState, capacity: usize, usage: GLenum) -> Result<DynamicBuffer, Error> {
+        let buffer = DynamicBuffer {
+            object: BufferObject::new(context),
+            capacity: capacity,
+            usage: usage,
+        };
+        buffer.orphan();
+        check_allocation("a dynamic buffer")?;
+
+        Ok(buffer)
+    }
+
+    /// The buffer object, e.g. for binding it to a vertex array.
+    #[inline]
+    pub fn object(&self) -> &BufferObject {
+        &self.object
+    }
+
+    /// The size of the buffer in bytes.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Give the buffer fresh storage of the same size. The driver keeps the old
+    /// storage alive until the draws that read it finish, so the next update does
+    /// not wait on them. The contents of the new storage are undefined.
+    pub fn orphan(&self) {
+        self.object.bind(UPLOAD_TARGET);
+        unsafe {
+            gl::BufferData(UPLOAD_TARGET, self.capacity as GLsizeiptr, ptr::null(), self.usage);
+        }
+    }
+
+    /// Overwrite the bytes of the buffer starting at `offset`.
+    pub fn update(&self, offset: usize, data: &[u8]) -> Result<(), Error> {
+        check_range(offset, data.len(), self.capacity)?;
+        self.object.bind(UPLOAD_TARGET);
+        unsafe {
+            gl::BufferSubData(
+                UPLOAD_TARGET, offset as GLintptr, data.len() as GLsizeiptr, data.as_ptr() as *const GLvoid
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Replace the whole contents of the buffer, orphaning the old storage. The
+    /// buffer grows if the data does not fit.
+    pub fn replace(&mut self, data: &[u8]) -> Result<(), Error> {
+        if data.len() > self.capacity {
+            self.capacity = data.len();
+        }
+        self.orphan();
+        check_allocation("a dynamic buffer")?;
+
+        self.update(0, data)
+    }
+}
+
+/// How a stream buffer avoids waiting on the GPU.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StreamStrategy {
+    /// Orphan the buffer at the start of every frame.
+    Orphan,
+    /// Write into a persistently mapped ring of `RING_FRAMES` regions, and fence
+    /// each region after the frame that used it.
+    PersistentRing,
+}
+
+/// Map immutable storage for a buffer for writing for as long as it exists.
+#[cfg(not(feature = "gles"))]
+unsafe fn map_persistent(buffer: &BufferObject, size: usize) -> *mut u8 {
+    let flags = gl::MAP_WRITE_BIT | gl::MAP_PERSISTENT_BIT | gl::MAP_COHERENT_BIT;
+    buffer.bind(UPLOAD_TARGET);
+    gl::BufferStorage(UPLOAD_TARGET, size as GLsizeiptr, ptr::null(), flags);
+
+    gl::MapBufferRange(UPLOAD_TARGET, 0, size as GLsizeiptr, flags) as *mut u8
+}
+
+/// The OpenGL ES bindings have no buffer storage, so streams there always orphan.
+#[cfg(feature = "gles")]
+unsafe fn map_persistent(_buffer: &BufferObject, _size: usize) -> *mut u8 {
+    unreachable!("OpenGL ES has no buffer storage");
+}
+
+/// A buffer that receives new data every frame. Write the frame's data with
+/// `write`, draw from the offsets it returns, then call `finish_frame` once the
+/// draws are submitted.
+#[derive(Debug)]
+pub struct StreamBuffer {
+    object: BufferObject,
+    strategy: StreamStrategy,
+    /// The size of the space for one frame, a multiple of the alignment.
+    region_size: usize,
+    alignment: usize,
+    region: usize,
+    write_offset: usize,
+    mapping: *mut u8,
+    fences: [GLsync; RING_FRAMES],
+    context: ContextHandle,
+}
+
+impl StreamBuffer {
+    /// Create a stream buffer with room for `frame_size` bytes per frame. Every
+    /// write starts at a multiple of `alignment` bytes from the start of the buffer,
+    /// so with the vertex stride as the alignment an offset divided by the stride
+    /// is the first vertex to draw.
+    pub fn new(context: &GLState, frame_size: usize, alignment: usize) -> Result<StreamBuffer, Error> {
+        let alignment = usize::max(alignment, 1);
+        let region_size = (frame_size + alignment - 1) / alignment * alignment;
+        let strategy = if context.features.buffer_storage && cfg!(not(feature = "gles")) {
+            StreamStrategy::PersistentRing
+        } else {
+            StreamStrategy::Orphan
+        };
+        let object = BufferObject::new(context);
+        let mapping = match strategy {
+            StreamStrategy::PersistentRing => {
+                let mapping = unsafe { map_persistent(&object, region_size * RING_FRAMES) };
+                check_allocation("a stream buffer")?;
+                if mapping.is_null() {
+                    return Err(Error::OutOfMemory(String::from("a mapping of a stream buffer")));
+                }
+
+                mapping
+            }
+            StreamStrategy::Orphan => ptr::null_mut(),
+        };
+        let buffer = StreamBuffer {
+            object: object,
+            strategy: strategy,
+            region_size: region_size,
+            alignment: alignment,
+            region: 0,
+            write_offset: 0,
+            mapping: mapping,
+            fences: [ptr::null(); RING_FRAMES],
+            context: context.handle(),
+        };
+        if strategy == StreamStrategy::Orphan {
+            buffer.orphan();
+            check_allocation("a stream buffer")?;
+        }
+
+        Ok(buffer)
+    }
+
+    /// The buffer object, e.g. for binding it to a vertex array.
+    #[inline]
+    pub fn object(&self) -> &BufferObject {
+        &self.object
+    }
+
+    /// The way the buffer streams data on this context.
+    #[inline]
+    pub fn strategy(&self) -> StreamStrategy {
+        self.strategy
+    }
+
+    /// The number of bytes one frame can write.
+    #[inline]
+    pub fn frame_size(&self) -> usize {
+        self.region_size
+    }
+
+    fn orphan(&self) {
+        self.object.bind(UPLOAD_TARGET);
+        unsafe {
+            gl::BufferData(UPLOAD_TARGET, self.region_size as GLsizeiptr, ptr::null(), gl::STREAM_DRAW);
+        }
+    }
+
+    /// Block until the GPU has finished reading the current region.
+    fn wait_for_region(&mut self) {
+        let fence = self.fences[self.region];
+        if fence.is_null() {
+            return;
+        }
+        unsafe {
+            loop {
+                let status = gl::ClientWaitSync(fence, gl::SYNC_FLUSH_COMMANDS_BIT, FENCE_TIMEOUT_NANOSECONDS);
+                if status != gl::TIMEOUT_EXPIRED {
+                    break;
+                }
+            }
+            gl::DeleteSync(fence);
+        }
+        self.fences[self.region] = ptr::null();
+    }
+
+    /// Append data to this frame's region. Returns the byte offset of the data from
+    /// the start of the buffer.
+    pub fn write(&mut self, data: &[u8]) -> Result<usize, Error> {
+        let offset = (self.write_offset + self.alignment - 1) / self.alignment * self.alignment;
+        check_range(offset, data.len(), self.region_size)?;
+        let buffer_offset = match self.strategy {
+            StreamStrategy::PersistentRing => {
+                self.wait_for_region();
+                let buffer_offset = self.region * self.region_size + offset;
+                unsafe {
+                    ptr::copy_nonoverlapping(data.as_ptr(), self.mapping.add(buffer_offset), data.len());
+                }
+
+                buffer_offset
+            }
+            StreamStrategy::Orphan => {
+                self.object.bind(UPLOAD_TARGET);
+                unsafe {
+                    gl::BufferSubData(
+                        UPLOAD_TARGET, offset as GLintptr, data.len() as GLsizeiptr, data.as_ptr() as *const GLvoid
+                    );
+                }
+
+                offset
+            }
+        };
+        self.write_offset = offset + data.len();
+
+        Ok(buffer_offset)
+    }
+
+    /// End the frame. Call this after submitting the draws that read this frame's
+    /// data. The next frame writes to the next region of the ring, or to fresh
+    /// storage when orphaning.
+    pub fn finish_frame(&mut self) {
+        match self.strategy {
+            StreamStrategy::PersistentRing => {
+                self.fences[self.region] = unsafe { gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0) };
+                self.region = (self.region + 1) % RING_FRAMES;
+            }
+            StreamStrategy::Orphan => self.orphan(),
+        }
+        self.write_offset = 0;
+    }
+}
+
+impl Drop for StreamBuffer {
+    fn drop(&mut self) {
+        // Deleting the buffer object unmaps it.
+        let fences = &self.fences;
+        self.context.with_current(|| {
+            for fence in fences.iter().filter(|fence| !fence.is_null()) {
+                unsafe {
+                    gl::DeleteSync(*fence);
+                }
+            }
+        });
+    }
+}
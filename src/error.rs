@@ -0,0 +1,150 @@
+/*
+ *  GB-Prototype is a renderer prototype demo.
+ *  Copyright (C) 2018,2019,2020  Christopher Blanchard
+ *
+ *  This program is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  This program is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use crate::gl::types::GLenum;
+use crate::gl_backend::{GL_API_NAME, GLVersion, ShaderCompilationError};
+use crate::gltf::GltfError;
+use crate::mesh::MeshError;
+use crate::mesh_file::MeshFileError;
+use crate::obj::ObjError;
+
+use std::error;
+use std::fmt;
+
+
+/// The errors that can occur while starting a GL context or creating GPU resources.
+#[derive(Clone, Debug)]
+pub enum Error {
+    /// GLFW could not be initialized.
+    GlfwInitFailed(String),
+    /// GLFW could not create a window with any of the GL context versions that were
+    /// tried. The last version is the minimum the renderer supports.
+    WindowCreationFailed(Vec<GLVersion>),
+    /// The GL implementation does not support a required extension.
+    MissingExtension(String),
+    /// A framebuffer is not complete. It carries the framebuffer status.
+    FramebufferIncomplete(GLenum),
+    /// A texture could not be uploaded to the GPU.
+    TextureUploadFailed(String),
+    /// A shader could not be loaded, compiled or linked.
+    Shader(ShaderCompilationError),
+    /// The GPU ran out of memory while creating the named resource.
+    OutOfMemory(String),
+    /// Shader reflection reported a type enum that is not a GLSL type.
+    UnknownGlslType(GLenum),
+    /// A mesh is malformed and cannot be sent to the GPU.
+    InvalidMesh(MeshError),
+    /// A write of `len` bytes at an offset does not fit in a buffer. It carries the
+    /// offset, the length and the size of the buffer.
+    BufferOverflow(usize, usize, usize),
+    /// A Wavefront OBJ model could not be loaded.
+    Obj(ObjError),
+    /// A glTF model could not be loaded.
+    Gltf(GltfError),
+    /// A mesh file could not be read or written.
+    MeshFile(MeshFileError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Error::GlfwInitFailed(ref reason) => {
+                write!(f, "Failed to initialize GLFW: {}", reason)
+            }
+            &Error::WindowCreationFailed(ref versions) => {
+                let tried: Vec<String> = versions.iter().map(|v| format!("{}", v)).collect();
+                write!(f, "Failed to create a window with an {} context. Tried versions {}",
+                    GL_API_NAME, tried.join(", ")
+                )?;
+                if let Some(minimum) = versions.last() {
+                    write!(f, ". {} {} or later is required", GL_API_NAME, minimum)?;
+                }
+
+                Ok(())
+            }
+            &Error::MissingExtension(ref extension) => {
+                write!(
+                    f, "The {} implementation does not support the extension {}", GL_API_NAME, extension
+                )
+            }
+            &Error::FramebufferIncomplete(status) => {
+                write!(f, "The framebuffer is incomplete. Got status 0x{:X}", status)
+            }
+            &Error::TextureUploadFailed(ref reason) => {
+                write!(f, "Failed to upload a texture to the GPU: {}", reason)
+            }
+            &Error::Shader(ref e) => {
+                write!(f, "{}", e)
+            }
+            &Error::OutOfMemory(ref resource) => {
+                write!(f, "The GPU ran out of memory while creating {}", resource)
+            }
+            &Error::UnknownGlslType(gl_type) => {
+                write!(f, "Got an unknown GLSL type enum 0x{:X}", gl_type)
+            }
+            &Error::InvalidMesh(ref e) => {
+                write!(f, "Invalid mesh: {}", e)
+            }
+            &Error::BufferOverflow(offset, len, capacity) => {
+                write!(
+                    f, "Cannot write {} bytes at offset {} into a buffer of {} bytes", len, offset, capacity
+                )
+            }
+            &Error::Obj(ref e) => {
+                write!(f, "{}", e)
+            }
+            &Error::Gltf(ref e) => {
+                write!(f, "{}", e)
+            }
+            &Error::MeshFile(ref e) => {
+                write!(f, "{}", e)
+            }
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<ShaderCompilationError> for Error {
+    fn from(e: ShaderCompilationError) -> Error {
+        Error::Shader(e)
+    }
+}
+
+impl From<MeshError> for Error {
+    fn from(e: MeshError) -> Error {
+        Error::InvalidMesh(e)
+    }
+}
+
+impl From<ObjError> for Error {
+    fn from(e: ObjError) -> Error {
+        Error::Obj(e)
+    }
+}
+
+impl From<GltfError> for Error {
+    fn from(e: GltfError) -> Error {
+        Error::Gltf(e)
+    }
+}
+
+impl From<MeshFileError> for Error {
+    fn from(e: MeshFileError) -> Error {
+        Error::MeshFile(e)
+    }
+}
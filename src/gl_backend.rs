@@ -16,9 +16,11 @@
  *  along with this program.  If not, see <http://www.gnu.org/licenses/>.
  */
 #![allow(dead_code)]
+use crate::error::Error;
 use crate::gl;
+use crate::gl_objects::{Framebuffer, Program, Renderbuffer, Texture};
 use crate::gl::types::{
-    GLboolean, GLchar, GLenum, GLfloat, GLint, GLubyte, GLuint
+    GLchar, GLenum, GLint, GLubyte, GLuint
 };
 use glfw;
 use glfw::{Context, Glfw};
@@ -28,8 +30,8 @@ use std::fs::File;
 use std::io::{Read, BufReader};
 use std::sync::mpsc::Receiver;
 use std::ptr;
+use std::rc::{Rc, Weak};
 use std::fmt;
-use std::mem;
 use std::path::Path;
 
 use log::{info, error};
@@ -51,6 +53,18 @@ pub fn gl_str(st: &str) -> CString {
     CString::new(st).unwrap()
 }
 
+// OpenGL ES counts varyings in vectors rather than in floats.
+#[cfg(not(feature = "gles"))]
+const MAX_VARYING: GLenum = gl::MAX_VARYING_FLOATS;
+#[cfg(not(feature = "gles"))]
+const MAX_VARYING_NAME: &str = "GL_MAX_VARYING_FLOATS";
+#[cfg(feature = "gles")]
+const MAX_VARYING: GLenum = gl::MAX_VARYING_VECTORS;
+#[cfg(feature = "gles")]
+const MAX_VARYING_NAME: &str = "GL_MAX_VARYING_VECTORS";
+// The ES bindings do not define GL_STEREO.
+const GL_STEREO: GLenum = 0x0C33;
+
 /// A record containing a description of the GL capabilities on a local machine.
 /// The contents of this record can be used for debugging OpenGL problems on
 /// different machines.
@@ -71,33 +85,31 @@ impl fmt::Display for GLParameters {
 /// Print out the GL capabilities on a local machine. This is handy for debugging
 /// OpenGL program problems on other people's machines.
 fn gl_params() -> GLParameters {
-    let params: [GLenum; 12] = [
+    let params: [GLenum; 11] = [
         gl::MAX_COMBINED_TEXTURE_IMAGE_UNITS,
         gl::MAX_CUBE_MAP_TEXTURE_SIZE,
         gl::MAX_DRAW_BUFFERS,
         gl::MAX_FRAGMENT_UNIFORM_COMPONENTS,
         gl::MAX_TEXTURE_IMAGE_UNITS,
         gl::MAX_TEXTURE_SIZE,
-        gl::MAX_VARYING_FLOATS,
+        MAX_VARYING,
         gl::MAX_VERTEX_ATTRIBS,
         gl::MAX_VERTEX_TEXTURE_IMAGE_UNITS,
         gl::MAX_VERTEX_UNIFORM_COMPONENTS,
         gl::MAX_VIEWPORT_DIMS,
-        gl::STEREO,
     ];
-    let names: [&str; 12] = [
+    let names: [&str; 11] = [
         "GL_MAX_COMBINED_TEXTURE_IMAGE_UNITS",
         "GL_MAX_CUBE_MAP_TEXTURE_SIZE",
         "GL_MAX_DRAW_BUFFERS",
         "GL_MAX_FRAGMENT_UNIFORM_COMPONENTS",
         "GL_MAX_TEXTURE_IMAGE_UNITS",
         "GL_MAX_TEXTURE_SIZE",
-        "GL_MAX_VARYING_FLOATS",
+        MAX_VARYING_NAME,
         "GL_MAX_VERTEX_ATTRIBS",
         "GL_MAX_VERTEX_TEXTURE_IMAGE_UNITS",
         "GL_MAX_VERTEX_UNIFORM_COMPONENTS",
         "GL_MAX_VIEWPORT_DIMS",
-        "GL_STEREO",
     ];
     let mut vec: Vec<(String, String)> = vec![];
     // Integers: this only works if the order is 0-10 integer return types.
@@ -114,134 +126,387 @@ fn gl_params() -> GLParameters {
         gl::GetIntegerv(params[10], &mut v[0]);
     }
     vec.push((format!("{}", names[10]), format!("{} {}", v[0], v[1])));
-    let mut s = 0;
-    unsafe {
-        gl::GetBooleanv(params[11], &mut s);
+    // OpenGL ES has no stereo rendering.
+    if cfg!(not(feature = "gles")) {
+        let mut s = 0;
+        unsafe {
+            gl::GetBooleanv(GL_STEREO, &mut s);
+        }
+        vec.push((String::from("GL_STEREO"), format!("{}", s as usize)));
     }
-    vec.push((format!("{}", names[11]), format!("{}", s as usize)));
 
     GLParameters {
         params: vec,
     }
 }
 
-/// Helper function to convert GLSL types to storage sizes
-fn type_size(gl_type: GLenum) -> usize {
-    match gl_type {
-        gl::FLOAT             => 1 * mem::size_of::<GLfloat>(),
-        gl::FLOAT_VEC2        => 2 * mem::size_of::<GLfloat>(),
-        gl::FLOAT_VEC3        => 3 * mem::size_of::<GLfloat>(),
-        gl::FLOAT_VEC4        => 4 * mem::size_of::<GLfloat>(),
-        gl::INT               => 1 * mem::size_of::<GLint>(),
-        gl::INT_VEC2          => 2 * mem::size_of::<GLint>(),
-        gl::INT_VEC3          => 3 * mem::size_of::<GLint>(),
-        gl::INT_VEC4          => 4 * mem::size_of::<GLint>(),
-        gl::UNSIGNED_INT      => 1 * mem::size_of::<GLuint>(),
-        gl::UNSIGNED_INT_VEC2 => 2 * mem::size_of::<GLuint>(),
-        gl::UNSIGNED_INT_VEC3 => 3 * mem::size_of::<GLuint>(),
-        gl::UNSIGNED_INT_VEC4 => 4 * mem::size_of::<GLuint>(),
-        gl::BOOL              => 1 * mem::size_of::<GLboolean>(),
-        gl::BOOL_VEC2         => 2 * mem::size_of::<GLboolean>(),
-        gl::BOOL_VEC3         => 3 * mem::size_of::<GLboolean>(),
-        gl::BOOL_VEC4         => 4 * mem::size_of::<GLboolean>(),
-        gl::FLOAT_MAT2        => 4 * mem::size_of::<GLfloat>(),
-        gl::FLOAT_MAT2x3      => 6 * mem::size_of::<GLfloat>(),
-        gl::FLOAT_MAT2x4      => 8 * mem::size_of::<GLfloat>(),
-        gl::FLOAT_MAT3        => 9 * mem::size_of::<GLfloat>(),
-        gl::FLOAT_MAT3x2      => 6 * mem::size_of::<GLfloat>(),
-        gl::FLOAT_MAT3x4      => 12 * mem::size_of::<GLfloat>(),
-        gl::FLOAT_MAT4        => 16 * mem::size_of::<GLfloat>(),
-        gl::FLOAT_MAT4x2      => 8 * mem::size_of::<GLfloat>(),
-        gl::FLOAT_MAT4x3      => 12 * mem::size_of::<GLfloat>(),
-        _ => panic!()
+/// Select where the GL context sends its rendered frames.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ContextMode {
+    /// Render into the default framebuffer of a visible window.
+    Windowed,
+    /// Render into an offscreen framebuffer attached to an invisible window. On a
+    /// machine without a GPU or a display, run under a virtual X server such as
+    /// `xvfb-run` with `LIBGL_ALWAYS_SOFTWARE=1` to get Mesa's llvmpipe rasterizer.
+    Headless,
+}
+
+/// An offscreen render target with a color texture and a depth renderbuffer.
+/// Headless contexts render into one of these instead of the default framebuffer.
+pub struct OffscreenFramebuffer {
+    pub fbo: Framebuffer,
+    pub color_tex: Texture,
+    pub depth_rbo: Renderbuffer,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Create an offscreen framebuffer with an RGBA8 color attachment and a 24-bit
+/// depth attachment.
+pub fn create_offscreen_framebuffer(
+    context: &GLState, width: u32, height: u32) -> Result<OffscreenFramebuffer, Error> {
+
+    let fbo = Framebuffer::new(context);
+    let color_tex = Texture::new(context, gl::TEXTURE_2D);
+    let depth_rbo = Renderbuffer::new(context);
+
+    let status = unsafe {
+        color_tex.bind();
+        gl::TexImage2D(
+            gl::TEXTURE_2D, 0, gl::RGBA8 as GLint, width as GLint, height as GLint, 0,
+            gl::RGBA, gl::UNSIGNED_BYTE, ptr::null()
+        );
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as GLint);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as GLint);
+        gl::BindTexture(gl::TEXTURE_2D, 0);
+
+        depth_rbo.bind();
+        gl::RenderbufferStorage(
+            gl::RENDERBUFFER, gl::DEPTH_COMPONENT24, width as GLint, height as GLint
+        );
+        gl::BindRenderbuffer(gl::RENDERBUFFER, 0);
+
+        fbo.bind(gl::FRAMEBUFFER);
+        gl::FramebufferTexture2D(
+            gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, color_tex.name(), 0
+        );
+        gl::FramebufferRenderbuffer(
+            gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, gl::RENDERBUFFER, depth_rbo.name()
+        );
+        gl::CheckFramebufferStatus(gl::FRAMEBUFFER)
+    };
+
+    if status != gl::FRAMEBUFFER_COMPLETE {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+        error!("Offscreen framebuffer is incomplete. Got status 0x{:X}", status);
+        return Err(Error::FramebufferIncomplete(status));
+    }
+
+    Ok(OffscreenFramebuffer {
+        fbo: fbo,
+        color_tex: color_tex,
+        depth_rbo: depth_rbo,
+        width: width,
+        height: height,
+    })
+}
+
+/// The name of the graphics API the crate was built against.
+#[cfg(not(feature = "gles"))]
+pub const GL_API_NAME: &str = "OpenGL";
+/// The name of the graphics API the crate was built against.
+#[cfg(feature = "gles")]
+pub const GL_API_NAME: &str = "OpenGL ES";
+
+/// An OpenGL version number.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct GLVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl fmt::Display for GLVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// The optional capabilities of a GL context. Each tier is available either from
+/// the core version of the context or from an extension. Code paths that depend
+/// on one of these check it and fall back to the minimum version otherwise.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct GLFeatures {
+    /// The version of the context the driver created. This can be newer than the
+    /// version that was requested.
+    pub version: GLVersion,
+    /// Compute shaders (GL 4.3, `GL_ARB_compute_shader`).
+    pub compute: bool,
+    /// Direct state access (GL 4.5, `GL_ARB_direct_state_access`).
+    pub direct_state_access: bool,
+    /// Immutable buffer storage (GL 4.4, `GL_ARB_buffer_storage`).
+    pub buffer_storage: bool,
+    /// Anisotropic texture filtering (GL 4.6, `GL_ARB_texture_filter_anisotropic`
+    /// or `GL_EXT_texture_filter_anisotropic`).
+    pub anisotropic_filtering: bool,
+}
+
+impl GLFeatures {
+    /// Query the capabilities of the current GL context.
+    pub fn query() -> GLFeatures {
+        let mut major = 0;
+        let mut minor = 0;
+        unsafe {
+            gl::GetIntegerv(gl::MAJOR_VERSION, &mut major);
+            gl::GetIntegerv(gl::MINOR_VERSION, &mut minor);
+        }
+        let version = GLVersion { major: major as u32, minor: minor as u32 };
+        let at_least = |maj, min| { version >= GLVersion { major: maj, minor: min } };
+
+        // OpenGL ES has none of these as core features at the versions we target,
+        // and the version numbers of the two APIs do not line up.
+        let is_desktop = cfg!(not(feature = "gles"));
+        GLFeatures {
+            version: version,
+            compute: (is_desktop && at_least(4, 3)) || has_extension("GL_ARB_compute_shader"),
+            direct_state_access: is_desktop && (at_least(4, 5) || has_extension("GL_ARB_direct_state_access")),
+            buffer_storage: (is_desktop && at_least(4, 4))
+                || has_extension("GL_ARB_buffer_storage")
+                || has_extension("GL_EXT_buffer_storage"),
+            anisotropic_filtering: (is_desktop && at_least(4, 6))
+                || has_extension("GL_ARB_texture_filter_anisotropic")
+                || has_extension("GL_EXT_texture_filter_anisotropic"),
+        }
+    }
+}
+
+impl Default for GLFeatures {
+    /// The capabilities of the minimum supported context.
+    fn default() -> GLFeatures {
+        let version = GL_VERSION_CANDIDATES[GL_VERSION_CANDIDATES.len() - 1];
+        GLFeatures {
+            version: version,
+            compute: false,
+            direct_state_access: false,
+            buffer_storage: false,
+            anisotropic_filtering: false,
+        }
+    }
+}
+
+impl fmt::Display for GLFeatures {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "GL Context Features:").unwrap();
+        writeln!(f, "version = {}", self.version).unwrap();
+        writeln!(f, "compute = {}", self.compute).unwrap();
+        writeln!(f, "direct_state_access = {}", self.direct_state_access).unwrap();
+        writeln!(f, "buffer_storage = {}", self.buffer_storage).unwrap();
+        writeln!(f, "anisotropic_filtering = {}", self.anisotropic_filtering)
+    }
+}
+
+/// A weak handle to a GL context. GL objects hold one of these so they can tell
+/// whether the context that owns their names still exists, and make that context
+/// current before deleting them.
+#[derive(Clone, Debug)]
+pub struct ContextHandle {
+    token: Weak<()>,
+    window: *mut glfw::ffi::GLFWwindow,
+}
+
+impl ContextHandle {
+    /// Determine whether the context is still alive.
+    #[inline]
+    pub fn is_alive(&self) -> bool {
+        self.token.upgrade().is_some()
+    }
+
+    /// Run a function with the context current, then restore whichever context was
+    /// current before. GL names belong to the context that created them, so this
+    /// keeps an object from deleting a name in some other context. The function is
+    /// not run if the context no longer exists.
+    pub fn with_current<F: FnOnce()>(&self, f: F) {
+        if !self.is_alive() {
+            return;
+        }
+        unsafe {
+            let previous = glfw::ffi::glfwGetCurrentContext();
+            if previous != self.window {
+                glfw::ffi::glfwMakeContextCurrent(self.window);
+            }
+            f();
+            if previous != self.window {
+                glfw::ffi::glfwMakeContextCurrent(previous);
+            }
+        }
     }
 }
 
+/// The source of time for a GL context.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Clock {
+    /// Read the wall clock time from GLFW.
+    Realtime,
+    /// A virtual clock that advances by a fixed step every frame. Use this to make
+    /// the timing of a run reproducible, e.g. when replaying recorded input.
+    Fixed { time_seconds: f64, step_seconds: f64 },
+}
+
 /// A record for storing all the OpenGL state needed on the application side
 /// of the graphics application in order to manage OpenGL and GLFW.
 pub struct GLState {
     pub glfw: glfw::Glfw,
     pub window: glfw::Window,
     pub events: Receiver<(f64, glfw::WindowEvent)>,
+    pub mode: ContextMode,
+    pub version: GLVersion,
+    pub features: GLFeatures,
+    pub offscreen: Option<OffscreenFramebuffer>,
+    context_token: Rc<()>,
     pub width: u32,
     pub height: u32,
     pub channel_depth: u32,
+    pub clock: Clock,
     pub running_time_seconds: f64,
     pub framerate_time_seconds: f64,
     pub frame_count: u32,
 }
 
-#[cfg(target_os = "macos")]
-fn __init_glfw() -> Glfw {
-    // Start a GL context and OS window using the GLFW helper library.
-    let mut glfw = glfw::init(glfw::FAIL_ON_ERRORS).unwrap();
+impl GLState {
+    /// Determine whether the context renders offscreen.
+    #[inline]
+    pub fn is_headless(&self) -> bool {
+        self.mode == ContextMode::Headless
+    }
 
-    // We must place the window hints before creating the window because
-    // glfw cannot change the properties of a window after it has been created.
-    glfw.window_hint(glfw::WindowHint::Resizable(false));
-    glfw.window_hint(glfw::WindowHint::Samples(Some(4)));
-    glfw.window_hint(glfw::WindowHint::ContextVersionMajor(3));
-    glfw.window_hint(glfw::WindowHint::ContextVersionMinor(3));
-    glfw.window_hint(glfw::WindowHint::OpenGlForwardCompat(true));
-    glfw.window_hint(glfw::WindowHint::OpenGlProfile(glfw::OpenGlProfileHint::Core));
-    glfw.window_hint(glfw::WindowHint::OpenGlForwardCompat(true));
+    /// Get a handle for tying the lifetime of GL objects to this context.
+    #[inline]
+    pub fn handle(&self) -> ContextHandle {
+        ContextHandle {
+            token: Rc::downgrade(&self.context_token),
+            window: self.window.window_ptr(),
+        }
+    }
 
-    glfw
+    /// Get the current time in seconds from the context's clock.
+    #[inline]
+    pub fn get_time(&self) -> f64 {
+        match self.clock {
+            Clock::Realtime => self.glfw.get_time(),
+            Clock::Fixed { time_seconds, .. } => time_seconds,
+        }
+    }
 }
 
-#[cfg(target_os = "windows")]
-fn __init_glfw() -> Glfw {
-    // Start a GL context and OS window using the GLFW helper library.
-    let mut glfw = glfw::init(glfw::FAIL_ON_ERRORS).unwrap();
+impl Drop for GLState {
+    fn drop(&mut self) {
+        // Delete the offscreen framebuffer while the window, and therefore the
+        // context, still exists.
+        self.offscreen = None;
+    }
+}
+
+/// Start GLFW and set the window hints that do not depend on the context version.
+fn __init_glfw() -> Result<Glfw, Error> {
+    // Start a GL context and OS window using the GLFW helper library. Log errors
+    // instead of panicking, so a failure to create a window reaches the caller.
+    let mut glfw = match glfw::init(glfw::LOG_ERRORS) {
+        Ok(val) => val,
+        Err(e) => return Err(Error::GlfwInitFailed(format!("{:?}", e))),
+    };
 
     // We must place the window hints before creating the window because
     // glfw cannot change the properties of a window after it has been created.
     glfw.window_hint(glfw::WindowHint::Resizable(false));
     glfw.window_hint(glfw::WindowHint::Samples(Some(4)));
-    glfw.window_hint(glfw::WindowHint::ContextVersionMajor(3));
-    glfw.window_hint(glfw::WindowHint::ContextVersionMinor(3));
-    glfw.window_hint(glfw::WindowHint::OpenGlForwardCompat(true));
-    glfw.window_hint(glfw::WindowHint::OpenGlProfile(glfw::OpenGlProfileHint::Core));
 
-    glfw
+    Ok(glfw)
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows")))]
-fn __init_glfw() -> Glfw {
-    // Start a GL context and OS window using the GLFW helper library.
-    let mut glfw = glfw::init(glfw::FAIL_ON_ERRORS).unwrap();
-
-    // We must place the window hints before creating the window because
-    // glfw cannot change the properties of a window after it has been created.
-    glfw.window_hint(glfw::WindowHint::Resizable(false));
-    glfw.window_hint(glfw::WindowHint::Samples(Some(4)));
+// The context versions to try, from most to least preferred. The last entry is
+// the minimum version the renderer supports. macOS stops at 4.1.
+#[cfg(all(target_os = "macos", not(feature = "gles")))]
+const GL_VERSION_CANDIDATES: [GLVersion; 2] = [
+    GLVersion { major: 4, minor: 1 },
+    GLVersion { major: 3, minor: 3 },
+];
+
+#[cfg(not(any(target_os = "macos", feature = "gles")))]
+const GL_VERSION_CANDIDATES: [GLVersion; 4] = [
+    GLVersion { major: 4, minor: 6 },
+    GLVersion { major: 4, minor: 5 },
+    GLVersion { major: 4, minor: 3 },
+    GLVersion { major: 3, minor: 3 },
+];
+
+#[cfg(feature = "gles")]
+const GL_VERSION_CANDIDATES: [GLVersion; 1] = [
+    GLVersion { major: 3, minor: 0 },
+];
+
+/// Set the window hints requesting a core profile context of a given version.
+#[cfg(not(feature = "gles"))]
+fn __hint_gl_version(glfw: &mut Glfw, version: GLVersion) {
+    glfw.window_hint(glfw::WindowHint::ContextVersionMajor(version.major));
+    glfw.window_hint(glfw::WindowHint::ContextVersionMinor(version.minor));
+    glfw.window_hint(glfw::WindowHint::OpenGlForwardCompat(true));
+    glfw.window_hint(glfw::WindowHint::OpenGlProfile(glfw::OpenGlProfileHint::Core));
+}
 
-    glfw
+/// Set the window hints requesting an OpenGL ES context of a given version.
+#[cfg(feature = "gles")]
+fn __hint_gl_version(glfw: &mut Glfw, version: GLVersion) {
+    // Mesa exposes OpenGL ES through EGL on boards without a desktop GL driver.
+    glfw.window_hint(glfw::WindowHint::ClientApi(glfw::ClientApiHint::OpenGlEs));
+    glfw.window_hint(glfw::WindowHint::ContextCreationApi(glfw::ContextCreationApi::Egl));
+    glfw.window_hint(glfw::WindowHint::ContextVersionMajor(version.major));
+    glfw.window_hint(glfw::WindowHint::ContextVersionMinor(version.minor));
 }
 
-/// Initialize a new OpenGL context and start a new GLFW window. 
-pub fn start_gl(width: u32, height: u32) -> Result<GLState, String> {
+/// Initialize a new OpenGL context and start a new GLFW window. In headless mode
+/// the window stays hidden and all rendering goes to an offscreen framebuffer.
+pub fn start_gl(width: u32, height: u32, mode: ContextMode) -> Result<GLState, Error> {
     // Start GL context and O/S window using the GLFW helper library.
     info!("Starting GLFW");
     info!("Using GLFW version {}", glfw::get_version_string());
 
     // Start a GL context and OS window using the GLFW helper library.
-    let glfw = __init_glfw();
+    let mut glfw = __init_glfw()?;
+    if mode == ContextMode::Headless {
+        info!("Starting GLFW in headless mode");
+        glfw.window_hint(glfw::WindowHint::Visible(false));
+    }
 
     info!("Started GLFW successfully");
-    let maybe_glfw_window = glfw.create_window(
-        width, height, &format!("GB_Prototype"), glfw::WindowMode::Windowed
-    );
-    let (mut window, events) = match maybe_glfw_window {
+    // Try each context version in turn until the driver gives us one.
+    let mut maybe_glfw_window = None;
+    for &version in GL_VERSION_CANDIDATES.iter() {
+        info!("Requesting an {} {} context", GL_API_NAME, version);
+        __hint_gl_version(&mut glfw, version);
+        maybe_glfw_window = glfw.create_window(
+            width, height, &format!("GB_Prototype"), glfw::WindowMode::Windowed
+        ).map(|(window, events)| (window, events, version));
+        if maybe_glfw_window.is_some() {
+            break;
+        }
+    }
+    let (mut window, events, version) = match maybe_glfw_window {
         Some(tuple) => tuple,
         None => {
-            error!("Failed to create GLFW window");
-            return Err(String::new());
+            let e = Error::WindowCreationFailed(GL_VERSION_CANDIDATES.to_vec());
+            error!("{}", e);
+            return Err(e);
         }
     };
+    info!("Created an {} {} context", GL_API_NAME, version);
 
     window.make_current();
     window.set_key_polling(true);
+    window.set_char_polling(true);
+    window.set_mouse_button_polling(true);
+    window.set_cursor_pos_polling(true);
+    window.set_scroll_polling(true);
     window.set_size_polling(true);
     window.set_refresh_polling(true);
     window.set_size_polling(true);
@@ -254,28 +519,114 @@ pub fn start_gl(width: u32, height: u32) -> Result<GLState, String> {
     let renderer = glubyte_ptr_to_string(unsafe { gl::GetString(gl::RENDERER) });
     info!("Renderer: {}", renderer);
 
-    let version = glubyte_ptr_to_string(unsafe { gl::GetString(gl::VERSION) });
-    info!("OpenGL version supported: {}", version);
+    let version_string = glubyte_ptr_to_string(unsafe { gl::GetString(gl::VERSION) });
+    info!("OpenGL version supported: {}", version_string);
     info!("{}", gl_params());
+    let features = GLFeatures::query();
+    info!("{}", features);
 
-    Ok(GLState {
+    let mut gl_state = GLState {
         glfw: glfw, 
         window: window, 
         events: events,
+        mode: mode,
+        version: version,
+        features: features,
+        offscreen: None,
+        context_token: Rc::new(()),
         width: width,
         height: height,
         channel_depth: 3,
+        clock: Clock::Realtime,
         running_time_seconds: 0.0,
         framerate_time_seconds: 0.0,
         frame_count: 0,
-    })
+    };
+    if mode == ContextMode::Headless {
+        let framebuffer = create_offscreen_framebuffer(&gl_state, width, height)?;
+        info!("Rendering to offscreen framebuffer {}", framebuffer.fbo.name());
+        gl_state.offscreen = Some(framebuffer);
+    }
+    bind_render_target(&gl_state);
+
+    Ok(gl_state)
+}
+
+/// Bind the framebuffer that the context renders into. This is the offscreen
+/// framebuffer for a headless context and the default framebuffer otherwise.
+#[inline]
+pub fn bind_render_target(context: &GLState) {
+    let fbo = match context.offscreen {
+        Some(ref framebuffer) => framebuffer.fbo.name(),
+        None => 0,
+    };
+    unsafe {
+        gl::BindFramebuffer(gl::FRAMEBUFFER, fbo);
+    }
+}
+
+/// Finish the current frame. A windowed context swaps its buffers, and a
+/// headless context waits for the GPU to finish rendering into the offscreen
+/// framebuffer.
+#[inline]
+pub fn present(context: &mut GLState) {
+    match context.mode {
+        ContextMode::Windowed => context.window.swap_buffers(),
+        ContextMode::Headless => unsafe { gl::Finish() },
+    }
+}
+
+/// Determine whether the current GL context supports an extension, e.g.
+/// `GL_EXT_texture_filter_anisotropic`.
+pub fn has_extension(extension: &str) -> bool {
+    let mut count = 0;
+    unsafe {
+        gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut count);
+    }
+    for i in 0..count {
+        let name = glubyte_ptr_to_string(unsafe { gl::GetStringi(gl::EXTENSIONS, i as GLuint) });
+        if name == extension {
+            return true;
+        }
+    }
+
+    false
+}
+
+/// Require the current GL context to support an extension.
+pub fn require_extension(extension: &str) -> Result<(), Error> {
+    if has_extension(extension) {
+        Ok(())
+    } else {
+        Err(Error::MissingExtension(String::from(extension)))
+    }
+}
+
+/// Clear the GL error flags, and return the first error that was raised since
+/// the last call.
+pub fn take_gl_error() -> Option<GLenum> {
+    let mut first_error = None;
+    loop {
+        let code = unsafe { gl::GetError() };
+        if code == gl::NO_ERROR {
+            break;
+        }
+        if first_error.is_none() {
+            first_error = Some(code);
+        }
+    }
+
+    first_error
 }
 
 /// Updates the timers in a GL context. It returns the elapsed time since the last call to
-/// `update_timers`.
+/// `update_timers`. A fixed clock advances by one step on every call.
 #[inline]
 pub fn update_timers(context: &mut GLState) -> f64 {
-    let current_seconds = context.glfw.get_time();
+    if let Clock::Fixed { ref mut time_seconds, step_seconds } = context.clock {
+        *time_seconds += step_seconds;
+    }
+    let current_seconds = context.get_time();
     let elapsed_seconds = current_seconds - context.running_time_seconds;
     context.running_time_seconds = current_seconds;
 
@@ -285,7 +636,7 @@ pub fn update_timers(context: &mut GLState) -> f64 {
 /// Update the framerate and display in the window titlebar.
 #[inline]
 pub fn update_fps_counter(context: &mut GLState) {     
-    let current_time_seconds = context.glfw.get_time();
+    let current_time_seconds = context.get_time();
     let elapsed_seconds = current_time_seconds - context.framerate_time_seconds;
     if elapsed_seconds > 0.5 {
         context.framerate_time_seconds = current_time_seconds;
@@ -347,6 +698,54 @@ pub fn parse_shader<P: AsRef<Path>, R: Read>(
     Ok(bytes_read)
 }
 
+/// The precision statements added to shaders rewritten for OpenGL ES. Fragment
+/// shaders have no default float precision in GLSL ES, and the sampler types below
+/// have no default precision in any stage.
+const GLES_PRECISION_QUALIFIERS: &str = "\
+precision highp float;
+precision highp int;
+precision highp sampler3D;
+precision highp sampler2DArray;
+precision highp sampler2DShadow;
+precision highp samplerCubeShadow;
+precision highp sampler2DArrayShadow;
+precision highp isampler2D;
+precision highp isampler3D;
+precision highp isamplerCube;
+precision highp isampler2DArray;
+precision highp usampler2D;
+precision highp usampler3D;
+precision highp usamplerCube;
+precision highp usampler2DArray;
+";
+
+/// Rewrite a desktop GLSL 3.30 shader as a GLSL ES 3.00 shader. The `#version`
+/// directive becomes `#version 300 es` and default precision qualifiers follow
+/// it. A shader without a `#version` directive gets one. Precision statements in
+/// the shader itself come after ours, so they take precedence.
+pub fn rewrite_shader_for_gles(source: &str) -> String {
+    let mut rewritten = String::with_capacity(source.len() + GLES_PRECISION_QUALIFIERS.len() + 32);
+    let mut found_version = false;
+    for line in source.lines() {
+        if !found_version && line.trim_start().starts_with("#version") {
+            rewritten.push_str("#version 300 es\n");
+            rewritten.push_str(GLES_PRECISION_QUALIFIERS);
+            found_version = true;
+        } else {
+            rewritten.push_str(line);
+            rewritten.push('\n');
+        }
+    }
+
+    if !found_version {
+        let mut header = String::from("#version 300 es\n");
+        header.push_str(GLES_PRECISION_QUALIFIERS);
+        rewritten.insert_str(0, &header);
+    }
+
+    rewritten
+}
+
 /// A record containing all the relevant compilation log information for a
 /// given GLSL shader compiled at run time.
 pub struct ShaderLog {
@@ -406,6 +805,14 @@ pub fn create_shader<P: AsRef<Path>, R: Read>(
         );
     }
 
+    // OpenGL ES builds compile the same shader sources as desktop builds.
+    if cfg!(feature = "gles") {
+        let source = String::from_utf8_lossy(&shader_string[0..bytes_read]);
+        let mut rewritten = rewrite_shader_for_gles(&source).into_bytes();
+        rewritten.push(0);
+        shader_string = rewritten;
+    }
+
     let shader = unsafe { gl::CreateShader(kind) };
     let p = shader_string.as_ptr() as *const GLchar;
     unsafe {
@@ -488,10 +895,10 @@ pub fn validate_shader_program(sp: GLuint) -> bool {
     true
 }
 
-/// Compile and link a shader program.
+/// Compile and link a shader program. The returned program deletes itself when dropped.
 pub fn create_program(
-    _context: &GLState,
-    vertex_shader: GLuint, fragment_shader: GLuint) -> Result<GLuint, ShaderCompilationError> {
+    context: &GLState,
+    vertex_shader: GLuint, fragment_shader: GLuint) -> Result<Program, ShaderCompilationError> {
 
     let program = unsafe { gl::CreateProgram() };
     info!("Created program {}. Attaching shaders {} and {}.\n",
@@ -513,6 +920,11 @@ pub fn create_program(
     if params != gl::TRUE as i32 {
         error!("ERROR: could not link shader program GL index {}\n", program);
         error!("{}", program_info_log(program));
+        unsafe {
+            gl::DeleteProgram(program);
+            gl::DeleteShader(vertex_shader);
+            gl::DeleteShader(fragment_shader);
+        }
         return Err(ShaderCompilationError::CouldNotLinkShader);
     }
 
@@ -522,13 +934,13 @@ pub fn create_program(
         gl::DeleteShader(fragment_shader);
     }
 
-    Ok(program)
+    Ok(Program::from_raw(context, program))
 }
 
 /// Compile and link a shader program directly from the files.
 pub fn create_program_from_files<P: AsRef<Path>, Q: AsRef<Path>>(
     context: &GLState,
-    vert_file_name: P, frag_file_name: Q) -> Result<GLuint, ShaderCompilationError> {
+    vert_file_name: P, frag_file_name: Q) -> Result<Program, ShaderCompilationError> {
 
     let mut vert_reader = BufReader::new(match File::open(&vert_file_name) {
         Ok(val) => val,
@@ -560,7 +972,7 @@ pub fn create_program_from_files<P: AsRef<Path>, Q: AsRef<Path>>(
 pub fn create_program_from_reader<R1: Read, P1: AsRef<Path>, R2: Read, P2: AsRef<Path>>(
     context: &GLState,
     vert_reader: &mut R1, vert_file_name: P1,
-    frag_reader: &mut R2, frag_file_name: P2) -> Result<GLuint, ShaderCompilationError> {
+    frag_reader: &mut R2, frag_file_name: P2) -> Result<Program, ShaderCompilationError> {
 
     let vertex_shader = create_shader(
         context, vert_reader, vert_file_name, gl::VERTEX_SHADER
@@ -572,3 +984,51 @@ pub fn create_program_from_reader<R1: Read, P1: AsRef<Path>, R2: Read, P2: AsRef
 
     Ok(program)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn test_rewrite_inserts_precision_after_version() {
+        let source = "#version 330 core\nin vec3 v_pos;\nvoid main() {}\n";
+        let rewritten = rewrite_shader_for_gles(source);
+
+        let expected = format!("#version 300 es\n{}in vec3 v_pos;\nvoid main() {{}}\n", GLES_PRECISION_QUALIFIERS);
+        assert_eq!(rewritten, expected);
+    }
+
+    #[test]
+    fn test_rewrite_keeps_leading_comment() {
+        let source = "// A comment before the directive.\n  #version 330 core\nvoid main() {}\n";
+        let rewritten = rewrite_shader_for_gles(source);
+
+        let expected = format!(
+            "// A comment before the directive.\n#version 300 es\n{}void main() {{}}\n",
+            GLES_PRECISION_QUALIFIERS
+        );
+        assert_eq!(rewritten, expected);
+    }
+
+    #[test]
+    fn test_rewrite_adds_missing_version() {
+        let source = "precision mediump float;\nvoid main() {}";
+        let rewritten = rewrite_shader_for_gles(source);
+
+        let expected = format!(
+            "#version 300 es\n{}precision mediump float;\nvoid main() {{}}\n",
+            GLES_PRECISION_QUALIFIERS
+        );
+        assert_eq!(rewritten, expected);
+    }
+
+    #[test]
+    fn test_rewrite_only_replaces_first_version() {
+        let source = "#version 330 core\n#version 330 core\n";
+        let rewritten = rewrite_shader_for_gles(source);
+
+        assert_eq!(rewritten.matches("#version 300 es").count(), 1);
+        assert!(rewritten.ends_with("#version 330 core\n"));
+    }
+}
@@ -0,0 +1,285 @@
+/*
+ *  GB-Prototype is a renderer prototype demo.
+ *  Copyright (C) 2018,2019,2020  Christopher Blanchard
+ *
+ *  This program is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  This program is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+#![allow(dead_code)]
+//! Owned OpenGL objects. Each type here owns a GL name and deletes it when it is
+//! dropped. The objects hold a weak handle to the context that created them, so
+//! an object that outlives its context does not touch OpenGL on drop; the driver
+//! already freed it along with the context. While the context lives, it is made
+//! current for the delete, so names are never deleted in another context.
+use crate::gl;
+use crate::gl::types::{GLenum, GLuint};
+use crate::gl_backend::{ContextHandle, GLState};
+
+
+/// An owned texture object.
+#[derive(Debug)]
+pub struct Texture {
+    name: GLuint,
+    target: GLenum,
+    context: ContextHandle,
+}
+
+impl Texture {
+    /// Generate a new texture object for a texture target such as `gl::TEXTURE_2D`.
+    pub fn new(context: &GLState, target: GLenum) -> Texture {
+        let mut name = 0;
+        unsafe {
+            gl::GenTextures(1, &mut name);
+        }
+        debug_assert!(name > 0);
+
+        Texture { name: name, target: target, context: context.handle() }
+    }
+
+    /// The GL name of the texture.
+    #[inline]
+    pub fn name(&self) -> GLuint {
+        self.name
+    }
+
+    /// The texture target the texture was created for.
+    #[inline]
+    pub fn target(&self) -> GLenum {
+        self.target
+    }
+
+    /// Bind the texture to the currently active texture unit.
+    #[inline]
+    pub fn bind(&self) {
+        unsafe {
+            gl::BindTexture(self.target, self.name);
+        }
+    }
+}
+
+impl Drop for Texture {
+    fn drop(&mut self) {
+        let name = self.name;
+        self.context.with_current(|| unsafe {
+            gl::DeleteTextures(1, &name);
+        });
+    }
+}
+
+/// An owned buffer object.
+#[derive(Debug)]
+pub struct BufferObject {
+    name: GLuint,
+    context: ContextHandle,
+}
+
+impl BufferObject {
+    /// Generate a new buffer object.
+    pub fn new(context: &GLState) -> BufferObject {
+        let mut name = 0;
+        unsafe {
+            gl::GenBuffers(1, &mut name);
+        }
+        debug_assert!(name > 0);
+
+        BufferObject { name: name, context: context.handle() }
+    }
+
+    /// The GL name of the buffer.
+    #[inline]
+    pub fn name(&self) -> GLuint {
+        self.name
+    }
+
+    /// Bind the buffer to a buffer target such as `gl::ARRAY_BUFFER`.
+    #[inline]
+    pub fn bind(&self, target: GLenum) {
+        unsafe {
+            gl::BindBuffer(target, self.name);
+        }
+    }
+}
+
+impl Drop for BufferObject {
+    fn drop(&mut self) {
+        let name = self.name;
+        self.context.with_current(|| unsafe {
+            gl::DeleteBuffers(1, &name);
+        });
+    }
+}
+
+/// An owned vertex array object.
+#[derive(Debug)]
+pub struct VertexArray {
+    name: GLuint,
+    context: ContextHandle,
+}
+
+impl VertexArray {
+    /// Generate a new vertex array object.
+    pub fn new(context: &GLState) -> VertexArray {
+        let mut name = 0;
+        unsafe {
+            gl::GenVertexArrays(1, &mut name);
+        }
+        debug_assert!(name > 0);
+
+        VertexArray { name: name, context: context.handle() }
+    }
+
+    /// The GL name of the vertex array.
+    #[inline]
+    pub fn name(&self) -> GLuint {
+        self.name
+    }
+
+    #[inline]
+    pub fn bind(&self) {
+        unsafe {
+            gl::BindVertexArray(self.name);
+        }
+    }
+}
+
+impl Drop for VertexArray {
+    fn drop(&mut self) {
+        let name = self.name;
+        self.context.with_current(|| unsafe {
+            gl::DeleteVertexArrays(1, &name);
+        });
+    }
+}
+
+/// An owned, linked shader program.
+#[derive(Debug)]
+pub struct Program {
+    name: GLuint,
+    context: ContextHandle,
+}
+
+impl Program {
+    /// Take ownership of a program object created with `gl::CreateProgram`.
+    pub fn from_raw(context: &GLState, name: GLuint) -> Program {
+        debug_assert!(name > 0);
+        Program { name: name, context: context.handle() }
+    }
+
+    /// The GL name of the program.
+    #[inline]
+    pub fn name(&self) -> GLuint {
+        self.name
+    }
+
+    /// Make the program part of the current rendering state.
+    #[inline]
+    pub fn bind(&self) {
+        unsafe {
+            gl::UseProgram(self.name);
+        }
+    }
+}
+
+impl Drop for Program {
+    fn drop(&mut self) {
+        let name = self.name;
+        self.context.with_current(|| unsafe {
+            gl::DeleteProgram(name);
+        });
+    }
+}
+
+/// An owned framebuffer object.
+#[derive(Debug)]
+pub struct Framebuffer {
+    name: GLuint,
+    context: ContextHandle,
+}
+
+impl Framebuffer {
+    /// Generate a new framebuffer object.
+    pub fn new(context: &GLState) -> Framebuffer {
+        let mut name = 0;
+        unsafe {
+            gl::GenFramebuffers(1, &mut name);
+        }
+        debug_assert!(name > 0);
+
+        Framebuffer { name: name, context: context.handle() }
+    }
+
+    /// The GL name of the framebuffer.
+    #[inline]
+    pub fn name(&self) -> GLuint {
+        self.name
+    }
+
+    /// Bind the framebuffer to a framebuffer target such as `gl::FRAMEBUFFER`.
+    #[inline]
+    pub fn bind(&self, target: GLenum) {
+        unsafe {
+            gl::BindFramebuffer(target, self.name);
+        }
+    }
+}
+
+impl Drop for Framebuffer {
+    fn drop(&mut self) {
+        let name = self.name;
+        self.context.with_current(|| unsafe {
+            gl::DeleteFramebuffers(1, &name);
+        });
+    }
+}
+
+/// An owned renderbuffer object.
+#[derive(Debug)]
+pub struct Renderbuffer {
+    name: GLuint,
+    context: ContextHandle,
+}
+
+impl Renderbuffer {
+    /// Generate a new renderbuffer object.
+    pub fn new(context: &GLState) -> Renderbuffer {
+        let mut name = 0;
+        unsafe {
+            gl::GenRenderbuffers(1, &mut name);
+        }
+        debug_assert!(name > 0);
+
+        Renderbuffer { name: name, context: context.handle() }
+    }
+
+    /// The GL name of the renderbuffer.
+    #[inline]
+    pub fn name(&self) -> GLuint {
+        self.name
+    }
+
+    #[inline]
+    pub fn bind(&self) {
+        unsafe {
+            gl::BindRenderbuffer(gl::RENDERBUFFER, self.name);
+        }
+    }
+}
+
+impl Drop for Renderbuffer {
+    fn drop(&mut self) {
+        let name = self.name;
+        self.context.with_current(|| unsafe {
+            gl::DeleteRenderbuffers(1, &name);
+        });
+    }
+}
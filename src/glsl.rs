@@ -0,0 +1,399 @@
+/*
+ *  GB-Prototype is a renderer prototype demo.
+ *  Copyright (C) 2018,2019,2020  Christopher Blanchard
+ *
+ *  This program is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  This program is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+#![allow(dead_code)]
+//! The types of GLSL shader variables, as reported by shader reflection, along with
+//! their sizes in the std140 and std430 interface block layouts.
+use crate::error::Error;
+use crate::gl::types::GLenum;
+
+use std::fmt;
+
+
+/// The scalar type that a GLSL type is built from.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum BaseType {
+    Float,
+    Double,
+    Int,
+    UnsignedInt,
+    Bool,
+}
+
+impl BaseType {
+    /// The size in bytes of one component. A GLSL `bool` occupies four bytes both
+    /// in interface blocks and when set with `glUniform*`.
+    #[inline]
+    pub fn size(self) -> usize {
+        match self {
+            BaseType::Double => 8,
+            _ => 4,
+        }
+    }
+}
+
+/// The dimensionality of the texture behind a sampler or an image.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TextureDim {
+    D1,
+    D2,
+    D3,
+    Cube,
+    Rect,
+    Buffer,
+    D1Array,
+    D2Array,
+    CubeArray,
+    D2Multisample,
+    D2MultisampleArray,
+}
+
+/// The type of a shader attribute or uniform.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum GlslType {
+    Scalar(BaseType),
+    /// A vector with 2, 3 or 4 components.
+    Vector(BaseType, u32),
+    /// A float or double matrix with a number of columns and rows, in that order.
+    Matrix(BaseType, u32, u32),
+    /// A texture sampler. `sampled` is the type of the values a lookup returns.
+    Sampler { dim: TextureDim, sampled: BaseType, shadow: bool },
+    /// An image for load and store operations.
+    Image { dim: TextureDim, sampled: BaseType },
+    AtomicCounter,
+}
+
+/// Every GLSL type that reflection can report, with its GL enum and its name in GLSL.
+/// The enums are spelled out because the OpenGL ES bindings lack most of them.
+const GLSL_TYPES: [(GLenum, &str, GlslType); 112] = [
+    (0x1406, "float", GlslType::Scalar(BaseType::Float)),
+    (0x8B50, "vec2", GlslType::Vector(BaseType::Float, 2)),
+    (0x8B51, "vec3", GlslType::Vector(BaseType::Float, 3)),
+    (0x8B52, "vec4", GlslType::Vector(BaseType::Float, 4)),
+    (0x140A, "double", GlslType::Scalar(BaseType::Double)),
+    (0x8FFC, "dvec2", GlslType::Vector(BaseType::Double, 2)),
+    (0x8FFD, "dvec3", GlslType::Vector(BaseType::Double, 3)),
+    (0x8FFE, "dvec4", GlslType::Vector(BaseType::Double, 4)),
+    (0x1404, "int", GlslType::Scalar(BaseType::Int)),
+    (0x8B53, "ivec2", GlslType::Vector(BaseType::Int, 2)),
+    (0x8B54, "ivec3", GlslType::Vector(BaseType::Int, 3)),
+    (0x8B55, "ivec4", GlslType::Vector(BaseType::Int, 4)),
+    (0x1405, "uint", GlslType::Scalar(BaseType::UnsignedInt)),
+    (0x8DC6, "uvec2", GlslType::Vector(BaseType::UnsignedInt, 2)),
+    (0x8DC7, "uvec3", GlslType::Vector(BaseType::UnsignedInt, 3)),
+    (0x8DC8, "uvec4", GlslType::Vector(BaseType::UnsignedInt, 4)),
+    (0x8B56, "bool", GlslType::Scalar(BaseType::Bool)),
+    (0x8B57, "bvec2", GlslType::Vector(BaseType::Bool, 2)),
+    (0x8B58, "bvec3", GlslType::Vector(BaseType::Bool, 3)),
+    (0x8B59, "bvec4", GlslType::Vector(BaseType::Bool, 4)),
+    (0x8B5A, "mat2", GlslType::Matrix(BaseType::Float, 2, 2)),
+    (0x8B5B, "mat3", GlslType::Matrix(BaseType::Float, 3, 3)),
+    (0x8B5C, "mat4", GlslType::Matrix(BaseType::Float, 4, 4)),
+    (0x8B65, "mat2x3", GlslType::Matrix(BaseType::Float, 2, 3)),
+    (0x8B66, "mat2x4", GlslType::Matrix(BaseType::Float, 2, 4)),
+    (0x8B67, "mat3x2", GlslType::Matrix(BaseType::Float, 3, 2)),
+    (0x8B68, "mat3x4", GlslType::Matrix(BaseType::Float, 3, 4)),
+    (0x8B69, "mat4x2", GlslType::Matrix(BaseType::Float, 4, 2)),
+    (0x8B6A, "mat4x3", GlslType::Matrix(BaseType::Float, 4, 3)),
+    (0x8F46, "dmat2", GlslType::Matrix(BaseType::Double, 2, 2)),
+    (0x8F47, "dmat3", GlslType::Matrix(BaseType::Double, 3, 3)),
+    (0x8F48, "dmat4", GlslType::Matrix(BaseType::Double, 4, 4)),
+    (0x8F49, "dmat2x3", GlslType::Matrix(BaseType::Double, 2, 3)),
+    (0x8F4A, "dmat2x4", GlslType::Matrix(BaseType::Double, 2, 4)),
+    (0x8F4B, "dmat3x2", GlslType::Matrix(BaseType::Double, 3, 2)),
+    (0x8F4C, "dmat3x4", GlslType::Matrix(BaseType::Double, 3, 4)),
+    (0x8F4D, "dmat4x2", GlslType::Matrix(BaseType::Double, 4, 2)),
+    (0x8F4E, "dmat4x3", GlslType::Matrix(BaseType::Double, 4, 3)),
+    (0x8B5D, "sampler1D", GlslType::Sampler { dim: TextureDim::D1, sampled: BaseType::Float, shadow: false }),
+    (0x8B5E, "sampler2D", GlslType::Sampler { dim: TextureDim::D2, sampled: BaseType::Float, shadow: false }),
+    (0x8B5F, "sampler3D", GlslType::Sampler { dim: TextureDim::D3, sampled: BaseType::Float, shadow: false }),
+    (0x8B60, "samplerCube", GlslType::Sampler { dim: TextureDim::Cube, sampled: BaseType::Float, shadow: false }),
+    (0x8B63, "sampler2DRect", GlslType::Sampler { dim: TextureDim::Rect, sampled: BaseType::Float, shadow: false }),
+    (0x8DC2, "samplerBuffer", GlslType::Sampler { dim: TextureDim::Buffer, sampled: BaseType::Float, shadow: false }),
+    (0x8DC0, "sampler1DArray", GlslType::Sampler { dim: TextureDim::D1Array, sampled: BaseType::Float, shadow: false }),
+    (0x8DC1, "sampler2DArray", GlslType::Sampler { dim: TextureDim::D2Array, sampled: BaseType::Float, shadow: false }),
+    (0x900C, "samplerCubeArray", GlslType::Sampler { dim: TextureDim::CubeArray, sampled: BaseType::Float, shadow: false }),
+    (0x9108, "sampler2DMS", GlslType::Sampler { dim: TextureDim::D2Multisample, sampled: BaseType::Float, shadow: false }),
+    (0x910B, "sampler2DMSArray", GlslType::Sampler { dim: TextureDim::D2MultisampleArray, sampled: BaseType::Float, shadow: false }),
+    (0x8DC9, "isampler1D", GlslType::Sampler { dim: TextureDim::D1, sampled: BaseType::Int, shadow: false }),
+    (0x8DCA, "isampler2D", GlslType::Sampler { dim: TextureDim::D2, sampled: BaseType::Int, shadow: false }),
+    (0x8DCB, "isampler3D", GlslType::Sampler { dim: TextureDim::D3, sampled: BaseType::Int, shadow: false }),
+    (0x8DCC, "isamplerCube", GlslType::Sampler { dim: TextureDim::Cube, sampled: BaseType::Int, shadow: false }),
+    (0x8DCD, "isampler2DRect", GlslType::Sampler { dim: TextureDim::Rect, sampled: BaseType::Int, shadow: false }),
+    (0x8DD0, "isamplerBuffer", GlslType::Sampler { dim: TextureDim::Buffer, sampled: BaseType::Int, shadow: false }),
+    (0x8DCE, "isampler1DArray", GlslType::Sampler { dim: TextureDim::D1Array, sampled: BaseType::Int, shadow: false }),
+    (0x8DCF, "isampler2DArray", GlslType::Sampler { dim: TextureDim::D2Array, sampled: BaseType::Int, shadow: false }),
+    (0x900E, "isamplerCubeArray", GlslType::Sampler { dim: TextureDim::CubeArray, sampled: BaseType::Int, shadow: false }),
+    (0x9109, "isampler2DMS", GlslType::Sampler { dim: TextureDim::D2Multisample, sampled: BaseType::Int, shadow: false }),
+    (0x910C, "isampler2DMSArray", GlslType::Sampler { dim: TextureDim::D2MultisampleArray, sampled: BaseType::Int, shadow: false }),
+    (0x8DD1, "usampler1D", GlslType::Sampler { dim: TextureDim::D1, sampled: BaseType::UnsignedInt, shadow: false }),
+    (0x8DD2, "usampler2D", GlslType::Sampler { dim: TextureDim::D2, sampled: BaseType::UnsignedInt, shadow: false }),
+    (0x8DD3, "usampler3D", GlslType::Sampler { dim: TextureDim::D3, sampled: BaseType::UnsignedInt, shadow: false }),
+    (0x8DD4, "usamplerCube", GlslType::Sampler { dim: TextureDim::Cube, sampled: BaseType::UnsignedInt, shadow: false }),
+    (0x8DD5, "usampler2DRect", GlslType::Sampler { dim: TextureDim::Rect, sampled: BaseType::UnsignedInt, shadow: false }),
+    (0x8DD8, "usamplerBuffer", GlslType::Sampler { dim: TextureDim::Buffer, sampled: BaseType::UnsignedInt, shadow: false }),
+    (0x8DD6, "usampler1DArray", GlslType::Sampler { dim: TextureDim::D1Array, sampled: BaseType::UnsignedInt, shadow: false }),
+    (0x8DD7, "usampler2DArray", GlslType::Sampler { dim: TextureDim::D2Array, sampled: BaseType::UnsignedInt, shadow: false }),
+    (0x900F, "usamplerCubeArray", GlslType::Sampler { dim: TextureDim::CubeArray, sampled: BaseType::UnsignedInt, shadow: false }),
+    (0x910A, "usampler2DMS", GlslType::Sampler { dim: TextureDim::D2Multisample, sampled: BaseType::UnsignedInt, shadow: false }),
+    (0x910D, "usampler2DMSArray", GlslType::Sampler { dim: TextureDim::D2MultisampleArray, sampled: BaseType::UnsignedInt, shadow: false }),
+    (0x8B61, "sampler1DShadow", GlslType::Sampler { dim: TextureDim::D1, sampled: BaseType::Float, shadow: true }),
+    (0x8B62, "sampler2DShadow", GlslType::Sampler { dim: TextureDim::D2, sampled: BaseType::Float, shadow: true }),
+    (0x8DC5, "samplerCubeShadow", GlslType::Sampler { dim: TextureDim::Cube, sampled: BaseType::Float, shadow: true }),
+    (0x8B64, "sampler2DRectShadow", GlslType::Sampler { dim: TextureDim::Rect, sampled: BaseType::Float, shadow: true }),
+    (0x8DC3, "sampler1DArrayShadow", GlslType::Sampler { dim: TextureDim::D1Array, sampled: BaseType::Float, shadow: true }),
+    (0x8DC4, "sampler2DArrayShadow", GlslType::Sampler { dim: TextureDim::D2Array, sampled: BaseType::Float, shadow: true }),
+    (0x900D, "samplerCubeArrayShadow", GlslType::Sampler { dim: TextureDim::CubeArray, sampled: BaseType::Float, shadow: true }),
+    (0x904C, "image1D", GlslType::Image { dim: TextureDim::D1, sampled: BaseType::Float }),
+    (0x904D, "image2D", GlslType::Image { dim: TextureDim::D2, sampled: BaseType::Float }),
+    (0x904E, "image3D", GlslType::Image { dim: TextureDim::D3, sampled: BaseType::Float }),
+    (0x904F, "image2DRect", GlslType::Image { dim: TextureDim::Rect, sampled: BaseType::Float }),
+    (0x9050, "imageCube", GlslType::Image { dim: TextureDim::Cube, sampled: BaseType::Float }),
+    (0x9051, "imageBuffer", GlslType::Image { dim: TextureDim::Buffer, sampled: BaseType::Float }),
+    (0x9052, "image1DArray", GlslType::Image { dim: TextureDim::D1Array, sampled: BaseType::Float }),
+    (0x9053, "image2DArray", GlslType::Image { dim: TextureDim::D2Array, sampled: BaseType::Float }),
+    (0x9054, "imageCubeArray", GlslType::Image { dim: TextureDim::CubeArray, sampled: BaseType::Float }),
+    (0x9055, "image2DMS", GlslType::Image { dim: TextureDim::D2Multisample, sampled: BaseType::Float }),
+    (0x9056, "image2DMSArray", GlslType::Image { dim: TextureDim::D2MultisampleArray, sampled: BaseType::Float }),
+    (0x9057, "iimage1D", GlslType::Image { dim: TextureDim::D1, sampled: BaseType::Int }),
+    (0x9058, "iimage2D", GlslType::Image { dim: TextureDim::D2, sampled: BaseType::Int }),
+    (0x9059, "iimage3D", GlslType::Image { dim: TextureDim::D3, sampled: BaseType::Int }),
+    (0x905A, "iimage2DRect", GlslType::Image { dim: TextureDim::Rect, sampled: BaseType::Int }),
+    (0x905B, "iimageCube", GlslType::Image { dim: TextureDim::Cube, sampled: BaseType::Int }),
+    (0x905C, "iimageBuffer", GlslType::Image { dim: TextureDim::Buffer, sampled: BaseType::Int }),
+    (0x905D, "iimage1DArray", GlslType::Image { dim: TextureDim::D1Array, sampled: BaseType::Int }),
+    (0x905E, "iimage2DArray", GlslType::Image { dim: TextureDim::D2Array, sampled: BaseType::Int }),
+    (0x905F, "iimageCubeArray", GlslType::Image { dim: TextureDim::CubeArray, sampled: BaseType::Int }),
+    (0x9060, "iimage2DMS", GlslType::Image { dim: TextureDim::D2Multisample, sampled: BaseType::Int }),
+    (0x9061, "iimage2DMSArray", GlslType::Image { dim: TextureDim::D2MultisampleArray, sampled: BaseType::Int }),
+    (0x9062, "uimage1D", GlslType::Image { dim: TextureDim::D1, sampled: BaseType::UnsignedInt }),
+    (0x9063, "uimage2D", GlslType::Image { dim: TextureDim::D2, sampled: BaseType::UnsignedInt }),
+    (0x9064, "uimage3D", GlslType::Image { dim: TextureDim::D3, sampled: BaseType::UnsignedInt }),
+    (0x9065, "uimage2DRect", GlslType::Image { dim: TextureDim::Rect, sampled: BaseType::UnsignedInt }),
+    (0x9066, "uimageCube", GlslType::Image { dim: TextureDim::Cube, sampled: BaseType::UnsignedInt }),
+    (0x9067, "uimageBuffer", GlslType::Image { dim: TextureDim::Buffer, sampled: BaseType::UnsignedInt }),
+    (0x9068, "uimage1DArray", GlslType::Image { dim: TextureDim::D1Array, sampled: BaseType::UnsignedInt }),
+    (0x9069, "uimage2DArray", GlslType::Image { dim: TextureDim::D2Array, sampled: BaseType::UnsignedInt }),
+    (0x906A, "uimageCubeArray", GlslType::Image { dim: TextureDim::CubeArray, sampled: BaseType::UnsignedInt }),
+    (0x906B, "uimage2DMS", GlslType::Image { dim: TextureDim::D2Multisample, sampled: BaseType::UnsignedInt }),
+    (0x906C, "uimage2DMSArray", GlslType::Image { dim: TextureDim::D2MultisampleArray, sampled: BaseType::UnsignedInt }),
+    (0x92DB, "atomic_uint", GlslType::AtomicCounter),
+];
+
+/// Round `value` up to a multiple of `alignment`.
+#[inline]
+fn round_up(value: usize, alignment: usize) -> usize {
+    (value + alignment - 1) / alignment * alignment
+}
+
+impl GlslType {
+    /// Look up the type of a shader variable from the GL enum that reflection
+    /// returns, e.g. from `glGetActiveUniform`.
+    pub fn from_gl(gl_type: GLenum) -> Result<GlslType, Error> {
+        for &(value, _, ty) in GLSL_TYPES.iter() {
+            if value == gl_type {
+                return Ok(ty);
+            }
+        }
+
+        Err(Error::UnknownGlslType(gl_type))
+    }
+
+    /// The GL enum for the type. Returns `None` for combinations that GLSL does not
+    /// have, e.g. an integer shadow sampler.
+    pub fn to_gl(self) -> Option<GLenum> {
+        GLSL_TYPES.iter().find(|&&(_, _, ty)| ty == self).map(|&(value, _, _)| value)
+    }
+
+    /// The name of the type in GLSL source code.
+    pub fn name(self) -> Option<&'static str> {
+        GLSL_TYPES.iter().find(|&&(_, _, ty)| ty == self).map(|&(_, name, _)| name)
+    }
+
+    /// The scalar type of the components. For samplers and images this is the type
+    /// of the values they return, and atomic counters are unsigned integers.
+    pub fn base_type(self) -> BaseType {
+        match self {
+            GlslType::Scalar(base) => base,
+            GlslType::Vector(base, _) => base,
+            GlslType::Matrix(base, _, _) => base,
+            GlslType::Sampler { sampled, .. } => sampled,
+            GlslType::Image { sampled, .. } => sampled,
+            GlslType::AtomicCounter => BaseType::UnsignedInt,
+        }
+    }
+
+    /// The number of components. Opaque types count as one component.
+    pub fn component_count(self) -> usize {
+        match self {
+            GlslType::Vector(_, n) => n as usize,
+            GlslType::Matrix(_, columns, rows) => (columns * rows) as usize,
+            _ => 1,
+        }
+    }
+
+    /// Determine whether the type is opaque, i.e. a sampler, an image or an atomic
+    /// counter. Opaque types have no layout in a buffer.
+    #[inline]
+    pub fn is_opaque(self) -> bool {
+        match self {
+            GlslType::Sampler { .. } | GlslType::Image { .. } | GlslType::AtomicCounter => true,
+            _ => false,
+        }
+    }
+
+    /// The size in bytes of the tightly packed components, e.g. for a vertex
+    /// attribute or a `glUniform*` upload.
+    #[inline]
+    pub fn size(self) -> usize {
+        self.component_count() * self.base_type().size()
+    }
+
+    /// The base alignment of a vector. Three component vectors align like four
+    /// component vectors in both layouts.
+    #[inline]
+    fn vector_alignment(base: BaseType, n: u32) -> usize {
+        let n = if n == 3 { 4 } else { n as usize };
+        n * base.size()
+    }
+
+    /// Compute the size and alignment of the type in a block. A matrix is laid out
+    /// as an array of column vectors, and std140 rounds the stride of an array up
+    /// to the alignment of a `vec4`.
+    fn block_layout(self, std140: bool) -> Option<(usize, usize)> {
+        match self {
+            GlslType::Scalar(base) => Some((base.size(), base.size())),
+            GlslType::Vector(base, n) => {
+                Some((n as usize * base.size(), GlslType::vector_alignment(base, n)))
+            }
+            GlslType::Matrix(base, columns, rows) => {
+                let mut stride = GlslType::vector_alignment(base, rows);
+                if std140 {
+                    stride = round_up(stride, 16);
+                }
+                Some((columns as usize * stride, stride))
+            }
+            _ => None,
+        }
+    }
+
+    /// The size in bytes of the type in a std140 block. Opaque types have none.
+    #[inline]
+    pub fn std140_size(self) -> Option<usize> {
+        self.block_layout(true).map(|(size, _)| size)
+    }
+
+    /// The base alignment in bytes of the type in a std140 block.
+    #[inline]
+    pub fn std140_alignment(self) -> Option<usize> {
+        self.block_layout(true).map(|(_, alignment)| alignment)
+    }
+
+    /// The size in bytes of the type in a std430 block. Opaque types have none.
+    #[inline]
+    pub fn std430_size(self) -> Option<usize> {
+        self.block_layout(false).map(|(size, _)| size)
+    }
+
+    /// The base alignment in bytes of the type in a std430 block.
+    #[inline]
+    pub fn std430_alignment(self) -> Option<usize> {
+        self.block_layout(false).map(|(_, alignment)| alignment)
+    }
+}
+
+impl fmt::Display for GlslType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.name() {
+            Some(name) => write!(f, "{}", name),
+            None => write!(f, "{:?}", self),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    /// The std140 and std430 sizes and alignments of a type, in that order.
+    fn layouts(ty: GlslType) -> (Option<usize>, Option<usize>, Option<usize>, Option<usize>) {
+        (ty.std140_size(), ty.std140_alignment(), ty.std430_size(), ty.std430_alignment())
+    }
+
+    #[test]
+    fn test_vec3_layout() {
+        let vec3 = GlslType::from_gl(0x8B51).unwrap();
+        assert_eq!(vec3, GlslType::Vector(BaseType::Float, 3));
+        assert_eq!(vec3.size(), 12);
+        assert_eq!(layouts(vec3), (Some(12), Some(16), Some(12), Some(16)));
+    }
+
+    #[test]
+    fn test_mat3_layout() {
+        let mat3 = GlslType::from_gl(0x8B5B).unwrap();
+        assert_eq!(mat3.size(), 36);
+        assert_eq!(layouts(mat3), (Some(48), Some(16), Some(48), Some(16)));
+    }
+
+    #[test]
+    fn test_mat2_layout() {
+        // std430 does not round the column stride up to a vec4.
+        let mat2 = GlslType::Matrix(BaseType::Float, 2, 2);
+        assert_eq!(layouts(mat2), (Some(32), Some(16), Some(16), Some(8)));
+    }
+
+    #[test]
+    fn test_dmat4_layout() {
+        let dmat4 = GlslType::Matrix(BaseType::Double, 4, 4);
+        assert_eq!(dmat4.name(), Some("dmat4"));
+        assert_eq!(dmat4.size(), 128);
+        assert_eq!(layouts(dmat4), (Some(128), Some(32), Some(128), Some(32)));
+    }
+
+    #[test]
+    fn test_bool_layout() {
+        let bool_type = GlslType::from_gl(0x8B56).unwrap();
+        assert_eq!(bool_type.size(), 4);
+        assert_eq!(layouts(bool_type), (Some(4), Some(4), Some(4), Some(4)));
+    }
+
+    #[test]
+    fn test_opaque_types_have_no_layout() {
+        let sampler = GlslType::Sampler { dim: TextureDim::D2, sampled: BaseType::Float, shadow: false };
+        assert!(sampler.is_opaque());
+        assert_eq!(layouts(sampler), (None, None, None, None));
+        assert_eq!(layouts(GlslType::AtomicCounter), (None, None, None, None));
+    }
+
+    #[test]
+    fn test_gl_enum_round_trip() {
+        for &(value, name, ty) in GLSL_TYPES.iter() {
+            assert_eq!(GlslType::from_gl(value).unwrap(), ty);
+            assert_eq!(ty.to_gl(), Some(value));
+            assert_eq!(ty.name(), Some(name));
+        }
+    }
+
+    #[test]
+    fn test_unknown_gl_enum() {
+        match GlslType::from_gl(0x1234) {
+            Err(Error::UnknownGlslType(value)) => assert_eq!(value, 0x1234),
+            other => panic!("expected an unknown type, got {:?}", other.ok()),
+        }
+    }
+}
@@ -0,0 +1,1325 @@
+/*
+ *  GB-Prototype is a renderer prototype demo.
+ *  Copyright (C) 2018,2019,2020  Christopher Blanchard
+ *
+ *  This program is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  This program is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+#![allow(dead_code)]
+//! An importer for glTF 2.0 scenes in both the `.gltf` and `.glb` forms. It reads
+//! the meshes, the metallic-roughness materials with their textures, the node
+//! hierarchy, and the skins and animations of a file.
+use crate::animation::{
+    AnimationClip, Channel, ChannelValues, Interpolation, Joint, Mat4, Skeleton, SkeletonError, Transform,
+    MAT4_IDENTITY, mat4_mul,
+};
+use crate::gl;
+use crate::gl::types::GLenum;
+use crate::json;
+use crate::json::{JsonError, JsonValue};
+use crate::mesh::{AttributeData, Indices, Mesh, Semantic};
+
+use std::error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::warn;
+
+
+/// The magic number at the start of a binary glTF file, `glTF`.
+const GLB_MAGIC: u32 = 0x4654_6C67;
+/// The chunk type of the JSON chunk of a binary glTF file.
+const GLB_CHUNK_JSON: u32 = 0x4E4F_534A;
+/// The chunk type of the binary buffer chunk of a binary glTF file.
+const GLB_CHUNK_BIN: u32 = 0x004E_4942;
+
+// Accessor component types.
+const COMPONENT_I8: usize = 5120;
+const COMPONENT_U8: usize = 5121;
+const COMPONENT_I16: usize = 5122;
+const COMPONENT_U16: usize = 5123;
+const COMPONENT_U32: usize = 5125;
+const COMPONENT_F32: usize = 5126;
+
+// Primitive topologies.
+const MODE_TRIANGLES: usize = 4;
+const MODE_TRIANGLE_STRIP: usize = 5;
+const MODE_TRIANGLE_FAN: usize = 6;
+
+
+#[derive(Clone, Debug)]
+pub enum GltfError {
+    FileNotFound(String),
+    CouldNotParseJson(String, JsonError),
+    InvalidGlb(String, String),
+    BufferNotFound(String),
+    InvalidGltf(String, String),
+}
+
+impl fmt::Display for GltfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &GltfError::FileNotFound(ref file_name) => {
+                write!(f, "Could not open the glTF file for reading: {}", file_name)
+            }
+            &GltfError::CouldNotParseJson(ref file_name, ref e) => {
+                write!(f, "The glTF file {} is not valid JSON. {}", file_name, e)
+            }
+            &GltfError::InvalidGlb(ref file_name, ref reason) => {
+                write!(f, "The binary glTF file {} is malformed: {}", file_name, reason)
+            }
+            &GltfError::BufferNotFound(ref uri) => {
+                write!(f, "Could not read the glTF buffer {}", uri)
+            }
+            &GltfError::InvalidGltf(ref file_name, ref reason) => {
+                write!(f, "The glTF file {} is invalid: {}", file_name, reason)
+            }
+        }
+    }
+}
+
+impl error::Error for GltfError {}
+
+/// Where the pixels of a texture come from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TextureSource {
+    /// An image file. The path is relative to the working directory.
+    File(PathBuf),
+    /// An encoded image stored inside the glTF file, e.g. a PNG in a buffer view.
+    Embedded { data: Vec<u8>, mime_type: String },
+}
+
+/// A texture and the way it is sampled. The filters and wrap modes are GL enums.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GltfTexture {
+    pub name: String,
+    pub source: TextureSource,
+    pub mag_filter: GLenum,
+    pub min_filter: GLenum,
+    pub wrap_s: GLenum,
+    pub wrap_t: GLenum,
+}
+
+/// A reference from a material to a texture, and the texture coordinate set it uses.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TextureRef {
+    pub texture: usize,
+    pub tex_coord: u32,
+}
+
+/// How a material treats the alpha channel of its base color.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum AlphaMode {
+    Opaque,
+    /// Discard fragments with an alpha below the cutoff.
+    Mask(f32),
+    Blend,
+}
+
+/// A physically based material in the metallic-roughness model.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PbrMaterial {
+    pub name: String,
+    pub base_color_factor: [f32; 4],
+    pub base_color_texture: Option<TextureRef>,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    pub metallic_roughness_texture: Option<TextureRef>,
+    pub normal_texture: Option<TextureRef>,
+    pub normal_scale: f32,
+    pub occlusion_texture: Option<TextureRef>,
+    pub occlusion_strength: f32,
+    pub emissive_texture: Option<TextureRef>,
+    pub emissive_factor: [f32; 3],
+    pub alpha_mode: AlphaMode,
+    pub double_sided: bool,
+}
+
+/// A part of a mesh drawn with a single material.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GltfPrimitive {
+    pub mesh: Mesh,
+    pub material: Option<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GltfMesh {
+    pub name: String,
+    pub primitives: Vec<GltfPrimitive>,
+}
+
+/// A node of the scene hierarchy. The transform is a column major matrix relative
+/// to the parent node.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SceneNode {
+    pub name: String,
+    pub transform: [[f32; 4]; 4],
+    /// The transform split into the translation, rotation and scale that animations
+    /// target.
+    pub local: Transform,
+    pub mesh: Option<usize>,
+    /// The skin that deforms the mesh of the node.
+    pub skin: Option<usize>,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+}
+
+/// The joints of a skinned mesh. The joints are node indices, in the order the
+/// joint attributes of the mesh refer to them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GltfSkin {
+    pub name: String,
+    pub joints: Vec<usize>,
+    pub inverse_bind_matrices: Vec<Mat4>,
+}
+
+/// An animation of the nodes of a scene. The channel targets are node indices; use
+/// `GltfScene::animation_clip` to animate the joints of a skin with it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GltfAnimation {
+    pub name: String,
+    pub channels: Vec<Channel>,
+}
+
+/// The contents of a glTF file. Nodes, meshes, materials and textures refer to each
+/// other by their index in these arrays.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GltfScene {
+    pub meshes: Vec<GltfMesh>,
+    pub materials: Vec<PbrMaterial>,
+    pub textures: Vec<GltfTexture>,
+    pub nodes: Vec<SceneNode>,
+    pub skins: Vec<GltfSkin>,
+    pub animations: Vec<GltfAnimation>,
+    /// The root nodes of the default scene.
+    pub roots: Vec<usize>,
+}
+
+impl GltfScene {
+    /// The transform from the space of a node to the space of the scene.
+    pub fn world_transform(&self, node: usize) -> [[f32; 4]; 4] {
+        let mut transform = self.nodes[node].transform;
+        let mut parent = self.nodes[node].parent;
+        while let Some(index) = parent {
+            transform = mat4_mul(&self.nodes[index].transform, &transform);
+            parent = self.nodes[index].parent;
+        }
+
+        transform
+    }
+
+    /// Build the skeleton of a skin. Joints whose node has no parent among the
+    /// joints hang off the scene transform of the parent of the first such node.
+    pub fn skeleton(&self, skin: usize) -> Result<Skeleton, SkeletonError> {
+        let skin = match self.skins.get(skin) {
+            Some(val) => val,
+            None => return Err(SkeletonError::SkinNotFound(skin)),
+        };
+        let joints = skin.joints.iter().enumerate().map(|(i, &node)| {
+            let parent = self.nodes[node].parent.and_then(|parent| skin.joints.iter().position(|&j| j == parent));
+            Joint {
+                name: self.nodes[node].name.clone(),
+                parent: parent,
+                rest: self.nodes[node].local,
+                inverse_bind: skin.inverse_bind_matrices.get(i).cloned().unwrap_or(MAT4_IDENTITY),
+            }
+        }).collect::<Vec<Joint>>();
+        let root_parent = joints.iter().zip(skin.joints.iter())
+            .filter(|&(joint, _)| joint.parent.is_none())
+            .filter_map(|(_, &node)| self.nodes[node].parent)
+            .next();
+        let mut skeleton = Skeleton::new(joints)?;
+        if let Some(node) = root_parent {
+            skeleton.root_transform = self.world_transform(node);
+        }
+
+        Ok(skeleton)
+    }
+
+    /// Turn an animation into a clip for the skeleton of a skin. Channels that
+    /// animate nodes outside the skin are dropped. Returns `None` when the
+    /// animation or the skin does not exist.
+    pub fn animation_clip(&self, animation: usize, skin: usize) -> Option<AnimationClip> {
+        let animation = self.animations.get(animation)?;
+        let joints = &self.skins.get(skin)?.joints;
+        let channels = animation.channels.iter().filter_map(|channel| {
+            let joint = joints.iter().position(|&node| node == channel.target)?;
+            Some(Channel { target: joint, ..channel.clone() })
+        }).collect();
+
+        Some(AnimationClip::new(&animation.name, channels))
+    }
+}
+
+/// Decode standard base64 with optional padding.
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    let mut accumulator: u32 = 0;
+    let mut bits = 0;
+    for c in text.bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' => break,
+            _ => return None,
+        };
+        accumulator = (accumulator << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((accumulator >> bits) as u8);
+            accumulator &= (1 << bits) - 1;
+        }
+    }
+
+    Some(bytes)
+}
+
+/// Split a data URI into its media type and decoded contents.
+fn decode_data_uri(uri: &str) -> Option<(String, Vec<u8>)> {
+    let rest = uri.get(5..)?;
+    if !uri.starts_with("data:") {
+        return None;
+    }
+    let comma = rest.find(',')?;
+    let (header, data) = (&rest[..comma], &rest[comma + 1..]);
+    if !header.ends_with(";base64") {
+        return None;
+    }
+    let mime_type = &header[..header.len() - ";base64".len()];
+
+    Some((String::from(mime_type), decode_base64(data)?))
+}
+
+#[inline]
+fn read_u32_le(bytes: &[u8], offset: usize) -> u32 {
+    let mut word = [0; 4];
+    word.copy_from_slice(&bytes[offset..offset + 4]);
+    u32::from_le_bytes(word)
+}
+
+/// Split a binary glTF file into its JSON text and its binary chunk.
+fn parse_glb(bytes: &[u8], file_name: &str) -> Result<(String, Option<Vec<u8>>), GltfError> {
+    let error = |reason: &str| GltfError::InvalidGlb(String::from(file_name), String::from(reason));
+    if bytes.len() < 20 || read_u32_le(bytes, 0) != GLB_MAGIC {
+        return Err(error("missing the glTF header"));
+    }
+    if read_u32_le(bytes, 4) != 2 {
+        return Err(error("only version 2 binary glTF files are supported"));
+    }
+    let length = usize::min(read_u32_le(bytes, 8) as usize, bytes.len());
+
+    let mut json_text = None;
+    let mut bin = None;
+    let mut offset = 12;
+    while offset + 8 <= length {
+        let chunk_length = read_u32_le(bytes, offset) as usize;
+        let chunk_type = read_u32_le(bytes, offset + 4);
+        let start = offset + 8;
+        let end = start + chunk_length;
+        if end > length {
+            return Err(error("a chunk runs past the end of the file"));
+        }
+        match chunk_type {
+            GLB_CHUNK_JSON => {
+                let text = String::from_utf8(bytes[start..end].to_vec()).map_err(|_| error("the JSON chunk is not UTF-8"))?;
+                json_text = Some(text);
+            }
+            GLB_CHUNK_BIN if bin.is_none() => bin = Some(bytes[start..end].to_vec()),
+            // Readers must skip chunks of unknown types.
+            _ => {}
+        }
+        // Chunks are padded to four byte boundaries.
+        offset = end + (4 - chunk_length % 4) % 4;
+    }
+
+    match json_text {
+        Some(text) => Ok((text, bin)),
+        None => Err(error("missing the JSON chunk")),
+    }
+}
+
+/// The state of an import in progress.
+struct Importer<'a> {
+    file_name: &'a str,
+    dir: &'a Path,
+    document: JsonValue,
+    buffers: Vec<Vec<u8>>,
+}
+
+/// Get an array member of an object, or an empty array.
+fn array<'a>(value: &'a JsonValue, key: &str) -> &'a [JsonValue] {
+    value.get(key).and_then(|v| v.as_array()).unwrap_or(&[])
+}
+
+fn number_or(value: &JsonValue, key: &str, default: f32) -> f32 {
+    value.get(key).and_then(|v| v.as_f64()).map_or(default, |v| v as f32)
+}
+
+/// Read a fixed size array of numbers, e.g. a color factor.
+fn floats_or<A: Default + AsMut<[f32]>>(value: &JsonValue, key: &str, default: A) -> A {
+    let values = match value.get(key).and_then(|v| v.as_array()) {
+        Some(val) => val,
+        None => return default,
+    };
+    let mut result = A::default();
+    if values.len() != result.as_mut().len() {
+        return default;
+    }
+    for (dst, src) in result.as_mut().iter_mut().zip(values.iter()) {
+        *dst = src.as_f64().unwrap_or(0.0) as f32;
+    }
+
+    result
+}
+
+impl<'a> Importer<'a> {
+    fn error(&self, reason: String) -> GltfError {
+        GltfError::InvalidGltf(String::from(self.file_name), reason)
+    }
+
+    fn load_buffers(&mut self, mut bin: Option<Vec<u8>>) -> Result<(), GltfError> {
+        let buffers = array(&self.document, "buffers").to_vec();
+        for (i, buffer) in buffers.iter().enumerate() {
+            let data = match buffer.get("uri").and_then(|v| v.as_str()) {
+                Some(uri) if uri.starts_with("data:") => match decode_data_uri(uri) {
+                    Some((_, data)) => data,
+                    None => return Err(self.error(format!("buffer {} has an invalid data URI", i))),
+                },
+                Some(uri) => {
+                    let path = self.dir.join(uri);
+                    match fs::read(&path) {
+                        Ok(val) => val,
+                        Err(_) => return Err(GltfError::BufferNotFound(path.display().to_string())),
+                    }
+                }
+                // A buffer without a URI is the binary chunk of a binary glTF file.
+                None if i == 0 => match bin.take() {
+                    Some(val) => val,
+                    None => return Err(self.error(String::from("buffer 0 has no URI and there is no binary chunk"))),
+                },
+                None => return Err(self.error(format!("buffer {} has no URI", i))),
+            };
+            let byte_length = buffer.get("byteLength").and_then(|v| v.as_usize()).unwrap_or(0);
+            if data.len() < byte_length {
+                return Err(self.error(format!("buffer {} is shorter than its byteLength", i)));
+            }
+            self.buffers.push(data);
+        }
+
+        Ok(())
+    }
+
+    /// The bytes of a buffer view and its byte stride, if it has one.
+    fn buffer_view(&self, index: usize) -> Result<(&[u8], Option<usize>), GltfError> {
+        let view = match array(&self.document, "bufferViews").get(index) {
+            Some(val) => val,
+            None => return Err(self.error(format!("buffer view {} does not exist", index))),
+        };
+        let buffer = view.get("buffer").and_then(|v| v.as_usize());
+        let data = match buffer.and_then(|buffer| self.buffers.get(buffer)) {
+            Some(val) => val,
+            None => return Err(self.error(format!("buffer view {} has an invalid buffer", index))),
+        };
+        let offset = view.get("byteOffset").and_then(|v| v.as_usize()).unwrap_or(0);
+        let length = view.get("byteLength").and_then(|v| v.as_usize()).unwrap_or(0);
+        if offset + length > data.len() {
+            return Err(self.error(format!("buffer view {} runs past the end of its buffer", index)));
+        }
+        let stride = view.get("byteStride").and_then(|v| v.as_usize());
+
+        Ok((&data[offset..offset + length], stride))
+    }
+
+    /// Read the components of `count` elements from a buffer view. Normalized
+    /// integers are mapped to `[0, 1]` or `[-1, 1]`.
+    fn read_elements(
+        &self, view: usize, offset: usize, count: usize,
+        component_type: usize, components: usize, normalized: bool) -> Result<Vec<f64>, GltfError> {
+
+        let (bytes, view_stride) = self.buffer_view(view)?;
+        let component_size = match component_type {
+            COMPONENT_I8 | COMPONENT_U8 => 1,
+            COMPONENT_I16 | COMPONENT_U16 => 2,
+            COMPONENT_U32 | COMPONENT_F32 => 4,
+            other => return Err(self.error(format!("unknown component type {}", other))),
+        };
+        let element_size = component_size * components;
+        let stride = view_stride.unwrap_or(element_size);
+        if count > 0 && offset + stride * (count - 1) + element_size > bytes.len() {
+            return Err(self.error(format!("an accessor of buffer view {} runs past its end", view)));
+        }
+
+        let mut values = Vec::with_capacity(count * components);
+        for element in 0..count {
+            for component in 0..components {
+                let at = offset + element * stride + component * component_size;
+                let value = match component_type {
+                    COMPONENT_I8 => {
+                        let v = bytes[at] as i8 as f64;
+                        if normalized { f64::max(v / 127.0, -1.0) } else { v }
+                    }
+                    COMPONENT_U8 => {
+                        let v = bytes[at] as f64;
+                        if normalized { v / 255.0 } else { v }
+                    }
+                    COMPONENT_I16 => {
+                        let v = i16::from_le_bytes([bytes[at], bytes[at + 1]]) as f64;
+                        if normalized { f64::max(v / 32767.0, -1.0) } else { v }
+                    }
+                    COMPONENT_U16 => {
+                        let v = u16::from_le_bytes([bytes[at], bytes[at + 1]]) as f64;
+                        if normalized { v / 65535.0 } else { v }
+                    }
+                    COMPONENT_U32 => read_u32_le(bytes, at) as f64,
+                    _ => f32::from_bits(read_u32_le(bytes, at)) as f64,
+                };
+                values.push(value);
+            }
+        }
+
+        Ok(values)
+    }
+
+    /// Read an accessor. Returns the values of its components in element order and
+    /// the number of components per element.
+    fn read_accessor(&self, index: usize) -> Result<(Vec<f64>, usize), GltfError> {
+        let accessor = match array(&self.document, "accessors").get(index) {
+            Some(val) => val,
+            None => return Err(self.error(format!("accessor {} does not exist", index))),
+        };
+        let count = accessor.get("count").and_then(|v| v.as_usize()).unwrap_or(0);
+        let component_type = accessor.get("componentType").and_then(|v| v.as_usize()).unwrap_or(0);
+        let normalized = accessor.get("normalized").and_then(|v| v.as_bool()).unwrap_or(false);
+        let components = match accessor.get("type").and_then(|v| v.as_str()) {
+            Some("SCALAR") => 1,
+            Some("VEC2") => 2,
+            Some("VEC3") => 3,
+            Some("VEC4") | Some("MAT2") => 4,
+            Some("MAT3") => 9,
+            Some("MAT4") => 16,
+            _ => return Err(self.error(format!("accessor {} has an unknown type", index))),
+        };
+
+        // An accessor without a buffer view is all zeros, unless sparse values
+        // replace some of them.
+        let mut values = match accessor.get("bufferView").and_then(|v| v.as_usize()) {
+            Some(view) => {
+                let offset = accessor.get("byteOffset").and_then(|v| v.as_usize()).unwrap_or(0);
+                self.read_elements(view, offset, count, component_type, components, normalized)?
+            }
+            None => vec![0.0; count * components],
+        };
+
+        if let Some(sparse) = accessor.get("sparse") {
+            let sparse_count = sparse.get("count").and_then(|v| v.as_usize()).unwrap_or(0);
+            let (indices, values_desc) = match (sparse.get("indices"), sparse.get("values")) {
+                (Some(indices), Some(values_desc)) => (indices, values_desc),
+                _ => return Err(self.error(format!("sparse accessor {} is incomplete", index))),
+            };
+            let index_view = indices.get("bufferView").and_then(|v| v.as_usize()).unwrap_or(usize::MAX);
+            let index_offset = indices.get("byteOffset").and_then(|v| v.as_usize()).unwrap_or(0);
+            let index_type = indices.get("componentType").and_then(|v| v.as_usize()).unwrap_or(0);
+            let targets = self.read_elements(index_view, index_offset, sparse_count, index_type, 1, false)?;
+            let value_view = values_desc.get("bufferView").and_then(|v| v.as_usize()).unwrap_or(usize::MAX);
+            let value_offset = values_desc.get("byteOffset").and_then(|v| v.as_usize()).unwrap_or(0);
+            let replacements = self.read_elements(
+                value_view, value_offset, sparse_count, component_type, components, normalized
+            )?;
+            for (i, &target) in targets.iter().enumerate() {
+                let target = target as usize;
+                if target >= count {
+                    return Err(self.error(format!("sparse accessor {} has an index out of range", index)));
+                }
+                let src = &replacements[i * components..(i + 1) * components];
+                values[target * components..(target + 1) * components].copy_from_slice(src);
+            }
+        }
+
+        Ok((values, components))
+    }
+
+    /// Read a vertex attribute and convert it to the mesh format for its semantic.
+    fn read_attribute(&self, name: &str, accessor: usize) -> Result<(Semantic, AttributeData), GltfError> {
+        let (values, components) = self.read_accessor(accessor)?;
+        let set = |prefix: &str| {
+            if name.starts_with(prefix) {
+                name[prefix.len()..].parse::<u32>().ok()
+            } else {
+                None
+            }
+        };
+        let f32x2 = || values.chunks(components).map(|c| [c[0] as f32, c[1] as f32]).collect();
+        let f32x3 = || values.chunks(components).map(|c| [c[0] as f32, c[1] as f32, c[2] as f32]).collect();
+        let f32x4 = || values.chunks(components).map(|c| {
+            [c[0] as f32, c[1] as f32, c[2] as f32, if components > 3 { c[3] as f32 } else { 1.0 }]
+        }).collect();
+        let check = |expected: &[usize]| {
+            if expected.contains(&components) {
+                Ok(())
+            } else {
+                Err(self.error(format!("attribute {} has {} components", name, components)))
+            }
+        };
+
+        if name == "POSITION" {
+            check(&[3])?;
+            Ok((Semantic::Position, AttributeData::F32x3(f32x3())))
+        } else if name == "NORMAL" {
+            check(&[3])?;
+            Ok((Semantic::Normal, AttributeData::F32x3(f32x3())))
+        } else if name == "TANGENT" {
+            check(&[4])?;
+            Ok((Semantic::Tangent, AttributeData::F32x4(f32x4())))
+        } else if let Some(n) = set("TEXCOORD_") {
+            check(&[2])?;
+            Ok((Semantic::TexCoord(n), AttributeData::F32x2(f32x2())))
+        } else if let Some(n) = set("COLOR_") {
+            // Colors without alpha are opaque.
+            check(&[3, 4])?;
+            Ok((Semantic::Color(n), AttributeData::F32x4(f32x4())))
+        } else if let Some(n) = set("JOINTS_") {
+            check(&[4])?;
+            let joints = values.chunks(4).map(|c| [c[0] as u16, c[1] as u16, c[2] as u16, c[3] as u16]).collect();
+            Ok((Semantic::Joints(n), AttributeData::U16x4(joints)))
+        } else if let Some(n) = set("WEIGHTS_") {
+            check(&[4])?;
+            Ok((Semantic::Weights(n), AttributeData::F32x4(f32x4())))
+        } else {
+            // Application specific attributes start with an underscore.
+            let data = match components {
+                1 => AttributeData::F32x1(values.iter().map(|&v| v as f32).collect()),
+                2 => AttributeData::F32x2(f32x2()),
+                3 => AttributeData::F32x3(f32x3()),
+                4 => AttributeData::F32x4(f32x4()),
+                _ => return Err(self.error(format!("attribute {} has {} components", name, components))),
+            };
+            Ok((Semantic::Custom(String::from(name)), data))
+        }
+    }
+
+    fn load_primitive(&self, mesh_index: usize, primitive: &JsonValue) -> Result<Option<GltfPrimitive>, GltfError> {
+        let mode = primitive.get("mode").and_then(|v| v.as_usize()).unwrap_or(MODE_TRIANGLES);
+        if mode != MODE_TRIANGLES && mode != MODE_TRIANGLE_STRIP && mode != MODE_TRIANGLE_FAN {
+            warn!("{}: skipping a primitive of mesh {} with point or line mode {}", self.file_name, mesh_index, mode);
+            return Ok(None);
+        }
+
+        let attributes = match primitive.get("attributes").and_then(|v| v.as_object()) {
+            Some(val) => val,
+            None => return Err(self.error(format!("a primitive of mesh {} has no attributes", mesh_index))),
+        };
+        let mut vertex_attributes = vec![];
+        for &(ref name, ref accessor) in attributes.iter() {
+            let accessor = match accessor.as_usize() {
+                Some(val) => val,
+                None => return Err(self.error(format!("attribute {} has an invalid accessor", name))),
+            };
+            let (semantic, data) = self.read_attribute(name, accessor)?;
+            vertex_attributes.push((name, semantic, data));
+        }
+        let position = vertex_attributes.iter().position(|&(_, ref semantic, _)| semantic == &Semantic::Position);
+        let mut mesh = match position.map(|i| vertex_attributes.remove(i)) {
+            Some((_, _, AttributeData::F32x3(points))) => Mesh::from_points(&points),
+            _ => {
+                warn!("{}: skipping a primitive of mesh {} without positions", self.file_name, mesh_index);
+                return Ok(None);
+            }
+        };
+        let vertex_count = mesh.len();
+        for (name, semantic, data) in vertex_attributes.into_iter() {
+            if data.len() != vertex_count {
+                return Err(self.error(format!("attribute {} of mesh {} has the wrong length", name, mesh_index)));
+            }
+            mesh.set_attribute(semantic, data);
+        }
+
+        let indices: Vec<u32> = match primitive.get("indices").and_then(|v| v.as_usize()) {
+            Some(accessor) => {
+                let (values, _) = self.read_accessor(accessor)?;
+                values.iter().map(|&v| v as u32).collect()
+            }
+            None => (0..vertex_count as u32).collect(),
+        };
+        if indices.iter().any(|&i| i as usize >= vertex_count) {
+            return Err(self.error(format!("a primitive of mesh {} has an index out of range", mesh_index)));
+        }
+
+        // Turn strips and fans into lists of triangles. Every other triangle of a
+        // strip swaps two vertices to keep the winding consistent.
+        let triangles = match mode {
+            MODE_TRIANGLE_STRIP => {
+                let mut triangles = vec![];
+                for i in 2..indices.len() {
+                    if i % 2 == 0 {
+                        triangles.extend_from_slice(&[indices[i - 2], indices[i - 1], indices[i]]);
+                    } else {
+                        triangles.extend_from_slice(&[indices[i - 1], indices[i - 2], indices[i]]);
+                    }
+                }
+                triangles
+            }
+            MODE_TRIANGLE_FAN => {
+                let mut triangles = vec![];
+                for i in 2..indices.len() {
+                    triangles.extend_from_slice(&[indices[0], indices[i - 1], indices[i]]);
+                }
+                triangles
+            }
+            _ => indices,
+        };
+        mesh.indices = Some(Indices::new(&triangles));
+
+        Ok(Some(GltfPrimitive {
+            mesh: mesh,
+            material: primitive.get("material").and_then(|v| v.as_usize()),
+        }))
+    }
+
+    fn load_meshes(&self) -> Result<Vec<GltfMesh>, GltfError> {
+        let mut meshes = vec![];
+        for (i, mesh) in array(&self.document, "meshes").iter().enumerate() {
+            let mut primitives = vec![];
+            for primitive in array(mesh, "primitives").iter() {
+                if let Some(primitive) = self.load_primitive(i, primitive)? {
+                    primitives.push(primitive);
+                }
+            }
+            meshes.push(GltfMesh {
+                name: mesh.get("name").and_then(|v| v.as_str()).map_or(format!("mesh{}", i), String::from),
+                primitives: primitives,
+            });
+        }
+
+        Ok(meshes)
+    }
+
+    fn texture_ref(&self, info: Option<&JsonValue>) -> Option<TextureRef> {
+        let info = info?;
+        Some(TextureRef {
+            texture: info.get("index").and_then(|v| v.as_usize())?,
+            tex_coord: info.get("texCoord").and_then(|v| v.as_usize()).unwrap_or(0) as u32,
+        })
+    }
+
+    fn load_materials(&self) -> Vec<PbrMaterial> {
+        let mut materials = vec![];
+        for (i, material) in array(&self.document, "materials").iter().enumerate() {
+            let null = JsonValue::Null;
+            let pbr = material.get("pbrMetallicRoughness").unwrap_or(&null);
+            let normal = material.get("normalTexture");
+            let occlusion = material.get("occlusionTexture");
+            let alpha_mode = match material.get("alphaMode").and_then(|v| v.as_str()) {
+                Some("MASK") => AlphaMode::Mask(number_or(material, "alphaCutoff", 0.5)),
+                Some("BLEND") => AlphaMode::Blend,
+                _ => AlphaMode::Opaque,
+            };
+            materials.push(PbrMaterial {
+                name: material.get("name").and_then(|v| v.as_str()).map_or(format!("material{}", i), String::from),
+                base_color_factor: floats_or(pbr, "baseColorFactor", [1.0; 4]),
+                base_color_texture: self.texture_ref(pbr.get("baseColorTexture")),
+                metallic_factor: number_or(pbr, "metallicFactor", 1.0),
+                roughness_factor: number_or(pbr, "roughnessFactor", 1.0),
+                metallic_roughness_texture: self.texture_ref(pbr.get("metallicRoughnessTexture")),
+                normal_texture: self.texture_ref(normal),
+                normal_scale: normal.map_or(1.0, |v| number_or(v, "scale", 1.0)),
+                occlusion_texture: self.texture_ref(occlusion),
+                occlusion_strength: occlusion.map_or(1.0, |v| number_or(v, "strength", 1.0)),
+                emissive_texture: self.texture_ref(material.get("emissiveTexture")),
+                emissive_factor: floats_or(material, "emissiveFactor", [0.0; 3]),
+                alpha_mode: alpha_mode,
+                double_sided: material.get("doubleSided").and_then(|v| v.as_bool()).unwrap_or(false),
+            });
+        }
+
+        materials
+    }
+
+    fn load_textures(&self) -> Result<Vec<GltfTexture>, GltfError> {
+        let images = array(&self.document, "images");
+        let samplers = array(&self.document, "samplers");
+        let mut textures = vec![];
+        for (i, texture) in array(&self.document, "textures").iter().enumerate() {
+            let image = match texture.get("source").and_then(|v| v.as_usize()).and_then(|s| images.get(s)) {
+                Some(val) => val,
+                None => return Err(self.error(format!("texture {} has no image", i))),
+            };
+            let mime_type = image.get("mimeType").and_then(|v| v.as_str()).unwrap_or("");
+            let source = match (image.get("uri").and_then(|v| v.as_str()), image.get("bufferView").and_then(|v| v.as_usize())) {
+                (Some(uri), _) if uri.starts_with("data:") => match decode_data_uri(uri) {
+                    Some((mime_type, data)) => TextureSource::Embedded { data: data, mime_type: mime_type },
+                    None => return Err(self.error(format!("the image of texture {} has an invalid data URI", i))),
+                },
+                (Some(uri), _) => TextureSource::File(self.dir.join(uri)),
+                (None, Some(view)) => {
+                    let (data, _) = self.buffer_view(view)?;
+                    TextureSource::Embedded { data: data.to_vec(), mime_type: String::from(mime_type) }
+                }
+                (None, None) => return Err(self.error(format!("the image of texture {} has no data", i))),
+            };
+
+            let null = JsonValue::Null;
+            let sampler = texture.get("sampler").and_then(|v| v.as_usize()).and_then(|s| samplers.get(s)).unwrap_or(&null);
+            let filter = |key: &str, default: GLenum| {
+                sampler.get(key).and_then(|v| v.as_usize()).map_or(default, |v| v as GLenum)
+            };
+            textures.push(GltfTexture {
+                name: texture.get("name").and_then(|v| v.as_str()).map_or(format!("texture{}", i), String::from),
+                source: source,
+                mag_filter: filter("magFilter", gl::LINEAR),
+                min_filter: filter("minFilter", gl::LINEAR_MIPMAP_LINEAR),
+                wrap_s: filter("wrapS", gl::REPEAT),
+                wrap_t: filter("wrapT", gl::REPEAT),
+            });
+        }
+
+        Ok(textures)
+    }
+
+    fn load_nodes(&self, mesh_count: usize, skin_count: usize) -> Result<Vec<SceneNode>, GltfError> {
+        let nodes_json = array(&self.document, "nodes");
+        let mut nodes = vec![];
+        for (i, node) in nodes_json.iter().enumerate() {
+            let (transform, local) = match node.get("matrix").and_then(|v| v.as_array()) {
+                Some(values) if values.len() == 16 => {
+                    let mut matrix = [[0.0; 4]; 4];
+                    for (j, value) in values.iter().enumerate() {
+                        matrix[j / 4][j % 4] = value.as_f64().unwrap_or(0.0) as f32;
+                    }
+                    (matrix, Transform::from_matrix(&matrix))
+                }
+                Some(_) => return Err(self.error(format!("node {} has a matrix without 16 values", i))),
+                None => {
+                    let local = Transform {
+                        translation: floats_or(node, "translation", [0.0; 3]),
+                        rotation: floats_or(node, "rotation", [0.0, 0.0, 0.0, 1.0]),
+                        scale: floats_or(node, "scale", [1.0; 3]),
+                    };
+                    (local.to_matrix(), local)
+                }
+            };
+            let mesh = node.get("mesh").and_then(|v| v.as_usize());
+            if mesh.map_or(false, |mesh| mesh >= mesh_count) {
+                return Err(self.error(format!("node {} refers to a missing mesh", i)));
+            }
+            let skin = node.get("skin").and_then(|v| v.as_usize());
+            if skin.map_or(false, |skin| skin >= skin_count) {
+                return Err(self.error(format!("node {} refers to a missing skin", i)));
+            }
+            let children: Vec<usize> = array(node, "children").iter().filter_map(|v| v.as_usize()).collect();
+            if children.iter().any(|&child| child >= nodes_json.len()) {
+                return Err(self.error(format!("node {} refers to a missing child", i)));
+            }
+            nodes.push(SceneNode {
+                name: node.get("name").and_then(|v| v.as_str()).map_or(format!("node{}", i), String::from),
+                transform: transform,
+                local: local,
+                mesh: mesh,
+                skin: skin,
+                parent: None,
+                children: children,
+            });
+        }
+
+        // A node has at most one parent, so the hierarchy is a forest.
+        for i in 0..nodes.len() {
+            for j in 0..nodes[i].children.len() {
+                let child = nodes[i].children[j];
+                if nodes[child].parent.is_some() || child == i {
+                    return Err(self.error(format!("node {} has more than one parent", child)));
+                }
+                nodes[child].parent = Some(i);
+            }
+        }
+        // Parents can still form a loop, e.g. two nodes that are children of each
+        // other. A chain of parents longer than the node count must be one.
+        for i in 0..nodes.len() {
+            let mut node = i;
+            let mut depth = 0;
+            while let Some(parent) = nodes[node].parent {
+                depth += 1;
+                if depth > nodes.len() {
+                    return Err(self.error(format!("the ancestors of node {} form a cycle", i)));
+                }
+                node = parent;
+            }
+        }
+
+        Ok(nodes)
+    }
+
+    fn load_skins(&self) -> Result<Vec<GltfSkin>, GltfError> {
+        let node_count = array(&self.document, "nodes").len();
+        let mut skins = vec![];
+        for (i, skin) in array(&self.document, "skins").iter().enumerate() {
+            let joints: Vec<usize> = array(skin, "joints").iter().filter_map(|v| v.as_usize()).collect();
+            if joints.iter().any(|&joint| joint >= node_count) {
+                return Err(self.error(format!("skin {} refers to a missing node", i)));
+            }
+            // Without inverse bind matrices every joint is bound at the origin.
+            let inverse_bind_matrices = match skin.get("inverseBindMatrices").and_then(|v| v.as_usize()) {
+                Some(accessor) => {
+                    let (values, components) = self.read_accessor(accessor)?;
+                    if components != 16 || values.len() / 16 < joints.len() {
+                        return Err(self.error(format!("skin {} has too few inverse bind matrices", i)));
+                    }
+                    values.chunks(16).map(|m| {
+                        let mut matrix = [[0.0; 4]; 4];
+                        for (j, &value) in m.iter().enumerate() {
+                            matrix[j / 4][j % 4] = value as f32;
+                        }
+                        matrix
+                    }).collect()
+                }
+                None => vec![MAT4_IDENTITY; joints.len()],
+            };
+            skins.push(GltfSkin {
+                name: skin.get("name").and_then(|v| v.as_str()).map_or(format!("skin{}", i), String::from),
+                joints: joints,
+                inverse_bind_matrices: inverse_bind_matrices,
+            });
+        }
+
+        Ok(skins)
+    }
+
+    fn load_animations(&self) -> Result<Vec<GltfAnimation>, GltfError> {
+        let node_count = array(&self.document, "nodes").len();
+        let mut animations = vec![];
+        for (i, animation) in array(&self.document, "animations").iter().enumerate() {
+            let samplers = array(animation, "samplers");
+            let mut channels = vec![];
+            for channel in array(animation, "channels").iter() {
+                let null = JsonValue::Null;
+                let target = channel.get("target").unwrap_or(&null);
+                // Channels without a node are for extensions.
+                let node = match target.get("node").and_then(|v| v.as_usize()) {
+                    Some(val) if val < node_count => val,
+                    Some(_) => return Err(self.error(format!("animation {} targets a missing node", i))),
+                    None => continue,
+                };
+                let sampler = match channel.get("sampler").and_then(|v| v.as_usize()).and_then(|s| samplers.get(s)) {
+                    Some(val) => val,
+                    None => return Err(self.error(format!("animation {} has a channel without a sampler", i))),
+                };
+                let interpolation = match sampler.get("interpolation").and_then(|v| v.as_str()) {
+                    Some("STEP") => Interpolation::Step,
+                    Some("CUBICSPLINE") => Interpolation::CubicSpline,
+                    _ => Interpolation::Linear,
+                };
+                let (input, output) = match (sampler.get("input"), sampler.get("output")) {
+                    (Some(input), Some(output)) => match (input.as_usize(), output.as_usize()) {
+                        (Some(input), Some(output)) => (input, output),
+                        _ => return Err(self.error(format!("animation {} has an invalid sampler", i))),
+                    },
+                    _ => return Err(self.error(format!("animation {} has an incomplete sampler", i))),
+                };
+                let (times, _) = self.read_accessor(input)?;
+                let times: Vec<f32> = times.iter().map(|&t| t as f32).collect();
+                let (values, components) = self.read_accessor(output)?;
+                let vec3 = || values.chunks(components).map(|c| [c[0] as f32, c[1] as f32, c[2] as f32]).collect();
+                let path = target.get("path").and_then(|v| v.as_str()).unwrap_or("");
+                let values = match (path, components) {
+                    ("translation", 3) => ChannelValues::Translation(vec3()),
+                    ("scale", 3) => ChannelValues::Scale(vec3()),
+                    ("rotation", 4) => ChannelValues::Rotation(
+                        values.chunks(4).map(|c| [c[0] as f32, c[1] as f32, c[2] as f32, c[3] as f32]).collect()
+                    ),
+                    ("weights", _) => {
+                        warn!("{}: skipping a morph target weight channel of animation {}", self.file_name, i);
+                        continue;
+                    }
+                    _ => return Err(self.error(format!("animation {} has a channel with an invalid path", i))),
+                };
+                let keyframe_values = if interpolation == Interpolation::CubicSpline { 3 } else { 1 };
+                let value_count = match values {
+                    ChannelValues::Translation(ref v) | ChannelValues::Scale(ref v) => v.len(),
+                    ChannelValues::Rotation(ref v) => v.len(),
+                };
+                if value_count != times.len() * keyframe_values {
+                    return Err(self.error(format!("animation {} has a channel with the wrong number of values", i)));
+                }
+                channels.push(Channel {
+                    target: node,
+                    times: times,
+                    values: values,
+                    interpolation: interpolation,
+                });
+            }
+            animations.push(GltfAnimation {
+                name: animation.get("name").and_then(|v| v.as_str()).map_or(format!("animation{}", i), String::from),
+                channels: channels,
+            });
+        }
+
+        Ok(animations)
+    }
+
+    /// The root nodes of the default scene. A file without scenes shows every node
+    /// that has no parent.
+    fn load_roots(&self, nodes: &[SceneNode]) -> Vec<usize> {
+        let scenes = array(&self.document, "scenes");
+        let scene = self.document.get("scene").and_then(|v| v.as_usize()).unwrap_or(0);
+        match scenes.get(scene) {
+            Some(scene) => array(scene, "nodes").iter().filter_map(|v| v.as_usize()).filter(|&n| n < nodes.len()).collect(),
+            None => (0..nodes.len()).filter(|&n| nodes[n].parent.is_none()).collect(),
+        }
+    }
+}
+
+/// Import a `.gltf` or `.glb` file. External buffers and images are resolved
+/// relative to the file.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<GltfScene, GltfError> {
+    let disp = path.as_ref().display().to_string();
+    let bytes = match fs::read(&path) {
+        Ok(val) => val,
+        Err(_) => return Err(GltfError::FileNotFound(disp)),
+    };
+    let dir = path.as_ref().parent().unwrap_or(Path::new(""));
+
+    load_from_memory(&bytes, &disp, dir)
+}
+
+/// Import a glTF file that is already in memory. `dir` is the directory that
+/// external buffers and images are relative to.
+pub fn load_from_memory(bytes: &[u8], file_name: &str, dir: &Path) -> Result<GltfScene, GltfError> {
+    let (text, bin) = if bytes.len() >= 4 && read_u32_le(bytes, 0) == GLB_MAGIC {
+        parse_glb(bytes, file_name)?
+    } else {
+        match String::from_utf8(bytes.to_vec()) {
+            Ok(text) => (text, None),
+            Err(_) => return Err(GltfError::InvalidGltf(String::from(file_name), String::from("the file is not UTF-8"))),
+        }
+    };
+    let document = match json::parse(&text) {
+        Ok(val) => val,
+        Err(e) => return Err(GltfError::CouldNotParseJson(String::from(file_name), e)),
+    };
+
+    let mut importer = Importer {
+        file_name: file_name,
+        dir: dir,
+        document: document,
+        buffers: vec![],
+    };
+    importer.load_buffers(bin)?;
+    let meshes = importer.load_meshes()?;
+    let materials = importer.load_materials();
+    let textures = importer.load_textures()?;
+    let skins = importer.load_skins()?;
+    let animations = importer.load_animations()?;
+    let nodes = importer.load_nodes(meshes.len(), skins.len())?;
+    let roots = importer.load_roots(&nodes);
+
+    Ok(GltfScene {
+        meshes: meshes,
+        materials: materials,
+        textures: textures,
+        nodes: nodes,
+        skins: skins,
+        animations: animations,
+        roots: roots,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    const BASE64_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    fn encode_base64(bytes: &[u8]) -> String {
+        let mut text = String::new();
+        for chunk in bytes.chunks(3) {
+            let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+            let word = (b[0] as usize) << 16 | (b[1] as usize) << 8 | b[2] as usize;
+            for i in 0..4 {
+                if i <= chunk.len() {
+                    text.push(BASE64_ALPHABET[(word >> (18 - 6 * i)) & 0x3F] as char);
+                } else {
+                    text.push('=');
+                }
+            }
+        }
+
+        text
+    }
+
+    fn f32_bytes(values: &[f32]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes().to_vec()).collect()
+    }
+
+    fn u16_bytes(values: &[u16]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes().to_vec()).collect()
+    }
+
+    /// Build a binary glTF file from its chunks, padding each to four bytes.
+    fn glb(version: u32, chunks: &[(u32, &[u8])]) -> Vec<u8> {
+        let mut body = vec![];
+        for &(chunk_type, data) in chunks.iter() {
+            let mut data = data.to_vec();
+            let padding = if chunk_type == GLB_CHUNK_JSON { b' ' } else { 0 };
+            while data.len() % 4 != 0 {
+                data.push(padding);
+            }
+            body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            body.extend_from_slice(&chunk_type.to_le_bytes());
+            body.extend_from_slice(&data);
+        }
+        let mut bytes = vec![];
+        bytes.extend_from_slice(&GLB_MAGIC.to_le_bytes());
+        bytes.extend_from_slice(&version.to_le_bytes());
+        bytes.extend_from_slice(&(12 + body.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&body);
+
+        bytes
+    }
+
+    /// Build a glTF document whose only buffer is embedded as a data URI. `members`
+    /// holds the rest of the top level members.
+    fn gltf_with_buffer(buffer: &[u8], members: &str) -> String {
+        format!(
+            r#"{{"asset": {{"version": "2.0"}}, "buffers": [{{"byteLength": {}, "uri": "data:application/octet-stream;base64,{}"}}], {}}}"#,
+            buffer.len(), encode_base64(buffer), members
+        )
+    }
+
+    fn load_text(text: &str) -> Result<GltfScene, GltfError> {
+        load_from_memory(text.as_bytes(), "test.gltf", Path::new(""))
+    }
+
+    fn triangle_points() -> Vec<u8> {
+        f32_bytes(&[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0])
+    }
+
+    const TRIANGLE_DOCUMENT: &str = r#"{
+        "asset": {"version": "2.0"},
+        "buffers": [{"byteLength": 42}],
+        "bufferViews": [
+            {"buffer": 0, "byteLength": 36},
+            {"buffer": 0, "byteOffset": 36, "byteLength": 6}
+        ],
+        "accessors": [
+            {"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3"},
+            {"bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR"}
+        ],
+        "meshes": [{"primitives": [{"attributes": {"POSITION": 0}, "indices": 1}]}],
+        "nodes": [{"mesh": 0}]
+    }"#;
+
+    fn assert_invalid_gltf(result: Result<GltfScene, GltfError>) {
+        match result {
+            Err(GltfError::InvalidGltf(_, _)) => {}
+            other => panic!("expected an invalid glTF error, got {:?}", other),
+        }
+    }
+
+    fn assert_invalid_glb(result: Result<GltfScene, GltfError>) {
+        match result {
+            Err(GltfError::InvalidGlb(_, _)) => {}
+            other => panic!("expected an invalid GLB error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_load_glb_with_binary_chunk() {
+        let mut bin = triangle_points();
+        bin.extend_from_slice(&u16_bytes(&[0, 2, 1]));
+        let bytes = glb(2, &[
+            (GLB_CHUNK_JSON, TRIANGLE_DOCUMENT.as_bytes()),
+            // Readers skip chunks of unknown types.
+            (0x1234_5678, &[1, 2, 3, 4]),
+            (GLB_CHUNK_BIN, &bin),
+        ]);
+        let scene = load_from_memory(&bytes, "test.glb", Path::new("")).unwrap();
+
+        let mesh = &scene.meshes[0].primitives[0].mesh;
+        assert_eq!(mesh.points(), &[[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]);
+        assert_eq!(mesh.indices().unwrap().iter().collect::<Vec<u32>>(), vec![0, 2, 1]);
+        assert_eq!(scene.roots, vec![0]);
+    }
+
+    #[test]
+    fn test_load_glb_rejects_malformed_files() {
+        let json = TRIANGLE_DOCUMENT.as_bytes();
+        let load = |bytes: &[u8]| load_from_memory(bytes, "test.glb", Path::new(""));
+
+        assert_invalid_glb(load(&glb(1, &[(GLB_CHUNK_JSON, json)])));
+        assert_invalid_glb(load(&glb(2, &[(GLB_CHUNK_BIN, &[0; 8])])));
+        let truncated = glb(2, &[(GLB_CHUNK_JSON, json)]);
+        assert_invalid_glb(load(&truncated[..truncated.len() - 8]));
+        // Buffer 0 has no URI, so it needs a binary chunk.
+        assert_invalid_gltf(load(&glb(2, &[(GLB_CHUNK_JSON, json)])));
+    }
+
+    #[test]
+    fn test_load_strided_accessors() {
+        // Each vertex is a position followed by an id, 16 bytes in all.
+        let buffer = f32_bytes(&[
+            0.0, 0.0, 0.0, 10.0,
+            1.0, 0.0, 0.0, 11.0,
+            0.0, 1.0, 0.0, 12.0,
+        ]);
+        let text = gltf_with_buffer(&buffer, r#"
+            "bufferViews": [{"buffer": 0, "byteLength": 48, "byteStride": 16}],
+            "accessors": [
+                {"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3"},
+                {"bufferView": 0, "byteOffset": 12, "componentType": 5126, "count": 3, "type": "SCALAR"}
+            ],
+            "meshes": [{"primitives": [{"attributes": {"POSITION": 0, "_ID": 1}}]}]
+        "#);
+        let scene = load_text(&text).unwrap();
+
+        let mesh = &scene.meshes[0].primitives[0].mesh;
+        assert_eq!(mesh.points(), &[[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]);
+        let ids = mesh.attribute(&Semantic::Custom(String::from("_ID"))).unwrap();
+        assert_eq!(ids.data, AttributeData::F32x1(vec![10.0, 11.0, 12.0]));
+    }
+
+    #[test]
+    fn test_load_sparse_accessor() {
+        // The base positions, the index of the replaced vertex padded to four
+        // bytes, and its new position.
+        let mut buffer = triangle_points();
+        buffer.extend_from_slice(&u16_bytes(&[1, 0]));
+        buffer.extend_from_slice(&f32_bytes(&[5.0, 0.0, 0.0]));
+        let text = gltf_with_buffer(&buffer, r#"
+            "bufferViews": [
+                {"buffer": 0, "byteLength": 36},
+                {"buffer": 0, "byteOffset": 36, "byteLength": 2},
+                {"buffer": 0, "byteOffset": 40, "byteLength": 12}
+            ],
+            "accessors": [
+                {
+                    "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                    "sparse": {
+                        "count": 1,
+                        "indices": {"bufferView": 1, "componentType": 5123},
+                        "values": {"bufferView": 2}
+                    }
+                },
+                {
+                    "componentType": 5126, "count": 3, "type": "VEC3",
+                    "sparse": {
+                        "count": 1,
+                        "indices": {"bufferView": 1, "componentType": 5123},
+                        "values": {"bufferView": 2}
+                    }
+                }
+            ],
+            "meshes": [
+                {"primitives": [{"attributes": {"POSITION": 0}}]},
+                {"primitives": [{"attributes": {"POSITION": 1}}]}
+            ]
+        "#);
+        let scene = load_text(&text).unwrap();
+
+        let replaced = scene.meshes[0].primitives[0].mesh.points();
+        assert_eq!(replaced, &[[0.0, 0.0, 0.0], [5.0, 0.0, 0.0], [0.0, 1.0, 0.0]]);
+        // Without a buffer view the other values are zero.
+        let zeros = scene.meshes[1].primitives[0].mesh.points();
+        assert_eq!(zeros, &[[0.0, 0.0, 0.0], [5.0, 0.0, 0.0], [0.0, 0.0, 0.0]]);
+    }
+
+    #[test]
+    fn test_load_strips_and_fans() {
+        let buffer = f32_bytes(&[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 1.0, 1.0, 0.0]);
+        let text = gltf_with_buffer(&buffer, r#"
+            "bufferViews": [{"buffer": 0, "byteLength": 48}],
+            "accessors": [{"bufferView": 0, "componentType": 5126, "count": 4, "type": "VEC3"}],
+            "meshes": [{"primitives": [
+                {"attributes": {"POSITION": 0}, "mode": 5},
+                {"attributes": {"POSITION": 0}, "mode": 6},
+                {"attributes": {"POSITION": 0}, "mode": 1}
+            ]}]
+        "#);
+        let scene = load_text(&text).unwrap();
+
+        // The line primitive is skipped.
+        let primitives = &scene.meshes[0].primitives;
+        assert_eq!(primitives.len(), 2);
+        let strip: Vec<u32> = primitives[0].mesh.indices().unwrap().iter().collect();
+        assert_eq!(strip, vec![0, 1, 2, 2, 1, 3]);
+        let fan: Vec<u32> = primitives[1].mesh.indices().unwrap().iter().collect();
+        assert_eq!(fan, vec![0, 1, 2, 0, 2, 3]);
+    }
+
+    #[test]
+    fn test_load_rejects_invalid_json() {
+        match load_text(r#"{"asset": }"#) {
+            Err(GltfError::CouldNotParseJson(_, _)) => {}
+            other => panic!("expected a JSON error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_load_rejects_bad_references() {
+        let mut buffer = triangle_points();
+        buffer.extend_from_slice(&u16_bytes(&[0, 1, 3]));
+        let mesh = |indices: &str| gltf_with_buffer(&buffer, &format!(r#"
+            "bufferViews": [
+                {{"buffer": 0, "byteLength": 36}},
+                {{"buffer": 0, "byteOffset": 36, "byteLength": 6}}
+            ],
+            "accessors": [
+                {{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3"}},
+                {{"bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR"}},
+                {{"bufferView": 0, "componentType": 5126, "count": 4, "type": "VEC3"}},
+                {{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC5"}}
+            ],
+            "meshes": [{{"primitives": [{{"attributes": {}}}]}}]
+        "#, indices));
+
+        // Index 3 is past the last vertex.
+        assert_invalid_gltf(load_text(&mesh(r#"{"POSITION": 0}, "indices": 1"#)));
+        // The accessor runs past the end of its buffer view.
+        assert_invalid_gltf(load_text(&mesh(r#"{"POSITION": 2}"#)));
+        assert_invalid_gltf(load_text(&mesh(r#"{"POSITION": 3}"#)));
+        assert_invalid_gltf(load_text(&mesh(r#"{"POSITION": 9}"#)));
+    }
+
+    #[test]
+    fn test_load_rejects_bad_hierarchies() {
+        let nodes = |nodes: &str| load_text(&format!(r#"{{"asset": {{"version": "2.0"}}, "nodes": {}}}"#, nodes));
+
+        assert_invalid_gltf(nodes(r#"[{"children": [1]}, {"children": [0]}]"#));
+        assert_invalid_gltf(nodes(r#"[{"children": [1]}, {"children": [2]}, {"children": [1]}]"#));
+        assert_invalid_gltf(nodes(r#"[{"children": [2]}, {"children": [2]}, {}]"#));
+        assert_invalid_gltf(nodes(r#"[{"children": [0]}]"#));
+        assert_invalid_gltf(nodes(r#"[{"children": [5]}]"#));
+
+        let scene = nodes(r#"[{"children": [1, 2]}, {}, {"children": [3]}, {}]"#).unwrap();
+        assert_eq!(scene.roots, vec![0]);
+        assert_eq!(scene.nodes[3].parent, Some(2));
+    }
+
+    #[test]
+    fn test_skeleton_and_clip_of_missing_skin() {
+        let scene = load_text(r#"{
+            "asset": {"version": "2.0"},
+            "nodes": [{"children": [1]}, {"children": [2], "translation": [0, 1, 0]}, {}],
+            "skins": [{"joints": [1, 2]}]
+        }"#).unwrap();
+
+        let skeleton = scene.skeleton(0).unwrap();
+        assert_eq!(skeleton.len(), 2);
+        assert_eq!(skeleton.joints()[1].parent, Some(0));
+        assert_eq!(scene.skeleton(1), Err(SkeletonError::SkinNotFound(1)));
+        assert_eq!(scene.animation_clip(0, 0), None);
+    }
+}
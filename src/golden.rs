@@ -0,0 +1,424 @@
+/*
+ *  GB-Prototype is a renderer prototype demo.
+ *  Copyright (C) 2018,2019,2020  Christopher Blanchard
+ *
+ *  This program is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  This program is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+#![allow(dead_code)]
+use crate::capture;
+use crate::capture::{CaptureError, CaptureSource, FrameCapture};
+use crate::error::Error;
+use crate::gl_backend as glh;
+use crate::gl_backend::GLState;
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::{info, error};
+
+
+#[derive(Clone, Debug)]
+pub enum GoldenError {
+    CaseNotFound(String),
+    CouldNotParseCase(String, String),
+    ReferenceNotFound(String),
+    UnknownScene(String),
+    CouldNotStartContext(Error),
+    CouldNotRenderScene(String, Error),
+    SizeMismatch((u32, u32), (u32, u32)),
+    Capture(CaptureError),
+}
+
+impl fmt::Display for GoldenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &GoldenError::CaseNotFound(ref file_name) => {
+                write!(f, "Could not open the golden image case for reading: {}", file_name)
+            }
+            &GoldenError::CouldNotParseCase(ref file_name, ref reason) => {
+                write!(f, "The golden image case {} is malformed: {}", file_name, reason)
+            }
+            &GoldenError::ReferenceNotFound(ref file_name) => {
+                write!(f, "The reference image {} does not exist. Run with --golden-bless to create it.", file_name)
+            }
+            &GoldenError::UnknownScene(ref scene) => {
+                write!(f, "There is no scene named {}", scene)
+            }
+            &GoldenError::CouldNotStartContext(ref e) => {
+                write!(f, "Could not start a headless GL context: {}", e)
+            }
+            &GoldenError::CouldNotRenderScene(ref scene, ref e) => {
+                write!(f, "Could not render the scene {}: {}", scene, e)
+            }
+            &GoldenError::SizeMismatch(actual, reference) => {
+                write!(f, "The rendered image is {} x {}, but the reference image is {} x {}",
+                    actual.0, actual.1, reference.0, reference.1
+                )
+            }
+            &GoldenError::Capture(ref e) => {
+                write!(f, "{}", e)
+            }
+        }
+    }
+}
+
+impl From<CaptureError> for GoldenError {
+    fn from(e: CaptureError) -> GoldenError {
+        GoldenError::Capture(e)
+    }
+}
+
+/// A golden image test case. It names a scene to render headlessly, the reference
+/// image to compare it against, and how far the result may stray from the reference.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GoldenCase {
+    pub name: String,
+    pub scene: String,
+    pub width: u32,
+    pub height: u32,
+    /// The number of frames to render before capturing.
+    pub frames: u32,
+    pub reference: PathBuf,
+    /// The largest difference in any color channel before a pixel counts as different.
+    pub tolerance: u8,
+    /// The largest percentage of different pixels for the case to pass.
+    pub threshold: f64,
+}
+
+/// Load a golden image case from a TOML file. The reference image path is relative
+/// to the directory containing the case file.
+pub fn load_case<P: AsRef<Path>>(path: P) -> Result<GoldenCase, GoldenError> {
+    let disp = path.as_ref().display().to_string();
+    let text = match fs::read_to_string(&path) {
+        Ok(val) => val,
+        Err(_) => return Err(GoldenError::CaseNotFound(disp)),
+    };
+    let value = match text.parse::<toml::Value>() {
+        Ok(val) => val,
+        Err(e) => return Err(GoldenError::CouldNotParseCase(disp, format!("{}", e))),
+    };
+
+    let get_str = |key: &str| -> Result<String, GoldenError> {
+        match value.get(key).and_then(|v| v.as_str()) {
+            Some(s) => Ok(String::from(s)),
+            None => Err(GoldenError::CouldNotParseCase(disp.clone(), format!("missing string `{}`", key))),
+        }
+    };
+    let get_int = |key: &str, default: i64| -> Result<i64, GoldenError> {
+        match value.get(key) {
+            Some(v) => match v.as_integer() {
+                Some(i) if i >= 0 => Ok(i),
+                _ => Err(GoldenError::CouldNotParseCase(disp.clone(), format!("`{}` must be a non-negative integer", key))),
+            },
+            None => Ok(default),
+        }
+    };
+    let get_float = |key: &str, default: f64| -> Result<f64, GoldenError> {
+        match value.get(key) {
+            Some(v) => match v.as_float().or_else(|| v.as_integer().map(|i| i as f64)) {
+                Some(x) => Ok(x),
+                None => Err(GoldenError::CouldNotParseCase(disp.clone(), format!("`{}` must be a number", key))),
+            },
+            None => Ok(default),
+        }
+    };
+
+    let name = get_str("name")?;
+    let scene = get_str("scene")?;
+    let reference = get_str("reference")?;
+    let width = get_int("width", 640)? as u32;
+    let height = get_int("height", 480)? as u32;
+    let frames = get_int("frames", 1)? as u32;
+    let tolerance = i64::min(get_int("tolerance", 0)?, 255) as u8;
+    let threshold = get_float("threshold", 0.0)?;
+
+    let base_dir = path.as_ref().parent().unwrap_or(Path::new("."));
+
+    Ok(GoldenCase {
+        name: name,
+        scene: scene,
+        width: width,
+        height: height,
+        frames: frames,
+        reference: base_dir.join(reference),
+        tolerance: tolerance,
+        threshold: threshold,
+    })
+}
+
+/// Load every golden image case (`*.toml`) in a directory, sorted by file name.
+pub fn load_cases<P: AsRef<Path>>(dir: P) -> Result<Vec<GoldenCase>, GoldenError> {
+    let disp = dir.as_ref().display().to_string();
+    let entries = match fs::read_dir(&dir) {
+        Ok(val) => val,
+        Err(_) => return Err(GoldenError::CaseNotFound(disp)),
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().map_or(false, |ext| ext == "toml"))
+        .collect();
+    paths.sort();
+
+    let mut cases = vec![];
+    for path in paths.iter() {
+        cases.push(load_case(path)?);
+    }
+
+    Ok(cases)
+}
+
+/// The result of comparing a rendered image against a reference image.
+#[derive(Clone, Debug)]
+pub struct ImageComparison {
+    pub differing_pixels: usize,
+    pub total_pixels: usize,
+    pub max_difference: u8,
+    /// An image highlighting the differing pixels in red over a faded copy of the
+    /// reference image.
+    pub diff: FrameCapture,
+}
+
+impl ImageComparison {
+    /// The percentage of pixels that differ by more than the tolerance.
+    pub fn percent_different(&self) -> f64 {
+        if self.total_pixels == 0 {
+            return 0.0;
+        }
+
+        100.0 * self.differing_pixels as f64 / self.total_pixels as f64
+    }
+
+    /// Determine whether the share of differing pixels is within a threshold given
+    /// as a percentage.
+    #[inline]
+    pub fn passes(&self, threshold: f64) -> bool {
+        self.percent_different() <= threshold
+    }
+}
+
+/// Compare two images pixel by pixel. A pixel differs when any of its channels
+/// differs by more than `tolerance`.
+pub fn compare_images(
+    actual: &FrameCapture, reference: &FrameCapture, tolerance: u8) -> Result<ImageComparison, GoldenError> {
+
+    if actual.width != reference.width || actual.height != reference.height {
+        return Err(GoldenError::SizeMismatch(
+            (actual.width, actual.height), (reference.width, reference.height)
+        ));
+    }
+
+    let mut differing_pixels = 0;
+    let mut max_difference = 0;
+    let mut diff_pixels = Vec::with_capacity(reference.pixels.len());
+    for (a, r) in actual.pixels.chunks(4).zip(reference.pixels.chunks(4)) {
+        let difference = a.iter()
+            .zip(r.iter())
+            .map(|(ac, rc)| (*ac as i16 - *rc as i16).abs() as u8)
+            .max()
+            .unwrap_or(0);
+        max_difference = u8::max(max_difference, difference);
+        if difference > tolerance {
+            differing_pixels += 1;
+            diff_pixels.extend_from_slice(&[u8::max(difference, 128), 0, 0, 255]);
+        } else {
+            // Fade the reference image so the differences stand out.
+            let luma = (0.299 * r[0] as f32 + 0.587 * r[1] as f32 + 0.114 * r[2] as f32) as u8;
+            let faded = luma / 4;
+            diff_pixels.extend_from_slice(&[faded, faded, faded, 255]);
+        }
+    }
+
+    Ok(ImageComparison {
+        differing_pixels: differing_pixels,
+        total_pixels: (reference.width * reference.height) as usize,
+        max_difference: max_difference,
+        diff: FrameCapture {
+            width: reference.width,
+            height: reference.height,
+            pixels: diff_pixels,
+        },
+    })
+}
+
+/// The outcome of running a golden image case.
+#[derive(Clone, Debug)]
+pub enum GoldenOutcome {
+    Passed(ImageComparison),
+    Failed(ImageComparison),
+    /// The rendered image was written out as the new reference image.
+    Blessed,
+}
+
+/// Render the scene for a golden image case in a headless context and compare the
+/// result to the reference image. The `render` function draws the given number of
+/// frames of a named scene and returns `Ok(false)` when it does not know the scene. On
+/// failure, the rendered image and a diff image are written to `output_dir`. When
+/// `bless` is set, the rendered image replaces the reference image instead.
+pub fn run_case<P, F>(
+    case: &GoldenCase, output_dir: P, bless: bool, mut render: F) -> Result<GoldenOutcome, GoldenError>
+    where P: AsRef<Path>, F: FnMut(&str, &mut GLState, u32) -> Result<bool, Error>
+{
+    info!("Running golden image case {}", case.name);
+    let mut context = match glh::start_gl(case.width, case.height, glh::ContextMode::Headless) {
+        Ok(val) => val,
+        Err(e) => return Err(GoldenError::CouldNotStartContext(e)),
+    };
+    match render(&case.scene, &mut context, u32::max(case.frames, 1)) {
+        Ok(true) => {}
+        Ok(false) => return Err(GoldenError::UnknownScene(case.scene.clone())),
+        Err(e) => return Err(GoldenError::CouldNotRenderScene(case.scene.clone(), e)),
+    }
+    let actual = capture::capture_frame(&context, CaptureSource::RenderTarget);
+
+    if bless {
+        actual.write_png(&case.reference)?;
+        info!("Blessed golden image case {}", case.name);
+        return Ok(GoldenOutcome::Blessed);
+    }
+
+    if !case.reference.exists() {
+        return Err(GoldenError::ReferenceNotFound(case.reference.display().to_string()));
+    }
+    let reference = FrameCapture::read_png(&case.reference)?;
+    let comparison = compare_images(&actual, &reference, case.tolerance)?;
+    if comparison.passes(case.threshold) {
+        info!(
+            "Golden image case {} passed with {:.3}% different pixels",
+            case.name, comparison.percent_different()
+        );
+        return Ok(GoldenOutcome::Passed(comparison));
+    }
+
+    error!(
+        "Golden image case {} failed with {:.3}% different pixels (threshold {:.3}%, max channel difference {})",
+        case.name, comparison.percent_different(), case.threshold, comparison.max_difference
+    );
+    if fs::create_dir_all(&output_dir).is_err() {
+        let disp = output_dir.as_ref().display().to_string();
+        return Err(GoldenError::Capture(CaptureError::CouldNotCreateFile(disp)));
+    }
+    actual.write_png(output_dir.as_ref().join(format!("{}.actual.png", case.name)))?;
+    comparison.diff.write_png(output_dir.as_ref().join(format!("{}.diff.png", case.name)))?;
+
+    Ok(GoldenOutcome::Failed(comparison))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+
+    fn solid_image(width: u32, height: u32, color: [u8; 4]) -> FrameCapture {
+        let pixels = (0..width * height).flat_map(|_| color.iter().cloned()).collect();
+
+        FrameCapture { width: width, height: height, pixels: pixels }
+    }
+
+    /// Write a case file into a fresh directory under the system temporary directory.
+    fn write_case(name: &str, text: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("golden_test_{}_{}", name, process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("case.toml");
+        fs::write(&path, text).unwrap();
+
+        path
+    }
+
+    #[test]
+    fn test_compare_images_tolerance() {
+        let reference = solid_image(2, 2, [100, 100, 100, 255]);
+        let mut actual = reference.clone();
+        // One pixel exactly at the tolerance and one just past it.
+        actual.pixels[0] = 104;
+        actual.pixels[6] = 95;
+        let comparison = compare_images(&actual, &reference, 4).unwrap();
+
+        assert_eq!(comparison.differing_pixels, 1);
+        assert_eq!(comparison.total_pixels, 4);
+        assert_eq!(comparison.max_difference, 5);
+        assert_eq!(&comparison.diff.pixels[4..8], &[128, 0, 0, 255]);
+
+        let comparison = compare_images(&actual, &reference, 5).unwrap();
+        assert_eq!(comparison.differing_pixels, 0);
+    }
+
+    #[test]
+    fn test_compare_images_size_mismatch() {
+        let actual = solid_image(4, 2, [0, 0, 0, 255]);
+        let reference = solid_image(2, 4, [0, 0, 0, 255]);
+
+        match compare_images(&actual, &reference, 0) {
+            Err(GoldenError::SizeMismatch(a, r)) => {
+                assert_eq!(a, (4, 2));
+                assert_eq!(r, (2, 4));
+            }
+            other => panic!("expected a size mismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_percent_different_threshold() {
+        let reference = solid_image(10, 10, [0, 0, 0, 255]);
+        let mut actual = reference.clone();
+        for pixel in 0..10 {
+            actual.pixels[4 * pixel] = 255;
+        }
+        let comparison = compare_images(&actual, &reference, 0).unwrap();
+
+        assert_eq!(comparison.percent_different(), 10.0);
+        assert!(comparison.passes(10.0));
+        assert!(!comparison.passes(9.9));
+    }
+
+    #[test]
+    fn test_load_case() {
+        let path = write_case("relative", r#"
+            name = "triangle"
+            scene = "marble_triangle"
+            reference = "images/triangle.png"
+            width = 320
+            tolerance = 300
+            threshold = 1
+        "#);
+        let case = load_case(&path).unwrap();
+
+        assert_eq!(case.name, "triangle");
+        assert_eq!(case.width, 320);
+        assert_eq!(case.height, 480);
+        assert_eq!(case.frames, 1);
+        assert_eq!(case.tolerance, 255);
+        assert_eq!(case.threshold, 1.0);
+        assert_eq!(case.reference, path.parent().unwrap().join("images").join("triangle.png"));
+    }
+
+    #[test]
+    fn test_load_case_missing_key() {
+        let path = write_case("missing", r#"
+            name = "triangle"
+            reference = "triangle.png"
+        "#);
+
+        match load_case(&path) {
+            Err(GoldenError::CouldNotParseCase(_, reason)) => assert!(reason.contains("scene")),
+            other => panic!("expected a malformed case, got {:?}", other),
+        }
+        match load_case(path.with_file_name("absent.toml")) {
+            Err(GoldenError::CaseNotFound(_)) => {}
+            other => panic!("expected a missing case, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,666 @@
+/*
+ *  GB-Prototype is a renderer prototype demo.
+ *  Copyright (C) 2018,2019,2020  Christopher Blanchard
+ *
+ *  This program is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  This program is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+#![allow(dead_code)]
+use glfw;
+use glfw::{Action, Key, MouseButton, WindowEvent};
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::mpsc::Receiver;
+
+
+#[derive(Clone, Debug)]
+pub enum InputError {
+    BindingsNotFound(String),
+    CouldNotParseBindings(String),
+    UnknownButton(String),
+    MalformedBinding(String),
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &InputError::BindingsNotFound(ref file_name) => {
+                write!(f, "Could not open the input bindings file for reading: {}", file_name)
+            }
+            &InputError::CouldNotParseBindings(ref reason) => {
+                write!(f, "The input bindings could not be parsed: {}", reason)
+            }
+            &InputError::UnknownButton(ref name) => {
+                write!(f, "There is no key or mouse button named {}", name)
+            }
+            &InputError::MalformedBinding(ref name) => {
+                write!(f, "The input binding for {} must be a list of button names", name)
+            }
+        }
+    }
+}
+
+/// A physical button that an action can be bound to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Button {
+    Key(Key),
+    Mouse(MouseButton),
+}
+
+/// The input state for a single frame. It tracks which buttons are held down,
+/// which ones changed state during the frame, and how far the cursor and scroll
+/// wheel moved.
+#[derive(Clone, Debug)]
+pub struct InputState {
+    down: HashSet<Button>,
+    pressed: HashSet<Button>,
+    released: HashSet<Button>,
+    cursor: (f64, f64),
+    cursor_delta: (f64, f64),
+    has_cursor: bool,
+    scroll_delta: (f64, f64),
+    text: String,
+}
+
+impl InputState {
+    pub fn new() -> InputState {
+        InputState {
+            down: HashSet::new(),
+            pressed: HashSet::new(),
+            released: HashSet::new(),
+            cursor: (0.0, 0.0),
+            cursor_delta: (0.0, 0.0),
+            has_cursor: false,
+            scroll_delta: (0.0, 0.0),
+            text: String::new(),
+        }
+    }
+
+    /// Clear the per-frame transitions and deltas. Call this once at the start of
+    /// every frame before processing that frame's events.
+    pub fn begin_frame(&mut self) {
+        self.pressed.clear();
+        self.released.clear();
+        self.cursor_delta = (0.0, 0.0);
+        self.scroll_delta = (0.0, 0.0);
+        self.text.clear();
+    }
+
+    fn update_button(&mut self, button: Button, action: Action) {
+        match action {
+            Action::Press => {
+                if self.down.insert(button) {
+                    self.pressed.insert(button);
+                }
+            }
+            Action::Release => {
+                if self.down.remove(&button) {
+                    self.released.insert(button);
+                }
+            }
+            Action::Repeat => {}
+        }
+    }
+
+    /// Update the input state from a single window event. Events that do not
+    /// carry input are ignored.
+    pub fn process_event(&mut self, event: &WindowEvent) {
+        match *event {
+            WindowEvent::Key(key, _, action, _) => {
+                self.update_button(Button::Key(key), action);
+            }
+            WindowEvent::MouseButton(button, action, _) => {
+                self.update_button(Button::Mouse(button), action);
+            }
+            WindowEvent::CursorPos(x, y) => {
+                if self.has_cursor {
+                    self.cursor_delta.0 += x - self.cursor.0;
+                    self.cursor_delta.1 += y - self.cursor.1;
+                }
+                self.cursor = (x, y);
+                self.has_cursor = true;
+            }
+            WindowEvent::Scroll(x, y) => {
+                self.scroll_delta.0 += x;
+                self.scroll_delta.1 += y;
+            }
+            WindowEvent::Char(ch) => {
+                self.text.push(ch);
+            }
+            _ => {}
+        }
+    }
+
+    /// Drain every pending event from a GLFW event receiver into the input state.
+    pub fn drain_events(&mut self, events: &Receiver<(f64, WindowEvent)>) {
+        for (_, event) in glfw::flush_messages(events) {
+            self.process_event(&event);
+        }
+    }
+
+    /// Determine whether a button is held down.
+    #[inline]
+    pub fn is_down(&self, button: Button) -> bool {
+        self.down.contains(&button)
+    }
+
+    /// Determine whether a button went down during this frame.
+    #[inline]
+    pub fn was_pressed(&self, button: Button) -> bool {
+        self.pressed.contains(&button)
+    }
+
+    /// Determine whether a button went up during this frame.
+    #[inline]
+    pub fn was_released(&self, button: Button) -> bool {
+        self.released.contains(&button)
+    }
+
+    /// The cursor position in screen coordinates.
+    #[inline]
+    pub fn cursor(&self) -> (f64, f64) {
+        self.cursor
+    }
+
+    /// How far the cursor moved during this frame.
+    #[inline]
+    pub fn cursor_delta(&self) -> (f64, f64) {
+        self.cursor_delta
+    }
+
+    /// How far the scroll wheel moved during this frame.
+    #[inline]
+    pub fn scroll_delta(&self) -> (f64, f64) {
+        self.scroll_delta
+    }
+
+    /// The text typed during this frame.
+    #[inline]
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+impl Default for InputState {
+    fn default() -> InputState {
+        InputState::new()
+    }
+}
+
+/// An axis built from two sets of buttons. It reads `1.0` when only a positive
+/// button is held, `-1.0` when only a negative button is held, and `0.0` otherwise.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AxisBinding {
+    pub positive: Vec<Button>,
+    pub negative: Vec<Button>,
+}
+
+/// The association between named actions and axes and the buttons bound to them.
+/// The bindings are written in TOML:
+///
+/// ```toml
+/// [actions]
+/// move_left = ["A", "Left"]
+/// fire = ["Space", "MouseLeft"]
+///
+/// [axes]
+/// horizontal = { positive = ["D", "Right"], negative = ["A", "Left"] }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ActionMap {
+    actions: HashMap<String, Vec<Button>>,
+    axes: HashMap<String, AxisBinding>,
+}
+
+impl ActionMap {
+    pub fn new() -> ActionMap {
+        ActionMap {
+            actions: HashMap::new(),
+            axes: HashMap::new(),
+        }
+    }
+
+    /// Bind an action to a set of buttons, replacing any previous binding.
+    pub fn bind_action(&mut self, action: &str, buttons: &[Button]) {
+        self.actions.insert(String::from(action), buttons.to_vec());
+    }
+
+    /// Bind an axis to a set of buttons, replacing any previous binding.
+    pub fn bind_axis(&mut self, axis: &str, positive: &[Button], negative: &[Button]) {
+        self.axes.insert(String::from(axis), AxisBinding {
+            positive: positive.to_vec(),
+            negative: negative.to_vec(),
+        });
+    }
+
+    /// Parse action and axis bindings from a TOML string.
+    pub fn parse(text: &str) -> Result<ActionMap, InputError> {
+        let value = match text.parse::<toml::Value>() {
+            Ok(val) => val,
+            Err(e) => return Err(InputError::CouldNotParseBindings(format!("{}", e))),
+        };
+
+        let mut map = ActionMap::new();
+        if let Some(actions) = value.get("actions") {
+            let table = match actions.as_table() {
+                Some(val) => val,
+                None => return Err(InputError::MalformedBinding(String::from("actions"))),
+            };
+            for (name, buttons) in table.iter() {
+                let buttons = parse_button_list(name, buttons)?;
+                map.bind_action(name, &buttons);
+            }
+        }
+        if let Some(axes) = value.get("axes") {
+            let table = match axes.as_table() {
+                Some(val) => val,
+                None => return Err(InputError::MalformedBinding(String::from("axes"))),
+            };
+            for (name, axis) in table.iter() {
+                let positive = match axis.get("positive") {
+                    Some(buttons) => parse_button_list(name, buttons)?,
+                    None => vec![],
+                };
+                let negative = match axis.get("negative") {
+                    Some(buttons) => parse_button_list(name, buttons)?,
+                    None => vec![],
+                };
+                map.bind_axis(name, &positive, &negative);
+            }
+        }
+
+        Ok(map)
+    }
+
+    /// Load action and axis bindings from a TOML file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ActionMap, InputError> {
+        let text = match fs::read_to_string(&path) {
+            Ok(val) => val,
+            Err(_) => {
+                let disp = path.as_ref().display().to_string();
+                return Err(InputError::BindingsNotFound(disp));
+            }
+        };
+
+        ActionMap::parse(&text)
+    }
+
+    /// Determine whether any button bound to an action is held down.
+    pub fn is_action_down(&self, state: &InputState, action: &str) -> bool {
+        self.actions.get(action).map_or(false, |buttons| {
+            buttons.iter().any(|button| state.is_down(*button))
+        })
+    }
+
+    /// Determine whether an action started during this frame. This is true when
+    /// a bound button went down and no other bound button was already held.
+    pub fn was_action_pressed(&self, state: &InputState, action: &str) -> bool {
+        self.actions.get(action).map_or(false, |buttons| {
+            let pressed = buttons.iter().any(|button| state.was_pressed(*button));
+            let held = buttons.iter().any(|button| {
+                state.is_down(*button) && !state.was_pressed(*button)
+            });
+            pressed && !held
+        })
+    }
+
+    /// Determine whether an action ended during this frame.
+    pub fn was_action_released(&self, state: &InputState, action: &str) -> bool {
+        self.actions.get(action).map_or(false, |buttons| {
+            let released = buttons.iter().any(|button| state.was_released(*button));
+            released && !buttons.iter().any(|button| state.is_down(*button))
+        })
+    }
+
+    /// Read the value of an axis in the range `[-1.0, 1.0]`.
+    pub fn axis(&self, state: &InputState, axis: &str) -> f32 {
+        self.axes.get(axis).map_or(0.0, |binding| {
+            let positive = binding.positive.iter().any(|button| state.is_down(*button));
+            let negative = binding.negative.iter().any(|button| state.is_down(*button));
+            match (positive, negative) {
+                (true, false) => 1.0,
+                (false, true) => -1.0,
+                _ => 0.0,
+            }
+        })
+    }
+}
+
+impl Default for ActionMap {
+    fn default() -> ActionMap {
+        ActionMap::new()
+    }
+}
+
+/// The input subsystem. It combines the per-frame input state with the action
+/// bindings, so game code can query actions instead of raw GLFW keys.
+#[derive(Clone, Debug)]
+pub struct Input {
+    pub state: InputState,
+    pub bindings: ActionMap,
+}
+
+impl Input {
+    pub fn new(bindings: ActionMap) -> Input {
+        Input {
+            state: InputState::new(),
+            bindings: bindings,
+        }
+    }
+
+    /// Start a new frame and drain the pending window events into the input state.
+    pub fn update(&mut self, events: &Receiver<(f64, WindowEvent)>) {
+        self.state.begin_frame();
+        self.state.drain_events(events);
+    }
+
+    #[inline]
+    pub fn is_action_down(&self, action: &str) -> bool {
+        self.bindings.is_action_down(&self.state, action)
+    }
+
+    #[inline]
+    pub fn was_action_pressed(&self, action: &str) -> bool {
+        self.bindings.was_action_pressed(&self.state, action)
+    }
+
+    #[inline]
+    pub fn was_action_released(&self, action: &str) -> bool {
+        self.bindings.was_action_released(&self.state, action)
+    }
+
+    #[inline]
+    pub fn axis(&self, axis: &str) -> f32 {
+        self.bindings.axis(&self.state, axis)
+    }
+}
+
+fn parse_button_list(name: &str, value: &toml::Value) -> Result<Vec<Button>, InputError> {
+    let list = match value.as_array() {
+        Some(val) => val,
+        None => return Err(InputError::MalformedBinding(String::from(name))),
+    };
+    let mut buttons = vec![];
+    for item in list.iter() {
+        match item.as_str() {
+            Some(button_name) => buttons.push(parse_button(button_name)?),
+            None => return Err(InputError::MalformedBinding(String::from(name))),
+        }
+    }
+
+    Ok(buttons)
+}
+
+/// The names of the keyboard keys in a bindings file. They follow the GLFW key names.
+/// When a key has more than one name, the first one is its canonical name.
+const KEY_NAMES: [(&str, Key); 130] = [
+    ("Space", Key::Space),
+    ("Apostrophe", Key::Apostrophe),
+    ("Comma", Key::Comma),
+    ("Minus", Key::Minus),
+    ("Period", Key::Period),
+    ("Slash", Key::Slash),
+    ("Num0", Key::Num0),
+    ("0", Key::Num0),
+    ("Num1", Key::Num1),
+    ("1", Key::Num1),
+    ("Num2", Key::Num2),
+    ("2", Key::Num2),
+    ("Num3", Key::Num3),
+    ("3", Key::Num3),
+    ("Num4", Key::Num4),
+    ("4", Key::Num4),
+    ("Num5", Key::Num5),
+    ("5", Key::Num5),
+    ("Num6", Key::Num6),
+    ("6", Key::Num6),
+    ("Num7", Key::Num7),
+    ("7", Key::Num7),
+    ("Num8", Key::Num8),
+    ("8", Key::Num8),
+    ("Num9", Key::Num9),
+    ("9", Key::Num9),
+    ("Semicolon", Key::Semicolon),
+    ("Equal", Key::Equal),
+    ("A", Key::A),
+    ("B", Key::B),
+    ("C", Key::C),
+    ("D", Key::D),
+    ("E", Key::E),
+    ("F", Key::F),
+    ("G", Key::G),
+    ("H", Key::H),
+    ("I", Key::I),
+    ("J", Key::J),
+    ("K", Key::K),
+    ("L", Key::L),
+    ("M", Key::M),
+    ("N", Key::N),
+    ("O", Key::O),
+    ("P", Key::P),
+    ("Q", Key::Q),
+    ("R", Key::R),
+    ("S", Key::S),
+    ("T", Key::T),
+    ("U", Key::U),
+    ("V", Key::V),
+    ("W", Key::W),
+    ("X", Key::X),
+    ("Y", Key::Y),
+    ("Z", Key::Z),
+    ("LeftBracket", Key::LeftBracket),
+    ("Backslash", Key::Backslash),
+    ("RightBracket", Key::RightBracket),
+    ("GraveAccent", Key::GraveAccent),
+    ("World1", Key::World1),
+    ("World2", Key::World2),
+    ("Escape", Key::Escape),
+    ("Enter", Key::Enter),
+    ("Tab", Key::Tab),
+    ("Backspace", Key::Backspace),
+    ("Insert", Key::Insert),
+    ("Delete", Key::Delete),
+    ("Right", Key::Right),
+    ("Left", Key::Left),
+    ("Down", Key::Down),
+    ("Up", Key::Up),
+    ("PageUp", Key::PageUp),
+    ("PageDown", Key::PageDown),
+    ("Home", Key::Home),
+    ("End", Key::End),
+    ("CapsLock", Key::CapsLock),
+    ("ScrollLock", Key::ScrollLock),
+    ("NumLock", Key::NumLock),
+    ("PrintScreen", Key::PrintScreen),
+    ("Pause", Key::Pause),
+    ("F1", Key::F1),
+    ("F2", Key::F2),
+    ("F3", Key::F3),
+    ("F4", Key::F4),
+    ("F5", Key::F5),
+    ("F6", Key::F6),
+    ("F7", Key::F7),
+    ("F8", Key::F8),
+    ("F9", Key::F9),
+    ("F10", Key::F10),
+    ("F11", Key::F11),
+    ("F12", Key::F12),
+    ("F13", Key::F13),
+    ("F14", Key::F14),
+    ("F15", Key::F15),
+    ("F16", Key::F16),
+    ("F17", Key::F17),
+    ("F18", Key::F18),
+    ("F19", Key::F19),
+    ("F20", Key::F20),
+    ("F21", Key::F21),
+    ("F22", Key::F22),
+    ("F23", Key::F23),
+    ("F24", Key::F24),
+    ("F25", Key::F25),
+    ("Kp0", Key::Kp0),
+    ("Kp1", Key::Kp1),
+    ("Kp2", Key::Kp2),
+    ("Kp3", Key::Kp3),
+    ("Kp4", Key::Kp4),
+    ("Kp5", Key::Kp5),
+    ("Kp6", Key::Kp6),
+    ("Kp7", Key::Kp7),
+    ("Kp8", Key::Kp8),
+    ("Kp9", Key::Kp9),
+    ("KpDecimal", Key::KpDecimal),
+    ("KpDivide", Key::KpDivide),
+    ("KpMultiply", Key::KpMultiply),
+    ("KpSubtract", Key::KpSubtract),
+    ("KpAdd", Key::KpAdd),
+    ("KpEnter", Key::KpEnter),
+    ("KpEqual", Key::KpEqual),
+    ("LeftShift", Key::LeftShift),
+    ("LeftControl", Key::LeftControl),
+    ("LeftAlt", Key::LeftAlt),
+    ("LeftSuper", Key::LeftSuper),
+    ("RightShift", Key::RightShift),
+    ("RightControl", Key::RightControl),
+    ("RightAlt", Key::RightAlt),
+    ("RightSuper", Key::RightSuper),
+    ("Menu", Key::Menu),
+];
+
+/// The names of the mouse buttons in a bindings file. When a button has more than
+/// one name, the first one is its canonical name.
+const MOUSE_BUTTON_NAMES: [(&str, MouseButton); 11] = [
+    ("MouseLeft", MouseButton::Button1),
+    ("Mouse1", MouseButton::Button1),
+    ("MouseRight", MouseButton::Button2),
+    ("Mouse2", MouseButton::Button2),
+    ("MouseMiddle", MouseButton::Button3),
+    ("Mouse3", MouseButton::Button3),
+    ("Mouse4", MouseButton::Button4),
+    ("Mouse5", MouseButton::Button5),
+    ("Mouse6", MouseButton::Button6),
+    ("Mouse7", MouseButton::Button7),
+    ("Mouse8", MouseButton::Button8),
+];
+
+/// Look up a key or mouse button by the name used in a bindings file. Key names
+/// follow the GLFW key names, e.g. `A`, `Num1`, `Left`, `F12`, `LeftShift`.
+pub fn parse_button(name: &str) -> Result<Button, InputError> {
+    if let Some(&(_, button)) = MOUSE_BUTTON_NAMES.iter().find(|&&(n, _)| n == name) {
+        return Ok(Button::Mouse(button));
+    }
+    if let Some(&(_, key)) = KEY_NAMES.iter().find(|&&(n, _)| n == name) {
+        return Ok(Button::Key(key));
+    }
+
+    Err(InputError::UnknownButton(String::from(name)))
+}
+
+/// Get the canonical name of a key or mouse button, as accepted by `parse_button`.
+/// Returns `None` for keys that have no name, such as `Key::Unknown`.
+pub fn button_name(button: Button) -> Option<&'static str> {
+    match button {
+        Button::Key(key) => {
+            KEY_NAMES.iter().find(|&&(_, k)| k == key).map(|&(n, _)| n)
+        }
+        Button::Mouse(mouse_button) => {
+            MOUSE_BUTTON_NAMES.iter().find(|&&(_, b)| b == mouse_button).map(|&(n, _)| n)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    /// Check that a bindings file is rejected as malformed for the named binding.
+    fn assert_malformed(text: &str, binding: &str) {
+        match ActionMap::parse(text) {
+            Err(InputError::MalformedBinding(ref name)) => assert_eq!(name, binding),
+            other => panic!("expected a malformed binding for {}, got {:?}", binding, other),
+        }
+    }
+
+    #[test]
+    fn test_key_names_round_trip() {
+        for &(name, key) in KEY_NAMES.iter() {
+            let button = parse_button(name).unwrap();
+            assert_eq!(button, Button::Key(key));
+
+            let canonical = button_name(button).unwrap();
+            assert_eq!(parse_button(canonical).unwrap(), button);
+        }
+    }
+
+    #[test]
+    fn test_mouse_button_names_round_trip() {
+        for &(name, mouse_button) in MOUSE_BUTTON_NAMES.iter() {
+            let button = parse_button(name).unwrap();
+            assert_eq!(button, Button::Mouse(mouse_button));
+
+            let canonical = button_name(button).unwrap();
+            assert_eq!(parse_button(canonical).unwrap(), button);
+        }
+    }
+
+    #[test]
+    fn test_canonical_name_comes_first() {
+        assert_eq!(button_name(Button::Mouse(MouseButton::Button1)), Some("MouseLeft"));
+        assert_eq!(button_name(Button::Key(Key::Space)), Some("Space"));
+        assert_eq!(button_name(Button::Key(Key::Unknown)), None);
+    }
+
+    #[test]
+    fn test_parse_unknown_button() {
+        match parse_button("NotAKey") {
+            Err(InputError::UnknownButton(ref name)) => assert_eq!(name, "NotAKey"),
+            other => panic!("expected an unknown button, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_bindings() {
+        let map = ActionMap::parse(r#"
+            [actions]
+            fire = ["Space", "MouseLeft"]
+
+            [axes]
+            horizontal = { positive = ["D"], negative = ["A"] }
+        "#).unwrap();
+
+        let mut expected = ActionMap::new();
+        expected.bind_action("fire", &[Button::Key(Key::Space), Button::Mouse(MouseButton::Button1)]);
+        expected.bind_axis("horizontal", &[Button::Key(Key::D)], &[Button::Key(Key::A)]);
+        assert_eq!(map, expected);
+    }
+
+    #[test]
+    fn test_parse_malformed_bindings() {
+        match ActionMap::parse("[actions\nfire = [\"Space\"]") {
+            Err(InputError::CouldNotParseBindings(_)) => {}
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+        assert_malformed("actions = 3", "actions");
+        assert_malformed("axes = \"horizontal\"", "axes");
+        assert_malformed("[actions]\nfire = \"Space\"", "fire");
+        assert_malformed("[actions]\nfire = [1, 2]", "fire");
+        assert_malformed("[axes]\nhorizontal = { positive = \"D\" }", "horizontal");
+        match ActionMap::parse("[actions]\nfire = [\"Spacebar\"]") {
+            Err(InputError::UnknownButton(ref name)) => assert_eq!(name, "Spacebar"),
+            other => panic!("expected an unknown button, got {:?}", other),
+        }
+    }
+}
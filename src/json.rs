@@ -0,0 +1,385 @@
+/*
+ *  GB-Prototype is a renderer prototype demo.
+ *  Copyright (C) 2018,2019,2020  Christopher Blanchard
+ *
+ *  This program is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  This program is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+#![allow(dead_code)]
+//! A small JSON reader. It parses a document into a tree of values, which is all
+//! the glTF importer needs.
+use std::fmt;
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct JsonError {
+    pub line: usize,
+    pub column: usize,
+    pub reason: String,
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid JSON on line {}, column {}: {}", self.line, self.column, self.reason)
+    }
+}
+
+/// A JSON value. Objects keep their members in document order.
+#[derive(Clone, Debug, PartialEq)]
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>),
+}
+
+impl JsonValue {
+    /// Look up a member of an object. Returns `None` for other values.
+    pub fn get(&self, key: &str) -> Option<&JsonValue> {
+        match *self {
+            JsonValue::Object(ref members) => {
+                members.iter().find(|&&(ref name, _)| name == key).map(|&(_, ref value)| value)
+            }
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            JsonValue::Bool(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            JsonValue::Number(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Get a number that is a non-negative integer, e.g. an index or a count.
+    #[inline]
+    pub fn as_usize(&self) -> Option<usize> {
+        match *self {
+            JsonValue::Number(value) if value >= 0.0 && value.fract() == 0.0 => Some(value as usize),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            JsonValue::String(ref value) => Some(value),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn as_array(&self) -> Option<&[JsonValue]> {
+        match *self {
+            JsonValue::Array(ref values) => Some(values),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn as_object(&self) -> Option<&[(String, JsonValue)]> {
+        match *self {
+            JsonValue::Object(ref members) => Some(members),
+            _ => None,
+        }
+    }
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, reason: &str) -> JsonError {
+        let consumed = &self.text[..self.position];
+        let line = consumed.iter().filter(|&&c| c == b'\n').count() + 1;
+        let line_start = consumed.iter().rposition(|&c| c == b'\n').map_or(0, |i| i + 1);
+
+        JsonError {
+            line: line,
+            column: self.position - line_start + 1,
+            reason: String::from(reason),
+        }
+    }
+
+    #[inline]
+    fn peek(&self) -> Option<u8> {
+        self.text.get(self.position).cloned()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek() {
+            match c {
+                b' ' | b'\t' | b'\n' | b'\r' => self.position += 1,
+                _ => break,
+            }
+        }
+    }
+
+    fn expect(&mut self, expected: u8) -> Result<(), JsonError> {
+        if self.peek() == Some(expected) {
+            self.position += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected `{}`", expected as char)))
+        }
+    }
+
+    fn parse_literal(&mut self, literal: &str, value: JsonValue) -> Result<JsonValue, JsonError> {
+        if self.text[self.position..].starts_with(literal.as_bytes()) {
+            self.position += literal.len();
+            Ok(value)
+        } else {
+            Err(self.error("unexpected token"))
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<JsonValue, JsonError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'{') => self.parse_object(),
+            Some(b'[') => self.parse_array(),
+            Some(b'"') => self.parse_string().map(JsonValue::String),
+            Some(b't') => self.parse_literal("true", JsonValue::Bool(true)),
+            Some(b'f') => self.parse_literal("false", JsonValue::Bool(false)),
+            Some(b'n') => self.parse_literal("null", JsonValue::Null),
+            Some(c) if c == b'-' || (c as char).is_ascii_digit() => self.parse_number(),
+            Some(_) => Err(self.error("unexpected character")),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<JsonValue, JsonError> {
+        self.expect(b'{')?;
+        let mut members = vec![];
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.position += 1;
+            return Ok(JsonValue::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.parse_string()?;
+            self.skip_whitespace();
+            self.expect(b':')?;
+            let value = self.parse_value()?;
+            members.push((key, value));
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.position += 1,
+                Some(b'}') => {
+                    self.position += 1;
+                    return Ok(JsonValue::Object(members));
+                }
+                _ => return Err(self.error("expected `,` or `}`")),
+            }
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<JsonValue, JsonError> {
+        self.expect(b'[')?;
+        let mut values = vec![];
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.position += 1;
+            return Ok(JsonValue::Array(values));
+        }
+        loop {
+            values.push(self.parse_value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.position += 1,
+                Some(b']') => {
+                    self.position += 1;
+                    return Ok(JsonValue::Array(values));
+                }
+                _ => return Err(self.error("expected `,` or `]`")),
+            }
+        }
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, JsonError> {
+        let digits = match self.text.get(self.position..self.position + 4) {
+            Some(val) => val,
+            None => return Err(self.error("truncated unicode escape")),
+        };
+        let digits = ::std::str::from_utf8(digits).map_err(|_| self.error("invalid unicode escape"))?;
+        let value = u32::from_str_radix(digits, 16).map_err(|_| self.error("invalid unicode escape"))?;
+        self.position += 4;
+
+        Ok(value)
+    }
+
+    fn parse_string(&mut self) -> Result<String, JsonError> {
+        self.expect(b'"')?;
+        let mut bytes = vec![];
+        loop {
+            let c = match self.peek() {
+                Some(val) => val,
+                None => return Err(self.error("unterminated string")),
+            };
+            self.position += 1;
+            match c {
+                b'"' => break,
+                b'\\' => {
+                    let escape = match self.peek() {
+                        Some(val) => val,
+                        None => return Err(self.error("unterminated string")),
+                    };
+                    self.position += 1;
+                    match escape {
+                        b'"' => bytes.push(b'"'),
+                        b'\\' => bytes.push(b'\\'),
+                        b'/' => bytes.push(b'/'),
+                        b'b' => bytes.push(0x08),
+                        b'f' => bytes.push(0x0C),
+                        b'n' => bytes.push(b'\n'),
+                        b'r' => bytes.push(b'\r'),
+                        b't' => bytes.push(b'\t'),
+                        b'u' => {
+                            let mut code = self.parse_hex4()?;
+                            // Characters outside the basic multilingual plane are
+                            // escaped as a surrogate pair.
+                            if code >= 0xD800 && code < 0xDC00 {
+                                if !self.text[self.position..].starts_with(b"\\u") {
+                                    return Err(self.error("unpaired surrogate"));
+                                }
+                                self.position += 2;
+                                let low = self.parse_hex4()?;
+                                if low < 0xDC00 || low >= 0xE000 {
+                                    return Err(self.error("unpaired surrogate"));
+                                }
+                                code = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00);
+                            }
+                            let ch = match ::std::char::from_u32(code) {
+                                Some(val) => val,
+                                None => return Err(self.error("invalid unicode escape")),
+                            };
+                            let mut buffer = [0; 4];
+                            bytes.extend_from_slice(ch.encode_utf8(&mut buffer).as_bytes());
+                        }
+                        _ => return Err(self.error("invalid escape")),
+                    }
+                }
+                _ => bytes.push(c),
+            }
+        }
+
+        String::from_utf8(bytes).map_err(|_| self.error("invalid UTF-8 in string"))
+    }
+
+    fn parse_number(&mut self) -> Result<JsonValue, JsonError> {
+        let start = self.position;
+        while let Some(c) = self.peek() {
+            match c {
+                b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9' => self.position += 1,
+                _ => break,
+            }
+        }
+        let literal = ::std::str::from_utf8(&self.text[start..self.position]).unwrap_or("");
+        match literal.parse::<f64>() {
+            Ok(value) => Ok(JsonValue::Number(value)),
+            Err(_) => {
+                self.position = start;
+                Err(self.error("invalid number"))
+            }
+        }
+    }
+}
+
+/// Parse a JSON document.
+pub fn parse(text: &str) -> Result<JsonValue, JsonError> {
+    let mut parser = Parser { text: text.as_bytes(), position: 0 };
+    let value = parser.parse_value()?;
+    parser.skip_whitespace();
+    if parser.position < parser.text.len() {
+        return Err(parser.error("unexpected data after the document"));
+    }
+
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn test_parse_values() {
+        let value = parse(r#" { "a": [1, -2.5, 3e2], "b": true, "c": null, "d": "x" } "#).unwrap();
+        let a = value.get("a").and_then(|v| v.as_array()).unwrap();
+        assert_eq!(a, &[JsonValue::Number(1.0), JsonValue::Number(-2.5), JsonValue::Number(300.0)]);
+        assert_eq!(value.get("b").and_then(|v| v.as_bool()), Some(true));
+        assert_eq!(value.get("c"), Some(&JsonValue::Null));
+        assert_eq!(value.get("d").and_then(|v| v.as_str()), Some("x"));
+        assert_eq!(value.get("e"), None);
+    }
+
+    #[test]
+    fn test_parse_keeps_member_order() {
+        let value = parse(r#"{"z": 1, "a": 2, "m": 3}"#).unwrap();
+        let keys: Vec<&str> = value.as_object().unwrap().iter().map(|(key, _)| key.as_str()).collect();
+        assert_eq!(keys, vec!["z", "a", "m"]);
+    }
+
+    #[test]
+    fn test_parse_empty_containers() {
+        assert_eq!(parse("[ ]").unwrap(), JsonValue::Array(vec![]));
+        assert_eq!(parse("{ }").unwrap(), JsonValue::Object(vec![]));
+    }
+
+    #[test]
+    fn test_parse_string_escapes() {
+        let value = parse(r#""a\"b\\c\/d\n\té😀""#).unwrap();
+        assert_eq!(value.as_str(), Some("a\"b\\c/d\n\té\u{1F600}"));
+    }
+
+    #[test]
+    fn test_as_usize_rejects_fractions_and_negatives() {
+        assert_eq!(parse("3").unwrap().as_usize(), Some(3));
+        assert_eq!(parse("3.5").unwrap().as_usize(), None);
+        assert_eq!(parse("-1").unwrap().as_usize(), None);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse("").is_err());
+        assert!(parse("[1, 2").is_err());
+        assert!(parse(r#"{"a" 1}"#).is_err());
+        assert!(parse(r#""unterminated"#).is_err());
+        assert!(parse(r#""\ud83d""#).is_err());
+        assert!(parse(r#""\q""#).is_err());
+        assert!(parse("tru").is_err());
+        assert!(parse("1 2").is_err());
+        assert!(parse("-").is_err());
+    }
+
+    #[test]
+    fn test_parse_error_position() {
+        let e = parse("{\n  \"a\": 1,\n  \"b\": ?\n}").unwrap_err();
+        assert_eq!(e.line, 3);
+        assert_eq!(e.column, 8);
+    }
+}
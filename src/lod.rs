@@ -0,0 +1,540 @@
+/*
+ *  GB-Prototype is a renderer prototype demo.
+ *  Copyright (C) 2018,2019,2020  Christopher Blanchard
+ *
+ *  This program is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  This program is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+#![allow(dead_code)]
+//! Levels of detail. A simplifier based on quadric error metrics builds cheaper
+//! versions of a mesh, and a selector picks one per draw from how large the mesh
+//! appears on screen.
+use crate::bounds::BoundingSphere;
+use crate::mesh::{Indices, Mesh};
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+use log::info;
+
+
+/// The error quadric of a vertex: the sum of the squared distances to the planes of
+/// its triangles, as the upper triangle of a symmetric 4x4 matrix.
+#[derive(Copy, Clone, Debug, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    /// The quadric of the plane `a*x + b*y + c*z + d = 0`, weighted by `weight`.
+    fn from_plane(a: f64, b: f64, c: f64, d: f64, weight: f64) -> Quadric {
+        Quadric([
+            a * a * weight, a * b * weight, a * c * weight, a * d * weight,
+            b * b * weight, b * c * weight, b * d * weight,
+            c * c * weight, c * d * weight,
+            d * d * weight,
+        ])
+    }
+
+    fn add(&self, other: &Quadric) -> Quadric {
+        let mut sum = *self;
+        for (a, b) in sum.0.iter_mut().zip(other.0.iter()) {
+            *a += *b;
+        }
+
+        sum
+    }
+
+    /// The error of moving a vertex with this quadric to `p`.
+    fn error(&self, p: [f32; 3]) -> f64 {
+        let q = &self.0;
+        let (x, y, z) = (p[0] as f64, p[1] as f64, p[2] as f64);
+        let error = q[0] * x * x + 2_f64 * q[1] * x * y + 2_f64 * q[2] * x * z + 2_f64 * q[3] * x
+            + q[4] * y * y + 2_f64 * q[5] * y * z + 2_f64 * q[6] * y
+            + q[7] * z * z + 2_f64 * q[8] * z
+            + q[9];
+
+        f64::max(error, 0_f64)
+    }
+}
+
+/// A candidate edge collapse that moves vertex `from` onto vertex `to`. The
+/// versions tell whether either vertex changed since the cost was computed.
+#[derive(Copy, Clone, Debug)]
+struct Collapse {
+    cost: f64,
+    from: u32,
+    to: u32,
+    from_version: u32,
+    to_version: u32,
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Collapse) -> bool {
+        self.cost == other.cost
+    }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Collapse) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Collapse {
+    /// Reversed, so that the binary heap pops the cheapest collapse first.
+    fn cmp(&self, other: &Collapse) -> Ordering {
+        other.cost.partial_cmp(&self.cost).unwrap_or(Ordering::Equal)
+    }
+}
+
+#[inline]
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+#[inline]
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+#[inline]
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+/// The state of a simplification in progress.
+struct Simplifier<'a> {
+    points: &'a [[f32; 3]],
+    triangles: Vec<[u32; 3]>,
+    triangle_alive: Vec<bool>,
+    live_triangles: usize,
+    vertex_triangles: Vec<Vec<usize>>,
+    quadrics: Vec<Quadric>,
+    locked: Vec<bool>,
+    removed: Vec<bool>,
+    versions: Vec<u32>,
+    heap: BinaryHeap<Collapse>,
+}
+
+impl<'a> Simplifier<'a> {
+    fn new(points: &'a [[f32; 3]], corners: &[u32]) -> Simplifier<'a> {
+        let vertex_count = points.len();
+        let triangles: Vec<[u32; 3]> = corners.chunks_exact(3).map(|c| [c[0], c[1], c[2]]).collect();
+        let mut vertex_triangles = vec![vec![]; vertex_count];
+        let mut quadrics = vec![Quadric::default(); vertex_count];
+        let mut edges: HashMap<(u32, u32), u32> = HashMap::new();
+        for (t, triangle) in triangles.iter().enumerate() {
+            let (a, b, c) = (points[triangle[0] as usize], points[triangle[1] as usize], points[triangle[2] as usize]);
+            let n = cross(sub(b, a), sub(c, a));
+            let length = f32::sqrt(dot(n, n));
+            if length > 0_f32 {
+                let n = [n[0] / length, n[1] / length, n[2] / length];
+                // Weighting by area keeps large flat regions flat.
+                let quadric = Quadric::from_plane(
+                    n[0] as f64, n[1] as f64, n[2] as f64, -dot(n, a) as f64, length as f64 / 2_f64
+                );
+                for &index in triangle.iter() {
+                    quadrics[index as usize] = quadrics[index as usize].add(&quadric);
+                }
+            }
+            for k in 0..3 {
+                vertex_triangles[triangle[k] as usize].push(t);
+                let (u, v) = (triangle[k], triangle[(k + 1) % 3]);
+                *edges.entry((u32::min(u, v), u32::max(u, v))).or_insert(0) += 1;
+            }
+        }
+
+        // Lock the vertices of open edges. These are the borders of the mesh and
+        // its seams, where vertices are split to carry different texture
+        // coordinates or normals, so locking them keeps the seams intact. The
+        // corners must not refer to exact copies of a vertex, or the edges between
+        // the copies would count as seams too.
+        let mut locked = vec![false; vertex_count];
+        for (&(u, v), &count) in edges.iter() {
+            if count != 2 {
+                locked[u as usize] = true;
+                locked[v as usize] = true;
+            }
+        }
+
+        let live_triangles = triangles.len();
+        let mut simplifier = Simplifier {
+            points: points,
+            triangle_alive: vec![true; triangles.len()],
+            triangles: triangles,
+            live_triangles: live_triangles,
+            vertex_triangles: vertex_triangles,
+            quadrics: quadrics,
+            locked: locked,
+            removed: vec![false; vertex_count],
+            versions: vec![0; vertex_count],
+            heap: BinaryHeap::new(),
+        };
+        for &(u, v) in edges.keys() {
+            simplifier.push(u, v);
+            simplifier.push(v, u);
+        }
+
+        simplifier
+    }
+
+    fn push(&mut self, from: u32, to: u32) {
+        if self.locked[from as usize] {
+            return;
+        }
+        let quadric = self.quadrics[from as usize].add(&self.quadrics[to as usize]);
+        self.heap.push(Collapse {
+            cost: quadric.error(self.points[to as usize]),
+            from: from,
+            to: to,
+            from_version: self.versions[from as usize],
+            to_version: self.versions[to as usize],
+        });
+    }
+
+    /// The vertices that share a live triangle with a vertex.
+    fn neighbours(&self, vertex: u32) -> Vec<u32> {
+        let mut neighbours = vec![];
+        for &t in self.vertex_triangles[vertex as usize].iter().filter(|&&t| self.triangle_alive[t]) {
+            for &other in self.triangles[t].iter() {
+                if other != vertex && !neighbours.contains(&other) {
+                    neighbours.push(other);
+                }
+            }
+        }
+
+        neighbours
+    }
+
+    /// Determine whether a collapse keeps the surface manifold and keeps every
+    /// remaining triangle facing the same way.
+    fn can_collapse(&self, from: u32, to: u32) -> bool {
+        // The link condition: the only vertices next to both ends of the edge are
+        // the third vertices of the triangles on the edge.
+        let shared = self.vertex_triangles[from as usize].iter()
+            .filter(|&&t| self.triangle_alive[t] && self.triangles[t].contains(&to))
+            .count();
+        let from_neighbours = self.neighbours(from);
+        let common = self.neighbours(to).iter().filter(|v| from_neighbours.contains(v)).count();
+        if common != shared {
+            return false;
+        }
+
+        for &t in self.vertex_triangles[from as usize].iter() {
+            let triangle = self.triangles[t];
+            if !self.triangle_alive[t] || triangle.contains(&to) {
+                continue;
+            }
+            let corner = |index: u32| self.points[index as usize];
+            let moved = |index: u32| if index == from { corner(to) } else { corner(index) };
+            let before = cross(sub(corner(triangle[1]), corner(triangle[0])), sub(corner(triangle[2]), corner(triangle[0])));
+            let after = cross(sub(moved(triangle[1]), moved(triangle[0])), sub(moved(triangle[2]), moved(triangle[0])));
+            if dot(before, after) <= 0_f32 {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    fn collapse(&mut self, from: u32, to: u32) {
+        let triangles = ::std::mem::take(&mut self.vertex_triangles[from as usize]);
+        for &t in triangles.iter() {
+            if !self.triangle_alive[t] {
+                continue;
+            }
+            if self.triangles[t].contains(&to) {
+                self.triangle_alive[t] = false;
+                self.live_triangles -= 1;
+            } else {
+                for index in self.triangles[t].iter_mut() {
+                    if *index == from {
+                        *index = to;
+                    }
+                }
+                self.vertex_triangles[to as usize].push(t);
+            }
+        }
+        self.removed[from as usize] = true;
+        self.quadrics[to as usize] = self.quadrics[to as usize].add(&self.quadrics[from as usize]);
+        self.versions[to as usize] += 1;
+
+        for neighbour in self.neighbours(to) {
+            self.push(neighbour, to);
+            self.push(to, neighbour);
+        }
+    }
+
+    /// Collapse edges, cheapest first, until at most `target` triangles are left
+    /// or the next collapse costs more than `max_error`.
+    fn run(&mut self, target: usize, max_error: f64) {
+        while self.live_triangles > target {
+            let candidate = match self.heap.pop() {
+                Some(val) => val,
+                None => break,
+            };
+            let (from, to) = (candidate.from as usize, candidate.to as usize);
+            if self.removed[from] || self.removed[to]
+                || self.versions[from] != candidate.from_version
+                || self.versions[to] != candidate.to_version {
+                continue;
+            }
+            if candidate.cost > max_error {
+                break;
+            }
+            if self.can_collapse(candidate.from, candidate.to) {
+                self.collapse(candidate.from, candidate.to);
+            }
+        }
+    }
+
+    fn corners(&self) -> Vec<u32> {
+        self.triangles.iter().zip(self.triangle_alive.iter())
+            .filter(|&(_, &alive)| alive)
+            .flat_map(|(triangle, _)| triangle.iter().cloned())
+            .collect()
+    }
+}
+
+/// Map every vertex to the first vertex with exactly the same position and
+/// attributes.
+fn first_copies(mesh: &Mesh) -> Vec<u32> {
+    let mut first: HashMap<Vec<u8>, u32> = HashMap::new();
+    (0..mesh.len()).map(|vertex| {
+        let key: Vec<u8> = mesh.attributes().iter().flat_map(|attribute| {
+            let size = attribute.format().size();
+            attribute.data.as_bytes()[vertex * size..(vertex + 1) * size].iter().cloned()
+        }).collect();
+        *first.entry(key).or_insert(vertex as u32)
+    }).collect()
+}
+
+/// Simplify a mesh to at most `target_triangles` triangles by collapsing edges in
+/// order of their quadric error, stopping early when a collapse would cost more
+/// than `max_error`, a squared distance in model units. Collapses move a vertex onto
+/// one of its neighbours, so the remaining vertices keep their attributes. Vertices
+/// on borders and on seams, where attributes differ across an edge, stay put. Exact
+/// copies of a vertex are merged first, but copies that differ slightly count as a
+/// seam, so weld a triangle soup before simplifying it.
+pub fn simplify(mesh: &Mesh, target_triangles: usize, max_error: f32) -> Mesh {
+    let copies = first_copies(mesh);
+    let corners: Vec<u32> = mesh.triangle_corners().iter().map(|&index| copies[index as usize]).collect();
+    let mut simplifier = Simplifier::new(mesh.points(), &corners);
+    simplifier.run(target_triangles, max_error as f64);
+
+    let mut simplified = mesh.clone();
+    simplified.indices = Some(Indices::new(&simplifier.corners()));
+    simplified.optimize_vertex_fetch();
+
+    simplified
+}
+
+/// Build a chain of levels of detail, one for each ratio of the triangle count of
+/// the mesh, e.g. `[1.0, 0.5, 0.25]`. Each level is simplified from the one before.
+/// A level can keep more triangles than its ratio asks for when the borders and
+/// seams of the mesh leave nothing else to collapse.
+pub fn generate_lods(mesh: &Mesh, ratios: &[f32]) -> Vec<Mesh> {
+    let triangle_count = mesh.draw_count() / 3;
+    let mut levels: Vec<Mesh> = Vec::with_capacity(ratios.len());
+    for &ratio in ratios.iter() {
+        let target = f32::ceil(triangle_count as f32 * ratio) as usize;
+        let level = {
+            let source = levels.last().unwrap_or(mesh);
+            if ratio >= 1_f32 {
+                source.clone()
+            } else {
+                simplify(source, target, f32::INFINITY)
+            }
+        };
+        let level_triangles = level.draw_count() / 3;
+        if level_triangles > target {
+            info!(
+                "Level of detail {} has {} triangles, more than the {} asked for by its ratio {}",
+                levels.len(), level_triangles, target, ratio
+            );
+        }
+        levels.push(level);
+    }
+
+    levels
+}
+
+/// The fraction of the screen height covered by a sphere, from a column major
+/// view projection matrix. Returns infinity for a sphere around the camera.
+pub fn projected_size(view_projection: &[f32; 16], sphere: &BoundingSphere) -> f32 {
+    let m = view_projection;
+    let c = sphere.center;
+    // The clip space w is the distance in front of the camera.
+    let w = m[3] * c[0] + m[7] * c[1] + m[11] * c[2] + m[15];
+    if w <= sphere.radius {
+        return f32::INFINITY;
+    }
+    // The vertical scale of the projection is the length of the y row, since
+    // the view matrix only rotates and translates.
+    let scale = f32::sqrt(m[1] * m[1] + m[5] * m[5] + m[9] * m[9]);
+
+    sphere.radius * scale / w
+}
+
+/// Picks a level of detail from the projected size of a mesh.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LodSelector {
+    /// The smallest projected size for each level but the last, in decreasing
+    /// order. Anything smaller than every threshold uses the last level.
+    pub thresholds: Vec<f32>,
+    /// How far past a threshold the size must move before the level changes, as
+    /// a fraction of the threshold. This stops objects near a threshold from
+    /// flickering between levels. Zero turns it off.
+    pub hysteresis: f32,
+}
+
+impl LodSelector {
+    pub fn new(thresholds: Vec<f32>, hysteresis: f32) -> LodSelector {
+        LodSelector {
+            thresholds: thresholds,
+            hysteresis: hysteresis,
+        }
+    }
+
+    #[inline]
+    fn level_for(&self, size: f32) -> usize {
+        self.thresholds.iter().position(|&threshold| size >= threshold).unwrap_or(self.thresholds.len())
+    }
+
+    /// Select the level for a projected size. `previous` is the level the object
+    /// had last frame, if any.
+    pub fn select(&self, size: f32, previous: Option<usize>) -> usize {
+        let previous = match previous {
+            Some(val) => usize::min(val, self.thresholds.len()),
+            None => return self.level_for(size),
+        };
+        let finer = self.level_for(size / (1_f32 + self.hysteresis));
+        if finer < previous {
+            return finer;
+        }
+        let coarser = self.level_for(size / f32::max(1_f32 - self.hysteresis, 1e-6));
+        if coarser > previous {
+            return coarser;
+        }
+
+        previous
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives;
+
+
+    /// The positions of the vertices on the texture seam of a UV sphere, where the
+    /// texture coordinates wrap from 1 back to 0, that a triangle still uses.
+    fn used_seam_points(mesh: &Mesh) -> Vec<[f32; 3]> {
+        let (points, tex_coords) = (mesh.points(), mesh.tex_coords());
+        let mut seam: Vec<[f32; 3]> = mesh.triangle_corners().iter()
+            .filter(|&&i| tex_coords[i as usize][0] == 0_f32 || tex_coords[i as usize][0] == 1_f32)
+            .map(|&i| points[i as usize])
+            .collect();
+        seam.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        seam.dedup();
+
+        seam
+    }
+
+    #[test]
+    fn test_simplify_uv_sphere_reaches_target() {
+        let sphere = primitives::uv_sphere(1_f32, 32, 16);
+        let triangle_count = sphere.draw_count() / 3;
+        for &target in [triangle_count / 2, triangle_count / 4].iter() {
+            let simplified = simplify(&sphere, target, f32::INFINITY);
+
+            assert_eq!(simplified.draw_count() / 3, target);
+            assert_eq!(simplified.validate(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_simplify_keeps_seam_vertices() {
+        let sphere = primitives::uv_sphere(1_f32, 32, 16);
+        let seam = used_seam_points(&sphere);
+        let simplified = simplify(&sphere, sphere.draw_count() / 3 / 4, f32::INFINITY);
+
+        assert!(seam.len() > 2);
+        assert_eq!(used_seam_points(&simplified), seam);
+    }
+
+    #[test]
+    fn test_simplify_stops_at_max_error() {
+        let grid = primitives::plane_grid(4_f32, 4_f32, 8, 8);
+        let flat = simplify(&grid, 0, 1e-6);
+        assert!(flat.draw_count() < grid.draw_count());
+
+        let sphere = primitives::uv_sphere(1_f32, 32, 16);
+        let unchanged = simplify(&sphere, 0, 0_f32);
+        assert_eq!(unchanged.draw_count(), sphere.draw_count());
+    }
+
+    #[test]
+    fn test_generate_lods() {
+        let sphere = primitives::uv_sphere(1_f32, 32, 16);
+        let triangle_count = sphere.draw_count() / 3;
+        let levels = generate_lods(&sphere, &[1_f32, 0.5, 0.25]);
+
+        assert_eq!(levels.len(), 3);
+        assert_eq!(levels[0], sphere);
+        assert_eq!(levels[1].draw_count() / 3, triangle_count / 2);
+        assert_eq!(levels[2].draw_count() / 3, triangle_count / 4);
+    }
+
+    #[test]
+    fn test_select_without_previous_level() {
+        let selector = LodSelector::new(vec![0.5, 0.25], 0.1);
+
+        assert_eq!(selector.select(0.6, None), 0);
+        assert_eq!(selector.select(0.5, None), 0);
+        assert_eq!(selector.select(0.48, None), 1);
+        assert_eq!(selector.select(0.25, None), 1);
+        assert_eq!(selector.select(0.1, None), 2);
+        assert_eq!(selector.select(f32::INFINITY, None), 0);
+    }
+
+    #[test]
+    fn test_select_hysteresis() {
+        let selector = LodSelector::new(vec![0.5, 0.25], 0.1);
+
+        // Sizes that wobble inside the band around a threshold keep the level.
+        let mut level = 0;
+        for &size in [0.48, 0.52, 0.46, 0.51, 0.455].iter() {
+            level = selector.select(size, Some(level));
+            assert_eq!(level, 0, "size {}", size);
+        }
+        // Past the band the level switches once and then stays.
+        level = selector.select(0.44, Some(level));
+        assert_eq!(level, 1);
+        for &size in [0.44, 0.52, 0.48, 0.54].iter() {
+            level = selector.select(size, Some(level));
+            assert_eq!(level, 1, "size {}", size);
+        }
+        assert_eq!(selector.select(0.56, Some(level)), 0);
+    }
+
+    #[test]
+    fn test_select_jumps_several_levels() {
+        let selector = LodSelector::new(vec![0.5, 0.25], 0.1);
+
+        assert_eq!(selector.select(0.01, Some(0)), 2);
+        assert_eq!(selector.select(1_f32, Some(2)), 0);
+        assert_eq!(selector.select(0.3, Some(5)), 1);
+    }
+}
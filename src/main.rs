@@ -22,28 +22,50 @@ extern crate log;
 extern crate rand;
 extern crate file_logger;
 extern crate teximage2d;
+extern crate png;
 
 
 mod gl {
     include!(concat!(env!("OUT_DIR"), "/gl_bindings.rs"));
 }
 
+mod animation;
+mod bounds;
+mod capture;
+mod dynamic_buffer;
+mod error;
 mod gl_backend;
+mod gl_objects;
+mod glsl;
+mod golden;
+mod gltf;
+mod input;
+mod json;
+mod lod;
 mod mesh;
-
+mod mesh_file;
+mod normals;
+mod obj;
+mod optimize;
+mod primitives;
+mod renderer;
+mod replay;
+mod vertex_layout;
+
+use error::Error;
 use gl_backend as glh;
-use mesh::Mesh;
+use gl_objects::{BufferObject, Program, Texture, VertexArray};
+use input::{ActionMap, Input};
+use mesh::{Mesh, MeshError};
+use renderer::ShaderSource;
+use replay::{InputPlayback, InputRecorder, InputSource, ReplayError};
+use vertex_layout::VertexLayout;
 
 use cgmath::{
     Array, 
     Matrix4,
     One
 };
-use glfw::{
-    Action, 
-    Context, 
-    Key
-};
 use gl::types::{
     GLfloat, 
     GLint, 
@@ -53,7 +75,7 @@ use gl::types::{
     GLsizei,
     GLenum,
 };
-use log::{info};
+use log::{info, error};
 use teximage2d::TexImage2D;
 
 use std::io;
@@ -65,15 +87,24 @@ const GL_MAX_TEXTURE_MAX_ANISOTROPY_EXT: u32 = 0x84FF;
 
 
 /// Load texture image into the GPU.
-fn send_to_gpu_texture(image: &TexImage2D, wrapping_mode: GLuint) -> Result<GLuint, String> {
-    let mut tex = 0;
+fn send_to_gpu_texture(
+    context: &glh::GLState, image: &TexImage2D, wrapping_mode: GLuint) -> Result<Texture, Error> {
+
+    let mut max_size = 0;
     unsafe {
-        gl::GenTextures(1, &mut tex);
+        gl::GetIntegerv(gl::MAX_TEXTURE_SIZE, &mut max_size);
+    }
+    if image.width > max_size as u32 || image.height > max_size as u32 {
+        return Err(Error::TextureUploadFailed(format!(
+            "The image is {}x{} but the largest supported texture is {}x{}",
+            image.width, image.height, max_size, max_size
+        )));
     }
-    debug_assert!(tex > 0);
+
+    let tex = Texture::new(context, gl::TEXTURE_2D);
     unsafe {
         gl::ActiveTexture(gl::TEXTURE0);
-        gl::BindTexture(gl::TEXTURE_2D, tex);
+        tex.bind();
         gl::TexImage2D(
             gl::TEXTURE_2D, 0, gl::RGBA as i32, image.width as i32, image.height as i32, 0,
             gl::RGBA, gl::UNSIGNED_BYTE,
@@ -85,39 +116,40 @@ fn send_to_gpu_texture(image: &TexImage2D, wrapping_mode: GLuint) -> Result<GLui
         gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as GLint);
         gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR_MIPMAP_LINEAR as GLint);
     }
+    match glh::take_gl_error() {
+        Some(gl::OUT_OF_MEMORY) => return Err(Error::OutOfMemory(String::from("a texture"))),
+        Some(code) => {
+            return Err(Error::TextureUploadFailed(format!("Got GL error 0x{:X}", code)));
+        }
+        None => {}
+    }
 
-    let mut max_aniso = 0.0;
-    unsafe {
-        gl::GetFloatv(GL_MAX_TEXTURE_MAX_ANISOTROPY_EXT, &mut max_aniso);
-        // Set the maximum!
-        gl::TexParameterf(gl::TEXTURE_2D, GL_TEXTURE_MAX_ANISOTROPY_EXT, max_aniso);
+    if context.features.anisotropic_filtering {
+        let mut max_aniso = 0.0;
+        unsafe {
+            gl::GetFloatv(GL_MAX_TEXTURE_MAX_ANISOTROPY_EXT, &mut max_aniso);
+            // Set the maximum!
+            gl::TexParameterf(gl::TEXTURE_2D, GL_TEXTURE_MAX_ANISOTROPY_EXT, max_aniso);
+        }
     }
 
     Ok(tex)
 }
 
-#[derive(Copy, Clone)]
-struct ShaderSource {
-    vert_name: &'static str,
-    vert_source: &'static str,
-    frag_name: &'static str,
-    frag_source: &'static str,
-}
-
-fn send_to_gpu_shaders(game: &mut glh::GLState, source: ShaderSource) -> GLuint {
+fn send_to_gpu_shaders(game: &mut glh::GLState, source: ShaderSource) -> Result<Program, Error> {
     let mut vert_reader = io::Cursor::new(source.vert_source);
     let mut frag_reader = io::Cursor::new(source.frag_source);
     let sp = glh::create_program_from_reader(
         game,
         &mut vert_reader, source.vert_name,
         &mut frag_reader, source.frag_name
-    ).unwrap();
-    debug_assert!(sp > 0);
+    )?;
+    debug_assert!(sp.name() > 0);
 
-    sp
+    Ok(sp)
 }
 
-fn create_mesh_triangle(h: f32) -> Mesh {
+fn create_mesh_triangle(h: f32) -> Result<Mesh, MeshError> {
     let a = f32::sqrt(4_f32 * h * h / 3_f32);
     let half_a = a / 2_f32;
     let half_h = h / 2_f32;
@@ -129,8 +161,9 @@ fn create_mesh_triangle(h: f32) -> Mesh {
     let tex_coords = [
         [0_f32, 0_f32], [1_f32, 0_f32], [0.5, 1_f32],
     ];
+    let indices = [0, 1, 2];
 
-    Mesh::new(&points, &tex_coords)
+    Mesh::try_new_indexed(&points, &tex_coords, &indices)
 }
 
 fn create_shaders_triangle() -> ShaderSource {
@@ -150,90 +183,54 @@ fn create_textures_triangle() -> TexImage2D {
     teximage2d::load_from_memory(asset).unwrap().image
 }
 
-#[derive(Copy, Clone)]
+/// The GPU buffers of a mesh.
 struct Handle {
-    vao: GLuint,
-    v_pos_vbo: GLuint,
-    v_tex_vbo: GLuint,
-    v_pos_loc: GLuint,
-    v_tex_loc: GLuint,
+    vao: VertexArray,
+    /// The vertex buffer holding every attribute, interleaved.
+    vbo: BufferObject,
+    ebo: BufferObject,
+    /// The type of the indices, or `None` for a mesh without an index array.
+    index_type: Option<GLenum>,
+    draw_count: usize,
 }
 
-fn create_buffers_triangle(sp: GLuint) -> Handle {
-    let v_pos_loc = unsafe {
-        gl::GetAttribLocation(sp, glh::gl_str("v_pos").as_ptr())
-    };
-    debug_assert!(v_pos_loc > -1);
-    let v_pos_loc = v_pos_loc as GLuint;
-    
-    let v_tex_loc = unsafe {
-        gl::GetAttribLocation(sp, glh::gl_str("v_tex").as_ptr())
-    };
-    debug_assert!(v_tex_loc > -1);
-    let v_tex_loc = v_tex_loc as GLuint;
-
-    let mut v_pos_vbo = 0;
-    unsafe {
-        gl::GenBuffers(1, &mut v_pos_vbo);
-    }
-    debug_assert!(v_pos_vbo > 0);
-
-    let mut v_tex_vbo = 0;
-    unsafe {
-        gl::GenBuffers(1, &mut v_tex_vbo);
-    }
-    debug_assert!(v_tex_vbo > 0);
-
-    let mut vao = 0;
-    unsafe {
-        gl::GenVertexArrays(1, &mut vao);
-    }
-    debug_assert!(vao > 0);
-    unsafe {
-        gl::BindVertexArray(vao);
-        gl::BindBuffer(gl::ARRAY_BUFFER, v_pos_vbo);
-        gl::VertexAttribPointer(v_pos_loc, 3, gl::FLOAT, gl::FALSE, 0, ptr::null());
-        gl::BindBuffer(gl::ARRAY_BUFFER, v_tex_vbo);
-        gl::VertexAttribPointer(v_tex_loc, 2, gl::FLOAT, gl::FALSE, 0, ptr::null());
-        gl::EnableVertexAttribArray(v_pos_loc);
-        gl::EnableVertexAttribArray(v_tex_loc);
-    }
-
+fn create_buffers_triangle(context: &glh::GLState) -> Handle {
     Handle {
-        vao: vao,
-        v_pos_vbo: v_pos_vbo,
-        v_tex_vbo: v_tex_vbo,
-        v_pos_loc: v_pos_loc,
-        v_tex_loc: v_tex_loc,
+        vao: VertexArray::new(context),
+        vbo: BufferObject::new(context),
+        ebo: BufferObject::new(context),
+        index_type: None,
+        draw_count: 0,
     }
 }
 
-fn send_to_gpu_geometry_triangle(handle: Handle, mesh: &Mesh) {
+/// Load the attributes and indices of a mesh into the buffers of a handle. The
+/// attributes are interleaved into one vertex buffer, and each feeds the shader
+/// input named after its semantic.
+fn send_to_gpu_geometry(handle: &mut Handle, sp: &Program, mesh: &Mesh) {
+    let layout = VertexLayout::from_mesh(mesh);
+    let vertices = vertex_layout::pack_vertices(mesh, &layout);
+    vertex_layout::setup_vertex_array(&handle.vao, &handle.vbo, sp.name(), &layout, &vertices);
+
+    // Load the index data. The element array buffer binding is part of the vertex
+    // array state, so this relies on the vertex array still being bound.
+    if let Some(indices) = mesh.indices() {
+        unsafe {
+            handle.ebo.bind(gl::ELEMENT_ARRAY_BUFFER);
+            gl::BufferData(
+                gl::ELEMENT_ARRAY_BUFFER,
+                indices.len_bytes() as GLsizeiptr,
+                indices.as_ptr() as *const GLvoid, gl::STATIC_DRAW
+            );
+        }
+        handle.index_type = Some(indices.gl_type());
+    } else {
+        handle.index_type = None;
+    }
     unsafe {
-        // Load position data.
-        gl::BindBuffer(gl::ARRAY_BUFFER, handle.v_pos_vbo);
-        gl::BufferData(
-            gl::ARRAY_BUFFER,
-            mesh.points.len_bytes() as GLsizeiptr,
-            mesh.points.as_ptr() as *const GLvoid, gl::STATIC_DRAW
-        );
-        // Load the texture coordinates.
-        gl::BindBuffer(gl::ARRAY_BUFFER, handle.v_tex_vbo);
-        gl::BufferData(
-            gl::ARRAY_BUFFER,
-            mesh.tex_coords.len_bytes() as GLsizeiptr,
-            mesh.tex_coords.as_ptr() as *const GLvoid, gl::STATIC_DRAW
-        );
-
-        // Enable the arrays for use by the shader.
-        gl::BindVertexArray(handle.vao);
-        gl::BindBuffer(gl::ARRAY_BUFFER, handle.v_pos_vbo);
-        gl::VertexAttribPointer(handle.v_pos_loc, 3, gl::FLOAT, gl::FALSE, 0, ptr::null());
-        gl::BindBuffer(gl::ARRAY_BUFFER, handle.v_tex_vbo);
-        gl::VertexAttribPointer(handle.v_tex_loc, 2, gl::FLOAT, gl::FALSE, 0, ptr::null());
-        gl::EnableVertexAttribArray(handle.v_pos_loc);
-        gl::EnableVertexAttribArray(handle.v_tex_loc);
+        gl::BindVertexArray(0);
     }
+    handle.draw_count = mesh.draw_count();
 }
 
 fn send_to_gpu_uniforms_triangle(sp: GLuint, trans_mat: Matrix4, scale_mat: Matrix4) {
@@ -253,42 +250,336 @@ fn send_to_gpu_uniforms_triangle(sp: GLuint, trans_mat: Matrix4, scale_mat: Matr
 }
 
 
+fn create_input_bindings() -> ActionMap {
+    let bindings = include_str!("../config/input.toml");
+    ActionMap::parse(bindings).unwrap()
+}
+
+/// The GPU state of the marble textured triangle scene. Dropping the scene frees
+/// its GPU resources.
+struct SceneTriangle {
+    sp: Program,
+    handle: Handle,
+    tex: Texture,
+    trans_mat: Matrix4,
+    scale_mat: Matrix4,
+}
+
+/// Load the components of the triangle scene and set them up on the GPU.
+fn load_scene_triangle(gl: &mut glh::GLState) -> Result<SceneTriangle, Error> {
+    let shaders = create_shaders_triangle();
+    let mesh = create_mesh_triangle(1_f32)?;
+    let image = create_textures_triangle();
+
+    let sp = send_to_gpu_shaders(gl, shaders)?;
+    if !gl_backend::validate_shader_program(sp.name()) {
+        info!("Got invalid shader program.");
+        info!("{}", gl_backend::program_info_log(sp.name()));
+        info!("{}", gl_backend::shader_info_log(sp.name()));
+    }
+
+    let mut handle = create_buffers_triangle(gl);
+    send_to_gpu_geometry(&mut handle, &sp, &mesh);
+    let tex = send_to_gpu_texture(gl, &image, gl::CLAMP_TO_EDGE)?;
+    let trans_mat = Matrix4::one();
+    let scale_mat = Matrix4::one();
+    send_to_gpu_uniforms_triangle(sp.name(), trans_mat, scale_mat);
+
+    Ok(SceneTriangle {
+        sp: sp,
+        handle: handle,
+        tex: tex,
+        trans_mat: trans_mat,
+        scale_mat: scale_mat,
+    })
+}
+
+/// Draw one frame of the triangle scene.
+fn render_scene_triangle(gl: &glh::GLState, scene: &SceneTriangle) {
+    unsafe {
+        gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+        gl::ClearColor(0.3, 0.3, 0.3, 1.0);
+        gl::Viewport(0, 0, gl.width as GLint, gl.height as GLint);
+    }
+
+    // Update the GPU.
+    send_to_gpu_uniforms_triangle(scene.sp.name(), scene.trans_mat, scene.scale_mat);
+
+    // Render the results.
+    unsafe {
+        scene.sp.bind();
+        gl::Enable(gl::DEPTH_TEST);
+        gl::DepthFunc(gl::LESS);
+        gl::ActiveTexture(gl::TEXTURE0);
+        scene.tex.bind();
+        scene.handle.vao.bind();
+        match scene.handle.index_type {
+            Some(index_type) => gl::DrawElements(
+                gl::TRIANGLES, scene.handle.draw_count as GLsizei, index_type, ptr::null()
+            ),
+            None => gl::DrawArrays(gl::TRIANGLES, 0, scene.handle.draw_count as GLsizei),
+        }
+    }
+}
+
+/// Render a named scene for the golden image harness. Returns `Ok(false)` if there
+/// is no scene with that name.
+fn render_golden_scene(scene: &str, gl: &mut glh::GLState, frames: u32) -> Result<bool, Error> {
+    match scene {
+        "marble_triangle" => {
+            let triangle = load_scene_triangle(gl)?;
+            for _ in 0..frames {
+                render_scene_triangle(gl, &triangle);
+                glh::present(gl);
+            }
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+/// Run every golden image case in a directory. Returns `true` if all of them passed.
+fn run_golden_cases(options: &Options, dir: &str) -> bool {
+    let cases = match golden::load_cases(dir) {
+        Ok(val) => val,
+        Err(e) => {
+            error!("{}", e);
+            println!("{}", e);
+            return false;
+        }
+    };
+
+    let mut all_passed = true;
+    for case in cases.iter() {
+        let outcome = golden::run_case(
+            case, &options.golden_output, options.golden_bless, render_golden_scene
+        );
+        match outcome {
+            Ok(golden::GoldenOutcome::Passed(comparison)) => {
+                println!("PASS {} ({:.3}% different)", case.name, comparison.percent_different());
+            }
+            Ok(golden::GoldenOutcome::Failed(comparison)) => {
+                println!(
+                    "FAIL {} ({:.3}% different, threshold {:.3}%). See {}",
+                    case.name, comparison.percent_different(), case.threshold, options.golden_output
+                );
+                all_passed = false;
+            }
+            Ok(golden::GoldenOutcome::Blessed) => {
+                println!("BLESSED {}", case.name);
+            }
+            Err(e) => {
+                error!("{}", e);
+                println!("ERROR {}: {}", case.name, e);
+                all_passed = false;
+            }
+        }
+    }
+
+    all_passed
+}
+
+/// The command line usage of the demo.
+const USAGE: &str = "\
+Usage: renderer [OPTIONS]
+
+Options:
+    --headless               Render offscreen without showing a window
+    --frames COUNT           Exit after rendering COUNT frames, at least one
+    --capture-frame FRAME    Capture frame FRAME to the capture path and exit
+    --capture-path PATH      The file to write a frame capture to
+    --golden DIR             Run the golden image cases in DIR
+    --golden-bless           Overwrite the golden reference images
+    --golden-output DIR      The directory to write the images of failed cases to
+    --record PATH            Record every input event to PATH
+    --replay PATH            Play back the input events from PATH";
+
+/// Report a bad command line and exit with status 2.
+fn exit_with_usage(message: &str) -> ! {
+    eprintln!("{}", message);
+    eprintln!();
+    eprintln!("{}", USAGE);
+    std::process::exit(2);
+}
+
+/// The command line options for the demo.
+#[derive(Clone, Debug)]
+struct Options {
+    /// Render offscreen without showing a window.
+    headless: bool,
+    /// Exit after rendering this many frames.
+    max_frames: Option<u32>,
+    /// Capture this frame to `capture_path` and exit.
+    capture_frame: Option<u32>,
+    /// The file to write a frame capture to.
+    capture_path: String,
+    /// Run the golden image cases in this directory instead of the demo.
+    golden_dir: Option<String>,
+    /// Overwrite the golden reference images with the rendered images.
+    golden_bless: bool,
+    /// The directory to write the images of failed golden image cases to.
+    golden_output: String,
+    /// Record every input event to this file.
+    record_path: Option<String>,
+    /// Play back the input events from this file instead of reading them from GLFW.
+    replay_path: Option<String>,
+}
+
+impl Options {
+    /// Parse the command line options from the program arguments. A headless
+    /// run renders a single frame unless told otherwise. A bad command line prints
+    /// the usage and exits.
+    fn parse<I: Iterator<Item = String>>(mut args: I) -> Options {
+        let mut headless = false;
+        let mut max_frames = None;
+        let mut capture_frame = None;
+        let mut capture_path = String::from("capture.png");
+        let mut golden_dir = None;
+        let mut golden_bless = false;
+        let mut golden_output = String::from("target/golden");
+        let mut record_path = None;
+        let mut replay_path = None;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--headless" => {
+                    headless = true;
+                }
+                "--frames" => {
+                    max_frames = match args.next().and_then(|s| s.parse::<u32>().ok()) {
+                        Some(frames) if frames > 0 => Some(frames),
+                        _ => exit_with_usage("--frames requires a frame count of at least one"),
+                    };
+                }
+                "--capture-frame" => {
+                    capture_frame = match args.next().and_then(|s| s.parse::<u32>().ok()) {
+                        Some(frame) => Some(frame),
+                        None => exit_with_usage("--capture-frame requires a frame number"),
+                    };
+                }
+                "--capture-path" => {
+                    capture_path = match args.next() {
+                        Some(path) => path,
+                        None => exit_with_usage("--capture-path requires a file name"),
+                    };
+                }
+                "--golden" => {
+                    golden_dir = match args.next() {
+                        Some(dir) => Some(dir),
+                        None => exit_with_usage("--golden requires a directory"),
+                    };
+                }
+                "--golden-bless" => {
+                    golden_bless = true;
+                }
+                "--golden-output" => {
+                    golden_output = match args.next() {
+                        Some(dir) => dir,
+                        None => exit_with_usage("--golden-output requires a directory"),
+                    };
+                }
+                "--record" => {
+                    record_path = match args.next() {
+                        Some(path) => Some(path),
+                        None => exit_with_usage("--record requires a file name"),
+                    };
+                }
+                "--replay" => {
+                    replay_path = match args.next() {
+                        Some(path) => Some(path),
+                        None => exit_with_usage("--replay requires a file name"),
+                    };
+                }
+                _ => {
+                    exit_with_usage(&format!("Unrecognized command line argument: {}", arg));
+                }
+            }
+        }
+        if record_path.is_some() && replay_path.is_some() {
+            exit_with_usage("--record and --replay cannot be used together");
+        }
+        if let Some(frame) = capture_frame {
+            max_frames = match frame.checked_add(1) {
+                Some(frames) => Some(frames),
+                None => exit_with_usage(&format!("--capture-frame requires a frame number below {}", u32::MAX)),
+            };
+        }
+        if headless && max_frames.is_none() {
+            max_frames = Some(1);
+        }
+
+        Options {
+            headless: headless,
+            max_frames: max_frames,
+            capture_frame: capture_frame,
+            capture_path: capture_path,
+            golden_dir: golden_dir,
+            golden_bless: golden_bless,
+            golden_output: golden_output,
+            record_path: record_path,
+            replay_path: replay_path,
+        }
+    }
+}
+
 /// Initialize the logger.
 fn init_logger(log_file: &str) {
     file_logger::init(log_file).expect("Failed to initialize logger.");
 }
 
-/// Create and OpenGL context.
-fn init_gl(width: u32, height: u32) -> glh::GLState {
-    let gl_state = match glh::start_gl(width, height) {
+/// Create and OpenGL context. Exits the process if there is no usable context.
+fn init_gl(width: u32, height: u32, mode: glh::ContextMode) -> glh::GLState {
+    let gl_state = match glh::start_gl(width, height, mode) {
         Ok(val) => val,
         Err(e) => {
-            panic!("Failed to Initialize OpenGL context. Got error: {}", e);
+            error!("Failed to Initialize OpenGL context. Got error: {}", e);
+            eprintln!("Failed to Initialize OpenGL context. Got error: {}", e);
+            info!("END LOG");
+            std::process::exit(1);
         }
     };
 
     gl_state
 }
 
+/// Report an error from recording or replaying input, and exit.
+fn exit_with_input_error(e: ReplayError) -> ! {
+    error!("Failed to record or replay input. Got error: {}", e);
+    eprintln!("Failed to record or replay input. Got error: {}", e);
+    info!("END LOG");
+    std::process::exit(1);
+}
+
 fn main() {
+    let options = Options::parse(std::env::args().skip(1));
     init_logger("gb_prototype.log");
     info!("BEGIN LOG");
     info!("build version: ??? ?? ???? ??:??:??");
 
-    // Load the components of the scene.
-    let shaders = create_shaders_triangle();
-    let mesh = create_mesh_triangle(1_f32);
-    let image = create_textures_triangle();
-    let mut gl = init_gl(640, 480);
-
-    // Set them up on the GPU.
-    let sp = send_to_gpu_shaders(&mut gl, shaders);
-    if !gl_backend::validate_shader_program(sp) {
-        info!("Got invalid shader program.");
-        info!("{}", gl_backend::program_info_log(sp));
-        info!("{}", gl_backend::shader_info_log(sp));
+    if let Some(ref dir) = options.golden_dir {
+        let passed = run_golden_cases(&options, dir);
+        info!("END LOG");
+        std::process::exit(if passed { 0 } else { 1 });
     }
 
+    let mode = if options.headless {
+        glh::ContextMode::Headless
+    } else {
+        glh::ContextMode::Windowed
+    };
+    let mut gl = init_gl(640, 480, mode);
+
+    // Load the components of the scene and set them up on the GPU.
+    let scene = match load_scene_triangle(&mut gl) {
+        Ok(val) => val,
+        Err(e) => {
+            error!("Failed to load the scene. Got error: {}", e);
+            eprintln!("Failed to load the scene. Got error: {}", e);
+            info!("END LOG");
+            std::process::exit(1);
+        }
+    };
+    let sp = scene.sp.name();
+
     let mut num_active_attribs = 0;
     let mut num_active_uniforms = 0;
     unsafe {
@@ -330,43 +621,68 @@ fn main() {
         println!("{}", array_size);
     }
 
-    let handle = create_buffers_triangle(sp);
-    send_to_gpu_geometry_triangle(handle, &mesh);
-    let tex = send_to_gpu_texture(&image, gl::CLAMP_TO_EDGE).unwrap();
-    let trans_mat = Matrix4::one();
-    let scale_mat = Matrix4::one();
-    send_to_gpu_uniforms_triangle(sp, trans_mat, scale_mat);
-
+    let mut input = Input::new(create_input_bindings());
+    let mut input_source = if let Some(ref path) = options.record_path {
+        match InputRecorder::create(path) {
+            Ok(recorder) => InputSource::Record(recorder),
+            Err(e) => exit_with_input_error(e),
+        }
+    } else if let Some(ref path) = options.replay_path {
+        let playback = match InputPlayback::load(path) {
+            Ok(val) => val,
+            Err(e) => exit_with_input_error(e),
+        };
+        // Drive the timers from a virtual clock so the replay is deterministic.
+        gl.clock = glh::Clock::Fixed { time_seconds: 0.0, step_seconds: playback.step_seconds() };
+        InputSource::Playback(playback)
+    } else {
+        InputSource::Live
+    };
+    let mut frames_rendered = 0;
     while !gl.window.should_close() {
         gl.glfw.poll_events();
-        match gl.window.get_key(Key::Escape) {
-            Action::Press | Action::Repeat => {
-                gl.window.set_should_close(true);
-            }
-            _ => {}
+        glh::update_timers(&mut gl);
+        let time = gl.get_time();
+        if let Err(e) = input_source.update(&mut input, &gl.events, frames_rendered, time) {
+            exit_with_input_error(e);
         }
-
-        unsafe {
-            gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
-            gl::ClearColor(0.3, 0.3, 0.3, 1.0);
-            gl::Viewport(0, 0, 640, 480);
+        if input.is_action_down("quit") {
+            gl.window.set_should_close(true);
         }
 
-        // Update the GPU.
-        send_to_gpu_uniforms_triangle(sp, trans_mat, scale_mat);
+        render_scene_triangle(&gl, &scene);
 
-        // Render the results.
-        unsafe {
-            gl::UseProgram(sp);
-            gl::Enable(gl::DEPTH_TEST);
-            gl::DepthFunc(gl::LESS);
-            gl::ActiveTexture(gl::TEXTURE0);
-            gl::BindTexture(gl::TEXTURE_2D, tex);
-            gl::BindVertexArray(handle.vao);
-            gl::DrawArrays(gl::TRIANGLES, 0, 3);
+        // Capture the frame before presenting it, since the back buffer contents
+        // are undefined after a swap.
+        if Some(frames_rendered) == options.capture_frame {
+            if let Err(e) = capture::capture_frame_to_file(&gl, &options.capture_path) {
+                error!("Failed to capture frame {}. Got error: {}", frames_rendered, e);
+                eprintln!("Failed to capture frame {}. Got error: {}", frames_rendered, e);
+                info!("END LOG");
+                std::process::exit(1);
+            }
+        }
+        if input.was_action_pressed("capture_frame") {
+            let path = format!("capture_{}.png", frames_rendered);
+            if let Err(e) = capture::capture_frame_to_file(&gl, &path) {
+                info!("Failed to capture frame {}: {}", frames_rendered, e);
+            }
         }
 
-        gl.window.swap_buffers();
+        glh::present(&mut gl);
+
+        frames_rendered += 1;
+        if Some(frames_rendered) == options.max_frames {
+            info!("Rendered {} frames. Exiting.", frames_rendered);
+            gl.window.set_should_close(true);
+        }
+        if input_source.is_finished(frames_rendered) {
+            info!("Finished replaying input after {} frames. Exiting.", frames_rendered);
+            gl.window.set_should_close(true);
+        }
+    }
+    if let Err(e) = input_source.finish(frames_rendered) {
+        exit_with_input_error(e);
     }
     info!("END LOG");
 }
@@ -15,92 +15,833 @@
  *  You should have received a copy of the GNU General Public License
  *  along with this program.  If not, see <http://www.gnu.org/licenses/>.
  */
+#![allow(dead_code)]
+use crate::bounds::Bounds;
+use crate::gl;
+use crate::gl::types::GLenum;
+
+use std::cell::Cell;
+use std::error;
+use std::fmt;
 use std::mem;
 
 
+/// The storage format of one vertex attribute value. The name gives the component
+/// type and the number of components. Normalized formats read as floats in the
+/// range `[0, 1]` in a shader.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum AttributeFormat {
+    F32x1,
+    F32x2,
+    F32x3,
+    F32x4,
+    U8x4,
+    Unorm8x4,
+    U16x2,
+    U16x4,
+    Unorm16x2,
+    Unorm16x4,
+    U32x1,
+}
+
+impl AttributeFormat {
+    /// The number of components in one value.
+    #[inline]
+    pub fn component_count(self) -> usize {
+        match self {
+            AttributeFormat::F32x1 | AttributeFormat::U32x1 => 1,
+            AttributeFormat::F32x2 | AttributeFormat::U16x2 | AttributeFormat::Unorm16x2 => 2,
+            AttributeFormat::F32x3 => 3,
+            _ => 4,
+        }
+    }
+
+    /// The size in bytes of one component.
+    #[inline]
+    pub fn component_size(self) -> usize {
+        match self {
+            AttributeFormat::U8x4 | AttributeFormat::Unorm8x4 => mem::size_of::<u8>(),
+            AttributeFormat::U16x2 | AttributeFormat::U16x4 => mem::size_of::<u16>(),
+            AttributeFormat::Unorm16x2 | AttributeFormat::Unorm16x4 => mem::size_of::<u16>(),
+            AttributeFormat::U32x1 => mem::size_of::<u32>(),
+            _ => mem::size_of::<f32>(),
+        }
+    }
+
+    /// The size in bytes of one value.
+    #[inline]
+    pub fn size(self) -> usize {
+        self.component_count() * self.component_size()
+    }
+
+    /// The GL type of the components, for `glVertexAttribPointer`.
+    #[inline]
+    pub fn gl_type(self) -> GLenum {
+        match self {
+            AttributeFormat::U8x4 | AttributeFormat::Unorm8x4 => gl::UNSIGNED_BYTE,
+            AttributeFormat::U16x2 | AttributeFormat::U16x4 => gl::UNSIGNED_SHORT,
+            AttributeFormat::Unorm16x2 | AttributeFormat::Unorm16x4 => gl::UNSIGNED_SHORT,
+            AttributeFormat::U32x1 => gl::UNSIGNED_INT,
+            _ => gl::FLOAT,
+        }
+    }
+
+    /// Determine whether the shader reads the components normalized to `[0, 1]`.
+    #[inline]
+    pub fn is_normalized(self) -> bool {
+        match self {
+            AttributeFormat::Unorm8x4 | AttributeFormat::Unorm16x2 | AttributeFormat::Unorm16x4 => true,
+            _ => false,
+        }
+    }
+
+    /// Determine whether the shader reads the components as integers. These must be
+    /// bound with `glVertexAttribIPointer`.
+    #[inline]
+    pub fn is_integer(self) -> bool {
+        match self {
+            AttributeFormat::U8x4 | AttributeFormat::U16x2 | AttributeFormat::U16x4 => true,
+            AttributeFormat::U32x1 => true,
+            _ => false,
+        }
+    }
+}
+
+/// The meaning of a vertex attribute. A semantic names the shader input that the
+/// attribute feeds, so meshes and shaders agree on attributes by name.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Semantic {
+    Position,
+    Normal,
+    Tangent,
+    /// A texture coordinate set. Set 0 is the primary UV set.
+    TexCoord(u32),
+    /// A vertex color set.
+    Color(u32),
+    /// The indices of the joints that influence a skinned vertex.
+    Joints(u32),
+    /// The weights of the joints that influence a skinned vertex.
+    Weights(u32),
+    /// An attribute with an application defined meaning. The name is the name of
+    /// the shader input.
+    Custom(String),
+}
+
+impl Semantic {
+    /// The name of the shader input the attribute feeds. Set 0 of the numbered
+    /// semantics has no suffix, e.g. `v_tex`, `v_tex1`, `v_tex2`.
+    pub fn shader_name(&self) -> String {
+        let numbered = |base: &str, set: u32| {
+            if set == 0 {
+                String::from(base)
+            } else {
+                format!("{}{}", base, set)
+            }
+        };
+
+        match *self {
+            Semantic::Position => String::from("v_pos"),
+            Semantic::Normal => String::from("v_normal"),
+            Semantic::Tangent => String::from("v_tangent"),
+            Semantic::TexCoord(set) => numbered("v_tex", set),
+            Semantic::Color(set) => numbered("v_color", set),
+            Semantic::Joints(set) => numbered("v_joints", set),
+            Semantic::Weights(set) => numbered("v_weights", set),
+            Semantic::Custom(ref name) => name.clone(),
+        }
+    }
+}
+
+/// The values of a vertex attribute, one per vertex.
 #[derive(Clone, Debug, PartialEq)]
-pub struct Points {
-    inner: Vec<[f32; 3]>,
+pub enum AttributeData {
+    F32x1(Vec<f32>),
+    F32x2(Vec<[f32; 2]>),
+    F32x3(Vec<[f32; 3]>),
+    F32x4(Vec<[f32; 4]>),
+    U8x4(Vec<[u8; 4]>),
+    Unorm8x4(Vec<[u8; 4]>),
+    U16x2(Vec<[u16; 2]>),
+    U16x4(Vec<[u16; 4]>),
+    Unorm16x2(Vec<[u16; 2]>),
+    Unorm16x4(Vec<[u16; 4]>),
+    U32x1(Vec<u32>),
 }
 
-impl Points {
+impl AttributeData {
+    /// The storage format of the values.
+    pub fn format(&self) -> AttributeFormat {
+        match *self {
+            AttributeData::F32x1(_) => AttributeFormat::F32x1,
+            AttributeData::F32x2(_) => AttributeFormat::F32x2,
+            AttributeData::F32x3(_) => AttributeFormat::F32x3,
+            AttributeData::F32x4(_) => AttributeFormat::F32x4,
+            AttributeData::U8x4(_) => AttributeFormat::U8x4,
+            AttributeData::Unorm8x4(_) => AttributeFormat::Unorm8x4,
+            AttributeData::U16x2(_) => AttributeFormat::U16x2,
+            AttributeData::U16x4(_) => AttributeFormat::U16x4,
+            AttributeData::Unorm16x2(_) => AttributeFormat::Unorm16x2,
+            AttributeData::Unorm16x4(_) => AttributeFormat::Unorm16x4,
+            AttributeData::U32x1(_) => AttributeFormat::U32x1,
+        }
+    }
+
     #[inline]
-    pub fn as_ptr(&self) -> *const [f32; 3] {
-        self.inner.as_ptr()
+    pub fn as_ptr(&self) -> *const u8 {
+        match *self {
+            AttributeData::F32x1(ref inner) => inner.as_ptr() as *const u8,
+            AttributeData::F32x2(ref inner) => inner.as_ptr() as *const u8,
+            AttributeData::F32x3(ref inner) => inner.as_ptr() as *const u8,
+            AttributeData::F32x4(ref inner) => inner.as_ptr() as *const u8,
+            AttributeData::U8x4(ref inner) => inner.as_ptr() as *const u8,
+            AttributeData::Unorm8x4(ref inner) => inner.as_ptr() as *const u8,
+            AttributeData::U16x2(ref inner) => inner.as_ptr() as *const u8,
+            AttributeData::U16x4(ref inner) => inner.as_ptr() as *const u8,
+            AttributeData::Unorm16x2(ref inner) => inner.as_ptr() as *const u8,
+            AttributeData::Unorm16x4(ref inner) => inner.as_ptr() as *const u8,
+            AttributeData::U32x1(ref inner) => inner.as_ptr() as *const u8,
+        }
     }
 
-    /// Get the length of the points buffer in bytes.
+    /// Get the number of values.
+    pub fn len(&self) -> usize {
+        match *self {
+            AttributeData::F32x1(ref inner) => inner.len(),
+            AttributeData::F32x2(ref inner) => inner.len(),
+            AttributeData::F32x3(ref inner) => inner.len(),
+            AttributeData::F32x4(ref inner) => inner.len(),
+            AttributeData::U8x4(ref inner) => inner.len(),
+            AttributeData::Unorm8x4(ref inner) => inner.len(),
+            AttributeData::U16x2(ref inner) => inner.len(),
+            AttributeData::U16x4(ref inner) => inner.len(),
+            AttributeData::Unorm16x2(ref inner) => inner.len(),
+            AttributeData::Unorm16x4(ref inner) => inner.len(),
+            AttributeData::U32x1(ref inner) => inner.len(),
+        }
+    }
+
+    /// Get the length of the values in bytes.
     #[inline]
     pub fn len_bytes(&self) -> usize {
-        3 * mem::size_of::<f32>() * self.inner.len()
+        self.format().size() * self.len()
     }
 
-    /// Get the number of elements in the points buffer.
+    /// Present the values as bytes, e.g. for uploading them to a buffer.
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { ::std::slice::from_raw_parts(self.as_ptr(), self.len_bytes()) }
+    }
+
+    /// Decode little endian values of a format. Trailing bytes that do not make up
+    /// a whole value are ignored.
+    pub fn from_bytes(format: AttributeFormat, bytes: &[u8]) -> AttributeData {
+        fn f32s(bytes: &[u8]) -> impl Iterator<Item = f32> + '_ {
+            bytes.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        }
+        fn u16s(bytes: &[u8]) -> impl Iterator<Item = u16> + '_ {
+            bytes.chunks_exact(2).map(|b| u16::from_le_bytes([b[0], b[1]]))
+        }
+        fn group<T: Copy + Default, A: Default + AsMut<[T]>>(values: impl Iterator<Item = T>) -> Vec<A> {
+            let values: Vec<T> = values.collect();
+            values.chunks_exact(A::default().as_mut().len()).map(|chunk| {
+                let mut value = A::default();
+                value.as_mut().copy_from_slice(chunk);
+                value
+            }).collect()
+        }
+
+        let bytes = &bytes[..bytes.len() - bytes.len() % format.size()];
+        match format {
+            AttributeFormat::F32x1 => AttributeData::F32x1(f32s(bytes).collect()),
+            AttributeFormat::F32x2 => AttributeData::F32x2(group(f32s(bytes))),
+            AttributeFormat::F32x3 => AttributeData::F32x3(group(f32s(bytes))),
+            AttributeFormat::F32x4 => AttributeData::F32x4(group(f32s(bytes))),
+            AttributeFormat::U8x4 => AttributeData::U8x4(group(bytes.iter().cloned())),
+            AttributeFormat::Unorm8x4 => AttributeData::Unorm8x4(group(bytes.iter().cloned())),
+            AttributeFormat::U16x2 => AttributeData::U16x2(group(u16s(bytes))),
+            AttributeFormat::U16x4 => AttributeData::U16x4(group(u16s(bytes))),
+            AttributeFormat::Unorm16x2 => AttributeData::Unorm16x2(group(u16s(bytes))),
+            AttributeFormat::Unorm16x4 => AttributeData::Unorm16x4(group(u16s(bytes))),
+            AttributeFormat::U32x1 => AttributeData::U32x1(
+                bytes.chunks_exact(4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect()
+            ),
+        }
+    }
+
+    /// Build new values where value `i` is a copy of value `sources[i]`.
+    pub fn gather(&self, sources: &[u32]) -> AttributeData {
+        fn pick<T: Copy>(values: &[T], sources: &[u32]) -> Vec<T> {
+            sources.iter().map(|&i| values[i as usize]).collect()
+        }
+
+        match *self {
+            AttributeData::F32x1(ref inner) => AttributeData::F32x1(pick(inner, sources)),
+            AttributeData::F32x2(ref inner) => AttributeData::F32x2(pick(inner, sources)),
+            AttributeData::F32x3(ref inner) => AttributeData::F32x3(pick(inner, sources)),
+            AttributeData::F32x4(ref inner) => AttributeData::F32x4(pick(inner, sources)),
+            AttributeData::U8x4(ref inner) => AttributeData::U8x4(pick(inner, sources)),
+            AttributeData::Unorm8x4(ref inner) => AttributeData::Unorm8x4(pick(inner, sources)),
+            AttributeData::U16x2(ref inner) => AttributeData::U16x2(pick(inner, sources)),
+            AttributeData::U16x4(ref inner) => AttributeData::U16x4(pick(inner, sources)),
+            AttributeData::Unorm16x2(ref inner) => AttributeData::Unorm16x2(pick(inner, sources)),
+            AttributeData::Unorm16x4(ref inner) => AttributeData::Unorm16x4(pick(inner, sources)),
+            AttributeData::U32x1(ref inner) => AttributeData::U32x1(pick(inner, sources)),
+        }
+    }
+
+    /// Find the first value with a NaN or infinite component. Integer values are
+    /// always finite.
+    pub fn first_non_finite(&self) -> Option<usize> {
+        fn find<A: AsRef<[f32]>>(values: &[A]) -> Option<usize> {
+            values.iter().position(|value| value.as_ref().iter().any(|x| !x.is_finite()))
+        }
+
+        match *self {
+            AttributeData::F32x1(ref inner) => inner.iter().position(|x| !x.is_finite()),
+            AttributeData::F32x2(ref inner) => find(inner),
+            AttributeData::F32x3(ref inner) => find(inner),
+            AttributeData::F32x4(ref inner) => find(inner),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn as_f32x2(&self) -> Option<&[[f32; 2]]> {
+        match *self {
+            AttributeData::F32x2(ref inner) => Some(inner),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn as_f32x3(&self) -> Option<&[[f32; 3]]> {
+        match *self {
+            AttributeData::F32x3(ref inner) => Some(inner),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn as_f32x4(&self) -> Option<&[[f32; 4]]> {
+        match *self {
+            AttributeData::F32x4(ref inner) => Some(inner),
+            _ => None,
+        }
+    }
+}
+
+/// A named stream of per vertex values.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VertexAttribute {
+    pub semantic: Semantic,
+    pub data: AttributeData,
+}
+
+impl VertexAttribute {
+    #[inline]
+    pub fn format(&self) -> AttributeFormat {
+        self.data.format()
+    }
+
+    /// Get the number of values in the attribute.
     #[inline]
     pub fn len(&self) -> usize {
-        self.inner.len()
+        self.data.len()
+    }
+
+    /// Get the length of the attribute in bytes.
+    #[inline]
+    pub fn len_bytes(&self) -> usize {
+        self.data.len_bytes()
     }
 }
 
+/// The index array of an indexed mesh. Index arrays whose largest index fits in
+/// 16 bits store 16 bit indices, and the rest store 32 bit indices.
 #[derive(Clone, Debug, PartialEq)]
-pub struct TextureCoordinates {
-    inner: Vec<[f32; 2]>,
+pub enum Indices {
+    U16(Vec<u16>),
+    U32(Vec<u32>),
 }
 
-impl TextureCoordinates {
+impl Indices {
+    /// Pack indices into the smallest index type that holds the largest of them.
+    pub fn new(indices: &[u32]) -> Indices {
+        let largest = indices.iter().cloned().max().unwrap_or(0);
+        if largest <= u16::max_value() as u32 {
+            Indices::U16(indices.iter().map(|&i| i as u16).collect())
+        } else {
+            Indices::U32(indices.to_vec())
+        }
+    }
+
+    #[inline]
+    pub fn as_ptr(&self) -> *const u8 {
+        match *self {
+            Indices::U16(ref inner) => inner.as_ptr() as *const u8,
+            Indices::U32(ref inner) => inner.as_ptr() as *const u8,
+        }
+    }
+
+    /// Get the size of one index in bytes.
+    #[inline]
+    pub fn index_size(&self) -> usize {
+        match *self {
+            Indices::U16(_) => mem::size_of::<u16>(),
+            Indices::U32(_) => mem::size_of::<u32>(),
+        }
+    }
+
+    /// The GL type of the indices, for `glDrawElements`.
     #[inline]
-    pub fn as_ptr(&self) -> *const [f32; 2] {
-        self.inner.as_ptr()
+    pub fn gl_type(&self) -> GLenum {
+        match *self {
+            Indices::U16(_) => gl::UNSIGNED_SHORT,
+            Indices::U32(_) => gl::UNSIGNED_INT,
+        }
     }
 
-    /// Get the length of the texture coordinates buffer in bytes.
+    /// Get the length of the index buffer in bytes.
     #[inline]
     pub fn len_bytes(&self) -> usize {
-        2 * mem::size_of::<f32>() * self.inner.len()
+        self.index_size() * self.len()
     }
 
-    /// Get the number of elements in the texture coordinates buffer.
+    /// Get the number of indices.
     #[inline]
     pub fn len(&self) -> usize {
-        self.inner.len()
+        match *self {
+            Indices::U16(ref inner) => inner.len(),
+            Indices::U32(ref inner) => inner.len(),
+        }
+    }
+
+    /// Get an index, widened to 32 bits.
+    #[inline]
+    pub fn get(&self, i: usize) -> u32 {
+        match *self {
+            Indices::U16(ref inner) => inner[i] as u32,
+            Indices::U32(ref inner) => inner[i],
+        }
+    }
+
+    /// Iterate over the indices, widened to 32 bits.
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = u32> + 'a {
+        (0..self.len()).map(move |i| self.get(i))
     }
 }
 
-/// An `ObjMesh` is a model space representation of a 2D geometric figure.
+/// The ways a mesh can be malformed. See `Mesh::validate`.
 #[derive(Clone, Debug, PartialEq)]
+pub enum MeshError {
+    /// The mesh has no position attribute.
+    MissingPositions,
+    /// An attribute has a different number of values than the mesh has vertices.
+    /// It carries the semantic, the number of values and the number of vertices.
+    AttributeLengthMismatch(Semantic, usize, usize),
+    /// The number of indices, or vertices for a mesh without indices, is not a
+    /// multiple of three.
+    IncompleteTriangle(usize),
+    /// An index refers to a vertex past the end of the mesh. It carries the
+    /// position in the index array, the index and the number of vertices.
+    IndexOutOfRange(usize, u32, usize),
+    /// An attribute value is NaN or infinite. It carries the semantic and the vertex.
+    NonFiniteValue(Semantic, usize),
+    /// A triangle has no area. It carries the number of the triangle.
+    DegenerateTriangle(usize),
+}
+
+impl fmt::Display for MeshError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &MeshError::MissingPositions => {
+                write!(f, "The mesh has no vertex positions")
+            }
+            &MeshError::AttributeLengthMismatch(ref semantic, length, vertex_count) => {
+                write!(f, "The mesh attribute {} has {} values but the mesh has {} vertices",
+                    semantic.shader_name(), length, vertex_count
+                )
+            }
+            &MeshError::IncompleteTriangle(count) => {
+                write!(f, "The mesh has {} vertices in its triangle list, which is not a multiple of three", count)
+            }
+            &MeshError::IndexOutOfRange(position, index, vertex_count) => {
+                write!(f, "Index {} of the mesh is {} but the mesh has {} vertices", position, index, vertex_count)
+            }
+            &MeshError::NonFiniteValue(ref semantic, vertex) => {
+                write!(f, "The mesh attribute {} is not finite at vertex {}", semantic.shader_name(), vertex)
+            }
+            &MeshError::DegenerateTriangle(triangle) => {
+                write!(f, "Triangle {} of the mesh has no area", triangle)
+            }
+        }
+    }
+}
+
+impl error::Error for MeshError {}
+
+/// A `Mesh` is a model space representation of a geometric figure. It is a set of
+/// vertex attributes, each holding one value per vertex, and an optional index array.
+#[derive(Clone, Debug)]
 pub struct Mesh {
-    pub points: Points,
-    pub tex_coords: TextureCoordinates,
+    attributes: Vec<VertexAttribute>,
+    /// The index array for an indexed mesh. Without one, every three consecutive
+    /// vertices form a triangle.
+    pub indices: Option<Indices>,
+    /// The bounds of the positions, computed on first use. Changing the attributes
+    /// clears them.
+    bounds: Cell<Option<Bounds>>,
+}
+
+impl PartialEq for Mesh {
+    fn eq(&self, other: &Mesh) -> bool {
+        self.attributes == other.attributes && self.indices == other.indices
+    }
 }
 
 impl Mesh {
-    /// Generate a new mesh object.
+    /// Generate a new mesh object with positions and one texture coordinate set.
     pub fn new(points: &[[f32; 3]], tex_coords: &[[f32; 2]]) -> Mesh {
+        let mut mesh = Mesh::from_points(points);
+        mesh.set_attribute(Semantic::TexCoord(0), AttributeData::F32x2(tex_coords.to_vec()));
+
+        mesh
+    }
+
+    /// Generate a new mesh object with positions only.
+    pub fn from_points(points: &[[f32; 3]]) -> Mesh {
         Mesh {
-            points: Points { inner: points.iter().map(|e| *e).collect() },
-            tex_coords: TextureCoordinates { inner: tex_coords.iter().map(|e| *e).collect() },
+            attributes: vec![VertexAttribute {
+                semantic: Semantic::Position,
+                data: AttributeData::F32x3(points.to_vec()),
+            }],
+            indices: None,
+            bounds: Cell::new(None),
+        }
+    }
+
+    /// Generate a new indexed mesh object. Every three consecutive indices form a
+    /// triangle, and every index must refer to a point.
+    pub fn new_indexed(
+        points: &[[f32; 3]], tex_coords: &[[f32; 2]], indices: &[u32]) -> Result<Mesh, MeshError> {
+
+        if let Some(position) = indices.iter().position(|&i| i as usize >= points.len()) {
+            return Err(MeshError::IndexOutOfRange(position, indices[position], points.len()));
+        }
+        let mut mesh = Mesh::new(points, tex_coords);
+        mesh.indices = Some(Indices::new(indices));
+
+        Ok(mesh)
+    }
+
+    /// Generate a new mesh object with positions and one texture coordinate set,
+    /// checking that the result is valid.
+    pub fn try_new(points: &[[f32; 3]], tex_coords: &[[f32; 2]]) -> Result<Mesh, MeshError> {
+        let mesh = Mesh::new(points, tex_coords);
+        mesh.validate()?;
+
+        Ok(mesh)
+    }
+
+    /// Generate a new indexed mesh object, checking that the result is valid.
+    pub fn try_new_indexed(
+        points: &[[f32; 3]], tex_coords: &[[f32; 2]], indices: &[u32]) -> Result<Mesh, MeshError> {
+
+        let mesh = Mesh::new_indexed(points, tex_coords, indices)?;
+        mesh.validate()?;
+
+        Ok(mesh)
+    }
+
+    /// Check that the mesh can be drawn as a triangle list. Every attribute must
+    /// have one value per vertex, every index must refer to a vertex, float
+    /// attributes must be finite, and every triangle must have some area.
+    pub fn validate(&self) -> Result<(), MeshError> {
+        let points = match self.attribute(&Semantic::Position).and_then(|a| a.data.as_f32x3()) {
+            Some(val) => val,
+            None => return Err(MeshError::MissingPositions),
+        };
+        let vertex_count = points.len();
+        for attribute in self.attributes.iter() {
+            if attribute.len() != vertex_count {
+                return Err(MeshError::AttributeLengthMismatch(
+                    attribute.semantic.clone(), attribute.len(), vertex_count
+                ));
+            }
+        }
+
+        let corner_count = self.draw_count();
+        if corner_count % 3 != 0 {
+            return Err(MeshError::IncompleteTriangle(corner_count));
         }
+        if let Some(ref indices) = self.indices {
+            if let Some(position) = indices.iter().position(|i| i as usize >= vertex_count) {
+                return Err(MeshError::IndexOutOfRange(position, indices.get(position), vertex_count));
+            }
+        }
+
+        for attribute in self.attributes.iter() {
+            if let Some(vertex) = attribute.data.first_non_finite() {
+                return Err(MeshError::NonFiniteValue(attribute.semantic.clone(), vertex));
+            }
+        }
+
+        let corners = self.triangle_corners();
+        for (triangle, corner) in corners.chunks(3).enumerate() {
+            let (a, b, c) = (points[corner[0] as usize], points[corner[1] as usize], points[corner[2] as usize]);
+            let e1 = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+            let e2 = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+            let normal = [e1[1] * e2[2] - e1[2] * e2[1], e1[2] * e2[0] - e1[0] * e2[2], e1[0] * e2[1] - e1[1] * e2[0]];
+            let length_squared = |v: [f32; 3]| v[0] * v[0] + v[1] * v[1] + v[2] * v[2];
+            // Collinear corners are degenerate too, so compare the area to the
+            // lengths of the edges rather than to zero.
+            let area_squared = length_squared(normal);
+            if area_squared <= 1e-14 * length_squared(e1) * length_squared(e2) {
+                return Err(MeshError::DegenerateTriangle(triangle));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Add an attribute to the mesh, replacing any attribute with the same semantic.
+    pub fn set_attribute(&mut self, semantic: Semantic, data: AttributeData) {
+        self.bounds.set(None);
+        match self.attributes.iter_mut().find(|attribute| attribute.semantic == semantic) {
+            Some(attribute) => attribute.data = data,
+            None => self.attributes.push(VertexAttribute { semantic: semantic, data: data }),
+        }
+    }
+
+    /// Remove an attribute from the mesh and return it.
+    pub fn remove_attribute(&mut self, semantic: &Semantic) -> Option<VertexAttribute> {
+        self.bounds.set(None);
+        let position = self.attributes.iter().position(|attribute| &attribute.semantic == semantic)?;
+        Some(self.attributes.remove(position))
+    }
+
+    /// Look up an attribute by its semantic.
+    #[inline]
+    pub fn attribute(&self, semantic: &Semantic) -> Option<&VertexAttribute> {
+        self.attributes.iter().find(|attribute| &attribute.semantic == semantic)
+    }
+
+    /// Look up an attribute by its semantic for modification.
+    #[inline]
+    pub fn attribute_mut(&mut self, semantic: &Semantic) -> Option<&mut VertexAttribute> {
+        self.bounds.set(None);
+        self.attributes.iter_mut().find(|attribute| &attribute.semantic == semantic)
+    }
+
+    /// Determine whether the mesh has an attribute.
+    #[inline]
+    pub fn has_attribute(&self, semantic: &Semantic) -> bool {
+        self.attribute(semantic).is_some()
+    }
+
+    /// Present every attribute of the mesh, in the order they were added.
+    #[inline]
+    pub fn attributes(&self) -> &[VertexAttribute] {
+        &self.attributes
     }
 
     /// Present the points map as an array slice. This function can be used
     /// to present the internal array buffer to OpenGL or another Graphics
-    /// system for rendering.
+    /// system for rendering. The slice is empty if the mesh has no positions.
     #[inline]
     pub fn points(&self) -> &[[f32; 3]] {
-        &self.points.inner
+        self.attribute(&Semantic::Position).and_then(|a| a.data.as_f32x3()).unwrap_or(&[])
     }
 
-    /// Present the texture map as an array slice. This function can be used
+    /// Present the primary texture map as an array slice. This function can be used
     /// to present the internal array buffer to OpenGL or another Graphics
-    /// system for rendering.
+    /// system for rendering. The slice is empty if the mesh has no texture coordinates.
     #[inline]
     pub fn tex_coords(&self) -> &[[f32; 2]] {
-        &self.tex_coords.inner
+        self.attribute(&Semantic::TexCoord(0)).and_then(|a| a.data.as_f32x2()).unwrap_or(&[])
+    }
+
+    /// Present the normals as an array slice, if the mesh has them.
+    #[inline]
+    pub fn normals(&self) -> Option<&[[f32; 3]]> {
+        self.attribute(&Semantic::Normal).and_then(|a| a.data.as_f32x3())
+    }
+
+    /// Present the index array, if the mesh has one.
+    #[inline]
+    pub fn indices(&self) -> Option<&Indices> {
+        self.indices.as_ref()
+    }
+
+    /// Determine whether the mesh has an index array.
+    #[inline]
+    pub fn is_indexed(&self) -> bool {
+        self.indices.is_some()
+    }
+
+    /// Determine whether the mesh has the joint and weight attributes the skinning
+    /// shader needs.
+    #[inline]
+    pub fn is_skinned(&self) -> bool {
+        self.has_attribute(&Semantic::Joints(0)) && self.has_attribute(&Semantic::Weights(0))
+    }
+
+    /// The bounding box and sphere of the vertex positions in model space. They
+    /// are computed once and cached until the attributes change.
+    pub fn bounds(&self) -> Bounds {
+        match self.bounds.get() {
+            Some(bounds) => bounds,
+            None => {
+                let bounds = Bounds::from_points(self.points());
+                self.bounds.set(Some(bounds));
+                bounds
+            }
+        }
     }
 
     /// Get the number of vertices in the mesh.
     #[inline]
     pub fn len(&self) -> usize {
-        self.points.len()
+        match self.attribute(&Semantic::Position) {
+            Some(attribute) => attribute.len(),
+            None => self.attributes.first().map_or(0, |attribute| attribute.len()),
+        }
+    }
+
+    /// Get the vertex index of every triangle corner. For a mesh without an index
+    /// array these are the vertices in order.
+    pub fn triangle_corners(&self) -> Vec<u32> {
+        match self.indices {
+            Some(ref indices) => indices.iter().collect(),
+            None => (0..self.len() as u32).collect(),
+        }
+    }
+
+    /// Rebuild every attribute so that vertex `i` takes the values of vertex
+    /// `sources[i]`. The index array is left alone.
+    pub fn gather_vertices(&mut self, sources: &[u32]) {
+        self.bounds.set(None);
+        for attribute in self.attributes.iter_mut() {
+            attribute.data = attribute.data.gather(sources);
+        }
+    }
+
+    /// Get the number of vertices a draw call processes. This is the number of
+    /// indices for an indexed mesh.
+    #[inline]
+    pub fn draw_count(&self) -> usize {
+        match self.indices {
+            Some(ref indices) => indices.len(),
+            None => self.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn test_indices_pick_the_smallest_type() {
+        assert_eq!(Indices::new(&[0, 1, 65535]), Indices::U16(vec![0, 1, 65535]));
+        assert_eq!(Indices::new(&[0, 1, 65536]), Indices::U32(vec![0, 1, 65536]));
+        assert_eq!(Indices::new(&[]), Indices::U16(vec![]));
+    }
+
+    #[test]
+    fn test_large_indices_are_not_truncated() {
+        let indices = [70000, 1, 65536];
+        let packed = Indices::new(&indices);
+
+        assert_eq!(packed.index_size(), 4);
+        assert_eq!(packed.iter().collect::<Vec<u32>>(), indices.to_vec());
+    }
+
+    #[test]
+    fn test_new_indexed_rejects_out_of_range_indices() {
+        let points = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+        let tex_coords = [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]];
+
+        let mesh = Mesh::new_indexed(&points, &tex_coords, &[0, 1, 2]).unwrap();
+        assert_eq!(mesh.indices(), Some(&Indices::U16(vec![0, 1, 2])));
+        assert_eq!(
+            Mesh::new_indexed(&points, &tex_coords, &[0, 1, 65539]).err(),
+            Some(MeshError::IndexOutOfRange(2, 65539, 3))
+        );
+        assert_eq!(
+            Mesh::try_new_indexed(&points, &tex_coords, &[0, 3, 2]).err(),
+            Some(MeshError::IndexOutOfRange(1, 3, 3))
+        );
+    }
+
+    /// A valid indexed quad made of two triangles.
+    fn quad_mesh() -> Mesh {
+        let points = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]];
+        let tex_coords = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+
+        Mesh::new_indexed(&points, &tex_coords, &[0, 1, 2, 0, 2, 3]).unwrap()
+    }
+
+    #[test]
+    fn test_validate_accepts_a_valid_mesh() {
+        assert_eq!(quad_mesh().validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_missing_positions() {
+        let mut mesh = quad_mesh();
+        mesh.remove_attribute(&Semantic::Position);
+        assert_eq!(mesh.validate(), Err(MeshError::MissingPositions));
+    }
+
+    #[test]
+    fn test_validate_attribute_length_mismatch() {
+        let mut mesh = quad_mesh();
+        mesh.set_attribute(Semantic::Normal, AttributeData::F32x3(vec![[0.0, 0.0, 1.0]; 3]));
+        assert_eq!(mesh.validate(), Err(MeshError::AttributeLengthMismatch(Semantic::Normal, 3, 4)));
+    }
+
+    #[test]
+    fn test_validate_incomplete_triangle() {
+        let mut mesh = quad_mesh();
+        mesh.indices = Some(Indices::new(&[0, 1, 2, 0, 2]));
+        assert_eq!(mesh.validate(), Err(MeshError::IncompleteTriangle(5)));
+
+        let points = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]];
+        assert_eq!(Mesh::from_points(&points).validate(), Err(MeshError::IncompleteTriangle(4)));
+    }
+
+    #[test]
+    fn test_validate_index_out_of_range() {
+        let mut mesh = quad_mesh();
+        mesh.indices = Some(Indices::new(&[0, 1, 2, 0, 2, 4]));
+        assert_eq!(mesh.validate(), Err(MeshError::IndexOutOfRange(5, 4, 4)));
+    }
+
+    #[test]
+    fn test_validate_non_finite_value() {
+        let mut mesh = quad_mesh();
+        let points = vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, f32::NAN, 0.0], [0.0, 1.0, 0.0]];
+        mesh.set_attribute(Semantic::Position, AttributeData::F32x3(points));
+        assert_eq!(mesh.validate(), Err(MeshError::NonFiniteValue(Semantic::Position, 2)));
+
+        let mut mesh = quad_mesh();
+        let tex_coords = vec![[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [f32::INFINITY, 1.0]];
+        mesh.set_attribute(Semantic::TexCoord(0), AttributeData::F32x2(tex_coords));
+        assert_eq!(mesh.validate(), Err(MeshError::NonFiniteValue(Semantic::TexCoord(0), 3)));
+    }
+
+    #[test]
+    fn test_validate_degenerate_triangle() {
+        let mut mesh = quad_mesh();
+        let points = vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [2.0, 2.0, 0.0]];
+        mesh.set_attribute(Semantic::Position, AttributeData::F32x3(points));
+        // The second triangle has collinear corners.
+        assert_eq!(mesh.validate(), Err(MeshError::DegenerateTriangle(1)));
+
+        let mut mesh = quad_mesh();
+        mesh.indices = Some(Indices::new(&[0, 1, 2, 0, 0, 3]));
+        assert_eq!(mesh.validate(), Err(MeshError::DegenerateTriangle(1)));
     }
 }
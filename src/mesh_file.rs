@@ -0,0 +1,589 @@
+/*
+ *  GB-Prototype is a renderer prototype demo.
+ *  Copyright (C) 2018,2019,2020  Christopher Blanchard
+ *
+ *  This program is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  This program is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+#![allow(dead_code)]
+//! A binary container for cooked meshes. The file is laid out so that a reader can
+//! hand its vertex and index blocks straight to the GPU, with no per vertex work.
+//!
+//! All values are little endian. The file is a fixed size header, a table of
+//! attributes, the names of custom attributes, one block holding every attribute
+//! one after another, and the index block. Blocks start on 16 byte boundaries.
+//! The header ends with the bounds of the mesh, and a CRC-32 of everything after
+//! the header guards against truncated or corrupted files.
+use crate::bounds::{Aabb, BoundingSphere, Bounds};
+use crate::mesh::{AttributeData, AttributeFormat, Indices, Mesh, Semantic};
+use crate::renderer::{BufferLayout, ComponentKind, Offset, Size, Stride, Type};
+use crate::vertex_layout::{AttributeLayout, VertexLayout};
+
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str;
+
+
+/// The first four bytes of a mesh file.
+pub const MAGIC: [u8; 4] = *b"GBMF";
+/// The version of the format this module writes. Readers reject other versions.
+pub const VERSION: u32 = 1;
+
+const HEADER_SIZE: usize = 96;
+const ATTRIBUTE_ENTRY_SIZE: usize = 32;
+const BLOCK_ALIGNMENT: usize = 16;
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MeshFileError {
+    FileNotFound(String),
+    /// The file is shorter than its header says.
+    Truncated,
+    BadMagic,
+    UnsupportedVersion(u32),
+    /// The checksum in the header does not match the contents. It carries the
+    /// checksum in the header and the checksum of the contents.
+    ChecksumMismatch(u32, u32),
+    /// The file is well formed but describes an impossible mesh.
+    Malformed(String),
+}
+
+impl fmt::Display for MeshFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &MeshFileError::FileNotFound(ref file_name) => {
+                write!(f, "Could not open the mesh file for reading: {}", file_name)
+            }
+            &MeshFileError::Truncated => {
+                write!(f, "The mesh file is truncated")
+            }
+            &MeshFileError::BadMagic => {
+                write!(f, "The file is not a mesh file")
+            }
+            &MeshFileError::UnsupportedVersion(version) => {
+                write!(f, "The mesh file has version {} but only version {} is supported", version, VERSION)
+            }
+            &MeshFileError::ChecksumMismatch(expected, actual) => {
+                write!(f, "The mesh file is corrupt. Expected checksum 0x{:08X} but got 0x{:08X}", expected, actual)
+            }
+            &MeshFileError::Malformed(ref reason) => {
+                write!(f, "The mesh file is malformed: {}", reason)
+            }
+        }
+    }
+}
+
+impl error::Error for MeshFileError {}
+
+/// Compute the CRC-32 (IEEE) of some bytes.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut table = [0_u32; 256];
+    for (i, entry) in table.iter_mut().enumerate() {
+        let mut c = i as u32;
+        for _ in 0..8 {
+            c = if c & 1 != 0 { 0xEDB8_8320 ^ (c >> 1) } else { c >> 1 };
+        }
+        *entry = c;
+    }
+
+    let mut crc = 0xFFFF_FFFF_u32;
+    for &byte in bytes.iter() {
+        crc = table[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+
+    crc ^ 0xFFFF_FFFF
+}
+
+fn format_code(format: AttributeFormat) -> u32 {
+    match format {
+        AttributeFormat::F32x1 => 0,
+        AttributeFormat::F32x2 => 1,
+        AttributeFormat::F32x3 => 2,
+        AttributeFormat::F32x4 => 3,
+        AttributeFormat::U8x4 => 4,
+        AttributeFormat::Unorm8x4 => 5,
+        AttributeFormat::U16x2 => 6,
+        AttributeFormat::U16x4 => 7,
+        AttributeFormat::Unorm16x2 => 8,
+        AttributeFormat::Unorm16x4 => 9,
+        AttributeFormat::U32x1 => 10,
+    }
+}
+
+fn format_from_code(code: u32) -> Option<AttributeFormat> {
+    match code {
+        0 => Some(AttributeFormat::F32x1),
+        1 => Some(AttributeFormat::F32x2),
+        2 => Some(AttributeFormat::F32x3),
+        3 => Some(AttributeFormat::F32x4),
+        4 => Some(AttributeFormat::U8x4),
+        5 => Some(AttributeFormat::Unorm8x4),
+        6 => Some(AttributeFormat::U16x2),
+        7 => Some(AttributeFormat::U16x4),
+        8 => Some(AttributeFormat::Unorm16x2),
+        9 => Some(AttributeFormat::Unorm16x4),
+        10 => Some(AttributeFormat::U32x1),
+        _ => None,
+    }
+}
+
+/// Encode a semantic as a kind and a set number. Custom semantics keep their name
+/// in the string block.
+fn semantic_code(semantic: &Semantic) -> (u32, u32) {
+    match *semantic {
+        Semantic::Position => (0, 0),
+        Semantic::Normal => (1, 0),
+        Semantic::Tangent => (2, 0),
+        Semantic::TexCoord(set) => (3, set),
+        Semantic::Color(set) => (4, set),
+        Semantic::Joints(set) => (5, set),
+        Semantic::Weights(set) => (6, set),
+        Semantic::Custom(_) => (7, 0),
+    }
+}
+
+fn semantic_from_code(kind: u32, set: u32, name: &str) -> Option<Semantic> {
+    match kind {
+        0 => Some(Semantic::Position),
+        1 => Some(Semantic::Normal),
+        2 => Some(Semantic::Tangent),
+        3 => Some(Semantic::TexCoord(set)),
+        4 => Some(Semantic::Color(set)),
+        5 => Some(Semantic::Joints(set)),
+        6 => Some(Semantic::Weights(set)),
+        7 => Some(Semantic::Custom(String::from(name))),
+        _ => None,
+    }
+}
+
+#[inline]
+fn align(offset: usize) -> usize {
+    (offset + BLOCK_ALIGNMENT - 1) / BLOCK_ALIGNMENT * BLOCK_ALIGNMENT
+}
+
+#[inline]
+fn put_u32(bytes: &mut [u8], offset: usize, value: u32) {
+    bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+#[inline]
+fn put_f32(bytes: &mut [u8], offset: usize, value: f32) {
+    put_u32(bytes, offset, value.to_bits());
+}
+
+#[inline]
+fn get_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+}
+
+#[inline]
+fn get_f32(bytes: &[u8], offset: usize) -> f32 {
+    f32::from_bits(get_u32(bytes, offset))
+}
+
+/// Copy values from memory into the file. Each component of `component_size`
+/// bytes is converted from the host byte order to little endian.
+fn put_components(dst: &mut [u8], src: &[u8], component_size: usize) {
+    dst.copy_from_slice(src);
+    if cfg!(target_endian = "big") {
+        for component in dst.chunks_exact_mut(component_size) {
+            component.reverse();
+        }
+    }
+}
+
+/// Encode a mesh in the binary mesh format.
+pub fn write(mesh: &Mesh) -> Vec<u8> {
+    let attributes = mesh.attributes();
+    let table_offset = HEADER_SIZE;
+    let names_offset = table_offset + attributes.len() * ATTRIBUTE_ENTRY_SIZE;
+    let mut names = vec![];
+    let mut name_ranges = vec![];
+    for attribute in attributes.iter() {
+        let name = match attribute.semantic {
+            Semantic::Custom(ref name) => name.as_bytes(),
+            _ => &[],
+        };
+        name_ranges.push((names_offset + names.len(), name.len()));
+        names.extend_from_slice(name);
+    }
+
+    let vertex_offset = align(names_offset + names.len());
+    let mut attribute_offsets = vec![];
+    let mut vertex_len = 0;
+    for attribute in attributes.iter() {
+        attribute_offsets.push(vertex_len);
+        vertex_len = align(vertex_len + attribute.len_bytes());
+    }
+    let index_offset = align(vertex_offset + vertex_len);
+    let (index_size, index_count, index_len) = match mesh.indices() {
+        Some(indices) => (indices.index_size(), indices.len(), indices.len_bytes()),
+        None => (0, 0, 0),
+    };
+
+    let mut bytes = vec![0_u8; index_offset + index_len];
+    for (i, attribute) in attributes.iter().enumerate() {
+        let entry = table_offset + i * ATTRIBUTE_ENTRY_SIZE;
+        let (kind, set) = semantic_code(&attribute.semantic);
+        put_u32(&mut bytes, entry, kind);
+        put_u32(&mut bytes, entry + 4, set);
+        put_u32(&mut bytes, entry + 8, format_code(attribute.format()));
+        put_u32(&mut bytes, entry + 12, attribute_offsets[i] as u32);
+        put_u32(&mut bytes, entry + 16, attribute.len_bytes() as u32);
+        put_u32(&mut bytes, entry + 20, name_ranges[i].0 as u32);
+        put_u32(&mut bytes, entry + 24, name_ranges[i].1 as u32);
+
+        let start = vertex_offset + attribute_offsets[i];
+        put_components(
+            &mut bytes[start..start + attribute.len_bytes()], attribute.data.as_bytes(),
+            attribute.format().component_size()
+        );
+    }
+    bytes[names_offset..names_offset + names.len()].copy_from_slice(&names);
+    if let Some(indices) = mesh.indices() {
+        let data = unsafe { ::std::slice::from_raw_parts(indices.as_ptr(), index_len) };
+        put_components(&mut bytes[index_offset..index_offset + index_len], data, index_size);
+    }
+
+    let bounds = mesh.bounds();
+    bytes[0..4].copy_from_slice(&MAGIC);
+    put_u32(&mut bytes, 4, VERSION);
+    put_u32(&mut bytes, 8, HEADER_SIZE as u32);
+    put_u32(&mut bytes, 12, mesh.len() as u32);
+    put_u32(&mut bytes, 16, attributes.len() as u32);
+    put_u32(&mut bytes, 20, index_size as u32);
+    put_u32(&mut bytes, 24, index_count as u32);
+    let values = bounds.aabb.min.iter().chain(bounds.aabb.max.iter()).chain(bounds.sphere.center.iter());
+    for (i, &value) in values.enumerate() {
+        put_f32(&mut bytes, 32 + 4 * i, value);
+    }
+    put_f32(&mut bytes, 68, bounds.sphere.radius);
+    put_u32(&mut bytes, 72, table_offset as u32);
+    put_u32(&mut bytes, 76, vertex_offset as u32);
+    put_u32(&mut bytes, 80, vertex_len as u32);
+    put_u32(&mut bytes, 84, index_offset as u32);
+    put_u32(&mut bytes, 88, index_len as u32);
+    let checksum = crc32(&bytes[HEADER_SIZE..]);
+    put_u32(&mut bytes, 28, checksum);
+
+    bytes
+}
+
+/// Write a mesh to a file in the binary mesh format.
+pub fn save<P: AsRef<Path>>(mesh: &Mesh, path: P) -> io::Result<()> {
+    fs::write(path, write(mesh))
+}
+
+/// An attribute of a mesh file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileAttribute {
+    pub semantic: Semantic,
+    pub format: AttributeFormat,
+    /// The offset of the values from the start of the vertex block.
+    pub offset: usize,
+    pub len_bytes: usize,
+}
+
+/// A mesh file read in place. It borrows the bytes of the file, e.g. a memory
+/// mapping of it, and presents the vertex and index blocks as slices of them.
+#[derive(Clone, Debug)]
+pub struct MeshFileView<'a> {
+    bytes: &'a [u8],
+    pub vertex_count: usize,
+    pub attributes: Vec<FileAttribute>,
+    /// The size of one index, or zero for a mesh without indices.
+    pub index_size: usize,
+    pub index_count: usize,
+    pub bounds: Bounds,
+    vertex_range: (usize, usize),
+    index_range: (usize, usize),
+}
+
+impl<'a> MeshFileView<'a> {
+    /// Check the header, table and checksum of a mesh file, and that every index
+    /// refers to a vertex. The vertex data is not parsed.
+    pub fn parse(bytes: &'a [u8]) -> Result<MeshFileView<'a>, MeshFileError> {
+        if bytes.len() < 4 || bytes[0..4] != MAGIC {
+            return Err(MeshFileError::BadMagic);
+        }
+        if bytes.len() < HEADER_SIZE {
+            return Err(MeshFileError::Truncated);
+        }
+        let version = get_u32(bytes, 4);
+        if version != VERSION {
+            return Err(MeshFileError::UnsupportedVersion(version));
+        }
+        let malformed = |reason: &str| MeshFileError::Malformed(String::from(reason));
+
+        let vertex_count = get_u32(bytes, 12) as usize;
+        let attribute_count = get_u32(bytes, 16) as usize;
+        let index_size = get_u32(bytes, 20) as usize;
+        let index_count = get_u32(bytes, 24) as usize;
+        let table_offset = get_u32(bytes, 72) as usize;
+        let vertex_range = (get_u32(bytes, 76) as usize, get_u32(bytes, 80) as usize);
+        let index_range = (get_u32(bytes, 84) as usize, get_u32(bytes, 88) as usize);
+        let table_end = table_offset + attribute_count * ATTRIBUTE_ENTRY_SIZE;
+        if table_end > bytes.len() || vertex_range.0 + vertex_range.1 > bytes.len()
+            || index_range.0 + index_range.1 > bytes.len() {
+            return Err(MeshFileError::Truncated);
+        }
+
+        let expected = get_u32(bytes, 28);
+        let actual = crc32(&bytes[HEADER_SIZE..]);
+        if expected != actual {
+            return Err(MeshFileError::ChecksumMismatch(expected, actual));
+        }
+
+        if index_size != 0 && index_size != 2 && index_size != 4 {
+            return Err(malformed("the index size is not 0, 2 or 4 bytes"));
+        }
+        if index_size * index_count != index_range.1 {
+            return Err(malformed("the index block does not hold the index count"));
+        }
+        let index_data = &bytes[index_range.0..index_range.0 + index_range.1];
+        let index_out_of_range = match index_size {
+            2 => index_data.chunks_exact(2).any(|b| u16::from_le_bytes([b[0], b[1]]) as usize >= vertex_count),
+            4 => index_data.chunks_exact(4).any(|b| get_u32(b, 0) as usize >= vertex_count),
+            _ => false,
+        };
+        if index_out_of_range {
+            return Err(malformed("an index refers to a vertex past the end of the mesh"));
+        }
+
+        let mut attributes = Vec::with_capacity(attribute_count);
+        for i in 0..attribute_count {
+            let entry = table_offset + i * ATTRIBUTE_ENTRY_SIZE;
+            let format = match format_from_code(get_u32(bytes, entry + 8)) {
+                Some(val) => val,
+                None => return Err(malformed("an attribute has an unknown format")),
+            };
+            let name_offset = get_u32(bytes, entry + 20) as usize;
+            let name_len = get_u32(bytes, entry + 24) as usize;
+            let name = match bytes.get(name_offset..name_offset + name_len).map(str::from_utf8) {
+                Some(Ok(val)) => val,
+                _ => return Err(malformed("an attribute has an invalid name")),
+            };
+            let semantic = match semantic_from_code(get_u32(bytes, entry), get_u32(bytes, entry + 4), name) {
+                Some(val) => val,
+                None => return Err(malformed("an attribute has an unknown semantic")),
+            };
+            let offset = get_u32(bytes, entry + 12) as usize;
+            let len_bytes = get_u32(bytes, entry + 16) as usize;
+            if len_bytes != vertex_count * format.size() || offset + len_bytes > vertex_range.1 {
+                return Err(malformed("an attribute does not hold one value per vertex"));
+            }
+            attributes.push(FileAttribute {
+                semantic: semantic,
+                format: format,
+                offset: offset,
+                len_bytes: len_bytes,
+            });
+        }
+
+        let bounds = Bounds {
+            aabb: Aabb {
+                min: [get_f32(bytes, 32), get_f32(bytes, 36), get_f32(bytes, 40)],
+                max: [get_f32(bytes, 44), get_f32(bytes, 48), get_f32(bytes, 52)],
+            },
+            sphere: BoundingSphere {
+                center: [get_f32(bytes, 56), get_f32(bytes, 60), get_f32(bytes, 64)],
+                radius: get_f32(bytes, 68),
+            },
+        };
+
+        Ok(MeshFileView {
+            bytes: bytes,
+            vertex_count: vertex_count,
+            attributes: attributes,
+            index_size: index_size,
+            index_count: index_count,
+            bounds: bounds,
+            vertex_range: vertex_range,
+            index_range: index_range,
+        })
+    }
+
+    /// The vertex block, ready to upload to a vertex buffer as is. Pass it to
+    /// `vertex_layout::setup_vertex_array` along with `vertex_layout`.
+    #[inline]
+    pub fn vertex_data(&self) -> &'a [u8] {
+        &self.bytes[self.vertex_range.0..self.vertex_range.0 + self.vertex_range.1]
+    }
+
+    /// The index block, ready to upload to an element array buffer as is.
+    #[inline]
+    pub fn index_data(&self) -> &'a [u8] {
+        &self.bytes[self.index_range.0..self.index_range.0 + self.index_range.1]
+    }
+
+    /// The values of an attribute, as a slice of the vertex block.
+    #[inline]
+    pub fn attribute_data(&self, attribute: &FileAttribute) -> &'a [u8] {
+        &self.vertex_data()[attribute.offset..attribute.offset + attribute.len_bytes]
+    }
+
+    /// The layout of the vertex block. Each attribute is its own tightly packed
+    /// array, so the block can feed a vertex array without repacking it.
+    pub fn vertex_layout(&self) -> VertexLayout {
+        let attributes = self.attributes.iter().map(|attribute| {
+            AttributeLayout {
+                semantic: attribute.semantic.clone(),
+                format: attribute.format,
+                layout: BufferLayout {
+                    offset: Offset(attribute.offset),
+                    stride: Stride(attribute.format.size()),
+                    size: Size(attribute.format.component_count()),
+                    ty: Type(attribute.format.gl_type()),
+                    kind: ComponentKind::from(attribute.format),
+                },
+            }
+        }).collect();
+
+        VertexLayout {
+            attributes: attributes,
+            stride: self.attributes.iter().map(|attribute| attribute.format.size()).sum(),
+        }
+    }
+
+    /// Decode the file into a mesh. This copies the vertex and index data.
+    pub fn to_mesh(&self) -> Result<Mesh, MeshFileError> {
+        let position = self.attributes.iter().find(|attribute| attribute.semantic == Semantic::Position);
+        let mut mesh = match position {
+            Some(attribute) => {
+                let points = AttributeData::from_bytes(attribute.format, self.attribute_data(attribute));
+                match points.as_f32x3() {
+                    Some(points) => Mesh::from_points(points),
+                    None => return Err(MeshFileError::Malformed(String::from("the positions are not F32x3"))),
+                }
+            }
+            None => return Err(MeshFileError::Malformed(String::from("the mesh has no positions"))),
+        };
+        for attribute in self.attributes.iter().filter(|attribute| attribute.semantic != Semantic::Position) {
+            let data = AttributeData::from_bytes(attribute.format, self.attribute_data(attribute));
+            mesh.set_attribute(attribute.semantic.clone(), data);
+        }
+
+        let data = self.index_data();
+        mesh.indices = match self.index_size {
+            2 => Some(Indices::U16(data.chunks_exact(2).map(|b| u16::from_le_bytes([b[0], b[1]])).collect())),
+            4 => Some(Indices::U32(data.chunks_exact(4).map(|b| get_u32(b, 0)).collect())),
+            _ => None,
+        };
+
+        Ok(mesh)
+    }
+}
+
+/// Read a mesh file into a mesh.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Mesh, MeshFileError> {
+    let bytes = match fs::read(&path) {
+        Ok(val) => val,
+        Err(_) => return Err(MeshFileError::FileNotFound(path.as_ref().display().to_string())),
+    };
+
+    MeshFileView::parse(&bytes)?.to_mesh()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    fn test_mesh() -> Mesh {
+        let points = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [1.0, 1.0, 0.5]];
+        let tex_coords = [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0], [1.0, 1.0]];
+        let mut mesh = Mesh::new_indexed(&points, &tex_coords, &[0, 1, 2, 2, 1, 3]).unwrap();
+        mesh.set_attribute(Semantic::Joints(0), AttributeData::U16x4(vec![[0, 1, 2, 3]; 4]));
+        mesh.set_attribute(Semantic::Color(0), AttributeData::Unorm8x4(vec![[255, 128, 0, 255]; 4]));
+        mesh.set_attribute(Semantic::Custom(String::from("_WIND")), AttributeData::F32x1(vec![0.5, 1.5, 2.5, 3.5]));
+
+        mesh
+    }
+
+    /// Recompute the checksum after editing the contents of a mesh file.
+    fn fix_checksum(bytes: &mut [u8]) {
+        let checksum = crc32(&bytes[HEADER_SIZE..]);
+        put_u32(bytes, 28, checksum);
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_write_parse_round_trip() {
+        let mesh = test_mesh();
+        let bytes = write(&mesh);
+        let view = MeshFileView::parse(&bytes).unwrap();
+
+        assert_eq!(view.vertex_count, 4);
+        assert_eq!(view.index_size, 2);
+        assert_eq!(view.index_count, 6);
+        assert_eq!(view.bounds, mesh.bounds());
+        assert_eq!(view.attributes.len(), mesh.attributes().len());
+        assert_eq!(view.to_mesh().unwrap(), mesh);
+    }
+
+    #[test]
+    fn test_write_is_little_endian() {
+        let bytes = write(&test_mesh());
+        let view = MeshFileView::parse(&bytes).unwrap();
+
+        let wind_semantic = Semantic::Custom(String::from("_WIND"));
+        let wind = view.attributes.iter().find(|attribute| attribute.semantic == wind_semantic).unwrap();
+        assert_eq!(&view.attribute_data(wind)[0..4], &0.5_f32.to_le_bytes());
+        assert_eq!(&view.index_data()[0..6], &[0, 0, 1, 0, 2, 0]);
+    }
+
+    #[test]
+    fn test_parse_rejects_corrupted_checksum() {
+        let mut bytes = write(&test_mesh());
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xFF;
+
+        match MeshFileView::parse(&bytes) {
+            Err(MeshFileError::ChecksumMismatch(expected, actual)) => assert_ne!(expected, actual),
+            other => panic!("expected a checksum mismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_rejects_indices_out_of_range() {
+        let mut bytes = write(&test_mesh());
+        let index_offset = get_u32(&bytes, 84) as usize;
+        bytes[index_offset] = 4;
+        fix_checksum(&mut bytes);
+
+        match MeshFileView::parse(&bytes) {
+            Err(MeshFileError::Malformed(_)) => {}
+            other => panic!("expected a malformed mesh file, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_rejects_bad_headers() {
+        let bytes = write(&test_mesh());
+        assert_eq!(MeshFileView::parse(b"GLTF").unwrap_err(), MeshFileError::BadMagic);
+        assert_eq!(MeshFileView::parse(&bytes[..HEADER_SIZE - 1]).unwrap_err(), MeshFileError::Truncated);
+        assert_eq!(MeshFileView::parse(&bytes[..bytes.len() - 1]).unwrap_err(), MeshFileError::Truncated);
+
+        let mut future = bytes.clone();
+        put_u32(&mut future, 4, VERSION + 1);
+        assert_eq!(MeshFileView::parse(&future).unwrap_err(), MeshFileError::UnsupportedVersion(VERSION + 1));
+    }
+}
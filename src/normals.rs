@@ -0,0 +1,271 @@
+/*
+ *  GB-Prototype is a renderer prototype demo.
+ *  Copyright (C) 2018,2019,2020  Christopher Blanchard
+ *
+ *  This program is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  This program is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+#![allow(dead_code)]
+//! Generation of vertex normals and tangents for meshes.
+use crate::mesh::{AttributeData, Indices, Mesh, Semantic};
+
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+
+#[inline]
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+#[inline]
+fn add_scaled(a: [f32; 3], b: [f32; 3], s: f32) -> [f32; 3] {
+    [a[0] + b[0] * s, a[1] + b[1] * s, a[2] + b[2] * s]
+}
+
+#[inline]
+fn scale(a: [f32; 3], s: f32) -> [f32; 3] {
+    [a[0] * s, a[1] * s, a[2] * s]
+}
+
+#[inline]
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+#[inline]
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+/// Normalize a vector, or return `None` if it is too short to have a direction.
+#[inline]
+fn normalize(a: [f32; 3]) -> Option<[f32; 3]> {
+    let length = f32::sqrt(dot(a, a));
+    if length > 1e-20 {
+        Some([a[0] / length, a[1] / length, a[2] / length])
+    } else {
+        None
+    }
+}
+
+/// Remove the component of `v` along the unit vector `n`.
+#[inline]
+fn project(v: [f32; 3], n: [f32; 3]) -> [f32; 3] {
+    add_scaled(v, n, -dot(v, n))
+}
+
+/// The angle of a triangle at the corner `p` between the edges to `next` and
+/// `prev`, optionally measured in the plane perpendicular to `n`.
+fn corner_angle(p: [f32; 3], next: [f32; 3], prev: [f32; 3], n: Option<[f32; 3]>) -> f32 {
+    let (mut a, mut b) = (sub(next, p), sub(prev, p));
+    if let Some(n) = n {
+        a = project(a, n);
+        b = project(b, n);
+    }
+    match (normalize(a), normalize(b)) {
+        (Some(a), Some(b)) => f32::acos(f32::max(-1_f32, f32::min(1_f32, dot(a, b)))),
+        _ => 0_f32,
+    }
+}
+
+/// A hashable key for vectors of floats that compares them bit for bit.
+#[inline]
+fn key<A: AsRef<[f32]>>(v: &A) -> Vec<u32> {
+    v.as_ref().iter().map(|x| x.to_bits()).collect()
+}
+
+/// A direction perpendicular to a unit vector, for vertices whose tangent is not
+/// determined by their texture coordinates.
+fn any_perpendicular(n: [f32; 3]) -> [f32; 3] {
+    let axis = if f32::abs(n[0]) < 0.9 { [1_f32, 0_f32, 0_f32] } else { [0_f32, 1_f32, 0_f32] };
+    normalize(project(axis, n)).unwrap_or([1_f32, 0_f32, 0_f32])
+}
+
+impl Mesh {
+    /// Give every triangle corner its own value of a new attribute. Corners of
+    /// the same vertex with equal values keep sharing it, and the others get copies
+    /// of the vertex. Vertices that no triangle uses are dropped. Returns the values
+    /// for the new vertices.
+    fn split_vertices<T: Copy + AsRef<[f32]>>(&mut self, corners: &[u32], values: &[T]) -> Vec<T> {
+        let mut vertices = HashMap::new();
+        let mut sources = vec![];
+        let mut new_values = vec![];
+        let mut new_corners = Vec::with_capacity(corners.len());
+        for (&vertex, value) in corners.iter().zip(values.iter()) {
+            let index = *vertices.entry((vertex, key(value))).or_insert_with(|| {
+                sources.push(vertex);
+                new_values.push(*value);
+                (sources.len() - 1) as u32
+            });
+            new_corners.push(index);
+        }
+
+        // A mesh without indices keeps none if every corner still has its own vertex.
+        if self.indices.is_some() || sources.len() != self.len() {
+            self.gather_vertices(&sources);
+            self.indices = Some(Indices::new(&new_corners));
+        }
+
+        new_values
+    }
+
+    /// Compute vertex normals from the triangles of the mesh. The normal at a
+    /// corner averages the normals of the triangles around its position, weighted
+    /// by the angle of each triangle at that position. Triangles whose normals
+    /// differ by more than `hard_angle` radians are not averaged, so the edge
+    /// between them is hard. Vertices are split where their corners end up with
+    /// different normals.
+    pub fn compute_normals(&mut self, hard_angle: f32) {
+        let positions = self.points().to_vec();
+        let corners = self.triangle_corners();
+        let triangle_count = corners.len() / 3;
+        let corners = &corners[..triangle_count * 3];
+        let corner_position = |corner: usize| positions[corners[corner] as usize];
+
+        let face_normals: Vec<Option<[f32; 3]>> = (0..triangle_count).map(|t| {
+            let (a, b, c) = (corner_position(3 * t), corner_position(3 * t + 1), corner_position(3 * t + 2));
+            normalize(cross(sub(b, a), sub(c, a)))
+        }).collect();
+        let weights: Vec<f32> = (0..corners.len()).map(|corner| {
+            let t = corner / 3;
+            let next = 3 * t + (corner + 1) % 3;
+            let prev = 3 * t + (corner + 2) % 3;
+            corner_angle(corner_position(corner), corner_position(next), corner_position(prev), None)
+        }).collect();
+
+        // Corners at the same position, whatever their vertex, are smoothed
+        // together so that seams in the texture coordinates do not show.
+        let mut shared: HashMap<Vec<u32>, Vec<usize>> = HashMap::new();
+        for corner in 0..corners.len() {
+            shared.entry(key(&corner_position(corner))).or_insert_with(Vec::new).push(corner);
+        }
+
+        let threshold = f32::cos(f32::min(hard_angle, PI)) - 1e-6;
+        let normals: Vec<[f32; 3]> = (0..corners.len()).map(|corner| {
+            let neighbours = &shared[&key(&corner_position(corner))];
+            let face_normal = face_normals[corner / 3];
+            let mut sum = [0_f32; 3];
+            for &other in neighbours.iter() {
+                let other_normal = match face_normals[other / 3] {
+                    Some(val) => val,
+                    None => continue,
+                };
+                if face_normal.map_or(false, |n| dot(n, other_normal) >= threshold) {
+                    sum = add_scaled(sum, other_normal, weights[other]);
+                }
+            }
+
+            // A degenerate triangle takes the average normal of its neighbours.
+            normalize(sum)
+                .or_else(|| {
+                    let all = neighbours.iter().filter_map(|&other| face_normals[other / 3]);
+                    normalize(all.fold([0_f32; 3], |sum, n| add_scaled(sum, n, 1_f32)))
+                })
+                .unwrap_or([0_f32, 0_f32, 1_f32])
+        }).collect();
+
+        let normals = self.split_vertices(corners, &normals);
+        self.set_attribute(Semantic::Normal, AttributeData::F32x3(normals));
+    }
+
+    /// Compute normals that are averaged across every edge.
+    #[inline]
+    pub fn compute_smooth_normals(&mut self) {
+        self.compute_normals(PI);
+    }
+
+    /// Compute normals that are flat across each triangle.
+    #[inline]
+    pub fn compute_flat_normals(&mut self) {
+        self.compute_normals(0_f32);
+    }
+
+    /// Compute tangents for normal mapping from the first texture coordinate set,
+    /// following MikkTSpace so that they match the tangent space normal maps are
+    /// baked in. The tangents are stored as four components, where the last is
+    /// the sign of the bitangent: `bitangent = w * cross(normal, tangent)`.
+    ///
+    /// Smooth normals are computed first if the mesh has none. Returns `false`
+    /// without changing the mesh if it has no texture coordinates.
+    pub fn compute_tangents(&mut self) -> bool {
+        if self.attribute(&Semantic::TexCoord(0)).and_then(|a| a.data.as_f32x2()).is_none() {
+            return false;
+        }
+        if self.normals().is_none() {
+            self.compute_smooth_normals();
+        }
+
+        let positions = self.points().to_vec();
+        let normals = self.normals().unwrap_or(&[]).to_vec();
+        let tex_coords = self.tex_coords().to_vec();
+        let corners = self.triangle_corners();
+        let triangle_count = corners.len() / 3;
+        let corners = &corners[..triangle_count * 3];
+
+        // The direction of increasing `u` across each triangle and whether the
+        // texture mapping preserves the orientation of the triangle.
+        let faces: Vec<([f32; 3], bool)> = (0..triangle_count).map(|t| {
+            let [i0, i1, i2] = [corners[3 * t] as usize, corners[3 * t + 1] as usize, corners[3 * t + 2] as usize];
+            let (e1, e2) = (sub(positions[i1], positions[i0]), sub(positions[i2], positions[i0]));
+            let s1 = [tex_coords[i1][0] - tex_coords[i0][0], tex_coords[i1][1] - tex_coords[i0][1]];
+            let s2 = [tex_coords[i2][0] - tex_coords[i0][0], tex_coords[i2][1] - tex_coords[i0][1]];
+            let signed_area = s1[0] * s2[1] - s1[1] * s2[0];
+            let preserving = signed_area > 0_f32;
+            let tangent = add_scaled(scale(e1, s2[1]), e2, -s1[1]);
+            let tangent = if preserving { tangent } else { scale(tangent, -1_f32) };
+
+            (normalize(tangent).unwrap_or([0_f32; 3]), preserving)
+        }).collect();
+
+        // Corners share a tangent when they have the same position, normal and
+        // texture coordinate, and their triangles have the same orientation in
+        // texture space. Mirrored halves of a model keep separate tangents.
+        let mut groups: HashMap<(Vec<u32>, bool), [f32; 3]> = HashMap::new();
+        let group_key = |corner: usize| {
+            let vertex = corners[corner] as usize;
+            let mut value = key(&positions[vertex]);
+            value.extend(key(&normals[vertex]));
+            value.extend(key(&tex_coords[vertex]));
+            (value, faces[corner / 3].1)
+        };
+        for corner in 0..corners.len() {
+            let t = corner / 3;
+            let vertex = corners[corner] as usize;
+            let next = corners[3 * t + (corner + 1) % 3] as usize;
+            let prev = corners[3 * t + (corner + 2) % 3] as usize;
+            let n = normals[vertex];
+            let tangent = match normalize(project(faces[t].0, n)) {
+                Some(val) => val,
+                None => continue,
+            };
+            let weight = corner_angle(positions[vertex], positions[next], positions[prev], Some(n));
+            let sum = groups.entry(group_key(corner)).or_insert([0_f32; 3]);
+            *sum = add_scaled(*sum, tangent, weight);
+        }
+
+        let tangents: Vec<[f32; 4]> = (0..corners.len()).map(|corner| {
+            let (value, preserving) = group_key(corner);
+            let n = normals[corners[corner] as usize];
+            let tangent = groups.get(&(value, preserving))
+                .and_then(|&sum| normalize(sum))
+                .unwrap_or_else(|| any_perpendicular(n));
+            [tangent[0], tangent[1], tangent[2], if preserving { 1_f32 } else { -1_f32 }]
+        }).collect();
+
+        let tangents = self.split_vertices(corners, &tangents);
+        self.set_attribute(Semantic::Tangent, AttributeData::F32x4(tangents));
+
+        true
+    }
+}
@@ -0,0 +1,558 @@
+/*
+ *  GB-Prototype is a renderer prototype demo.
+ *  Copyright (C) 2018,2019,2020  Christopher Blanchard
+ *
+ *  This program is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  This program is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+#![allow(dead_code)]
+//! A loader for Wavefront OBJ models and their MTL material libraries.
+use crate::mesh::{AttributeData, Indices, Mesh, Semantic};
+
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::{info, warn};
+
+
+#[derive(Clone, Debug)]
+pub enum ObjError {
+    ObjNotFound(String),
+    CouldNotParseObj(String, usize, String),
+    CouldNotParseMtl(String, usize, String),
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &ObjError::ObjNotFound(ref file_name) => {
+                write!(f, "Could not open the OBJ file for reading: {}", file_name)
+            }
+            &ObjError::CouldNotParseObj(ref file_name, line, ref reason) => {
+                write!(f, "The OBJ file {} is malformed on line {}: {}", file_name, line, reason)
+            }
+            &ObjError::CouldNotParseMtl(ref file_name, line, ref reason) => {
+                write!(f, "The MTL file {} is malformed on line {}: {}", file_name, line, reason)
+            }
+        }
+    }
+}
+
+impl error::Error for ObjError {}
+
+/// A material from an MTL library. Texture paths are relative to the working
+/// directory, i.e. they already include the directory of the library.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Material {
+    pub name: String,
+    pub ambient: [f32; 3],
+    pub diffuse: [f32; 3],
+    pub specular: [f32; 3],
+    pub emissive: [f32; 3],
+    pub shininess: f32,
+    pub optical_density: f32,
+    /// The opacity of the material. 1 is fully opaque.
+    pub dissolve: f32,
+    pub illumination_model: u32,
+    pub ambient_map: Option<PathBuf>,
+    pub diffuse_map: Option<PathBuf>,
+    pub specular_map: Option<PathBuf>,
+    pub shininess_map: Option<PathBuf>,
+    pub dissolve_map: Option<PathBuf>,
+    pub bump_map: Option<PathBuf>,
+    pub normal_map: Option<PathBuf>,
+    pub displacement_map: Option<PathBuf>,
+}
+
+impl Material {
+    /// A material with the default MTL values.
+    pub fn new(name: &str) -> Material {
+        Material {
+            name: String::from(name),
+            ambient: [0.0, 0.0, 0.0],
+            diffuse: [0.8, 0.8, 0.8],
+            specular: [0.0, 0.0, 0.0],
+            emissive: [0.0, 0.0, 0.0],
+            shininess: 0.0,
+            optical_density: 1.0,
+            dissolve: 1.0,
+            illumination_model: 2,
+            ambient_map: None,
+            diffuse_map: None,
+            specular_map: None,
+            shininess_map: None,
+            dissolve_map: None,
+            bump_map: None,
+            normal_map: None,
+            displacement_map: None,
+        }
+    }
+}
+
+/// The faces of an OBJ file that share an object, a group and a material.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ObjMesh {
+    pub object: String,
+    pub group: String,
+    pub material: Option<String>,
+    pub mesh: Mesh,
+}
+
+/// The meshes and materials of an OBJ file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ObjModel {
+    pub meshes: Vec<ObjMesh>,
+    pub materials: Vec<Material>,
+}
+
+impl ObjModel {
+    /// Look up a material by name.
+    pub fn material(&self, name: &str) -> Option<&Material> {
+        self.materials.iter().find(|material| material.name == name)
+    }
+}
+
+/// A reference to the position, texture coordinates and normal of a face vertex.
+/// These are zero based indices into the arrays of the whole file.
+type VertexKey = (usize, Option<usize>, Option<usize>);
+
+/// Accumulates the faces of one mesh. Each distinct combination of position,
+/// texture coordinate and normal becomes one vertex.
+struct MeshBuilder {
+    object: String,
+    group: String,
+    material: Option<String>,
+    vertices: HashMap<VertexKey, u32>,
+    points: Vec<[f32; 3]>,
+    tex_coords: Vec<Option<[f32; 2]>>,
+    normals: Vec<Option<[f32; 3]>>,
+    indices: Vec<u32>,
+}
+
+impl MeshBuilder {
+    fn new(object: &str, group: &str, material: Option<&str>) -> MeshBuilder {
+        MeshBuilder {
+            object: String::from(object),
+            group: String::from(group),
+            material: material.map(String::from),
+            vertices: HashMap::new(),
+            points: vec![],
+            tex_coords: vec![],
+            normals: vec![],
+            indices: vec![],
+        }
+    }
+
+    fn vertex(&mut self, key: VertexKey, data: &ObjData) -> u32 {
+        if let Some(&index) = self.vertices.get(&key) {
+            return index;
+        }
+
+        let index = self.points.len() as u32;
+        self.points.push(data.points[key.0]);
+        self.tex_coords.push(key.1.map(|i| data.tex_coords[i]));
+        self.normals.push(key.2.map(|i| data.normals[i]));
+        self.vertices.insert(key, index);
+
+        index
+    }
+
+    /// Build the mesh. A mesh gets texture coordinates or normals if any of its
+    /// vertices has them, and the vertices without them get zeros.
+    fn build(self) -> ObjMesh {
+        let mut mesh = Mesh::from_points(&self.points);
+        if self.tex_coords.iter().any(|tex_coord| tex_coord.is_some()) {
+            let tex_coords = self.tex_coords.iter().map(|tex_coord| tex_coord.unwrap_or([0.0; 2])).collect();
+            mesh.set_attribute(Semantic::TexCoord(0), AttributeData::F32x2(tex_coords));
+        }
+        if self.normals.iter().any(|normal| normal.is_some()) {
+            let normals = self.normals.iter().map(|normal| normal.unwrap_or([0.0; 3])).collect();
+            mesh.set_attribute(Semantic::Normal, AttributeData::F32x3(normals));
+        }
+        mesh.indices = Some(Indices::new(&self.indices));
+
+        ObjMesh {
+            object: self.object,
+            group: self.group,
+            material: self.material,
+            mesh: mesh,
+        }
+    }
+}
+
+/// The vertex data of a whole OBJ file. Faces index into these arrays.
+struct ObjData {
+    points: Vec<[f32; 3]>,
+    tex_coords: Vec<[f32; 2]>,
+    normals: Vec<[f32; 3]>,
+}
+
+fn parse_floats(fields: &[&str], min: usize, max: usize) -> Result<Vec<f32>, String> {
+    if fields.len() < min || fields.len() > max {
+        return Err(format!("expected between {} and {} numbers, got {}", min, max, fields.len()));
+    }
+    let mut values = Vec::with_capacity(fields.len());
+    for field in fields.iter() {
+        let value = field.parse::<f32>().map_err(|_| format!("invalid number `{}`", field))?;
+        values.push(value);
+    }
+
+    Ok(values)
+}
+
+/// Resolve a one based OBJ index, which counts back from the end of the list when
+/// it is negative, to a zero based index.
+fn resolve_index(field: &str, count: usize, kind: &str) -> Result<usize, String> {
+    let index = field.parse::<i64>().map_err(|_| format!("invalid {} index `{}`", kind, field))?;
+    let resolved = if index > 0 {
+        index - 1
+    } else if index < 0 {
+        count as i64 + index
+    } else {
+        return Err(format!("{} indices start at 1", kind));
+    };
+    if resolved < 0 || resolved >= count as i64 {
+        return Err(format!("{} index {} is out of range; there are {}", kind, index, count));
+    }
+
+    Ok(resolved as usize)
+}
+
+/// Parse a face vertex of the form `v`, `v/vt`, `v//vn` or `v/vt/vn`.
+fn parse_face_vertex(field: &str, data: &ObjData) -> Result<VertexKey, String> {
+    let parts: Vec<&str> = field.split('/').collect();
+    if parts.len() > 3 {
+        return Err(format!("invalid face vertex `{}`", field));
+    }
+    let point = resolve_index(parts[0], data.points.len(), "position")?;
+    let tex_coord = match parts.get(1) {
+        Some(part) if !part.is_empty() => Some(resolve_index(part, data.tex_coords.len(), "texture coordinate")?),
+        _ => None,
+    };
+    let normal = match parts.get(2) {
+        Some(part) if !part.is_empty() => Some(resolve_index(part, data.normals.len(), "normal")?),
+        _ => None,
+    };
+
+    Ok((point, tex_coord, normal))
+}
+
+/// The name given by a statement such as `o`, `g` or `usemtl`. Names may contain spaces.
+fn statement_name(fields: &[&str], default: &str) -> String {
+    if fields.is_empty() {
+        String::from(default)
+    } else {
+        fields.join(" ")
+    }
+}
+
+/// Twice the signed area of the triangle `o`, `a`, `b`. It is positive when the
+/// triangle winds counter clockwise.
+#[inline]
+fn cross2(o: [f32; 2], a: [f32; 2], b: [f32; 2]) -> f32 {
+    (a[0] - o[0]) * (b[1] - o[1]) - (a[1] - o[1]) * (b[0] - o[0])
+}
+
+/// Determine whether the corner `cur` of a counter clockwise polygon is an ear: it is
+/// convex, and no other corner lies inside the triangle it makes with its neighbours.
+fn is_ear(projected: &[[f32; 2]], remaining: &[usize], prev: usize, cur: usize, next: usize) -> bool {
+    let (a, b, c) = (projected[prev], projected[cur], projected[next]);
+    if cross2(a, b, c) <= 0_f32 {
+        return false;
+    }
+
+    remaining.iter()
+        .map(|&k| projected[k])
+        .filter(|&p| p != a && p != b && p != c)
+        .all(|p| cross2(a, b, p) < 0_f32 || cross2(b, c, p) < 0_f32 || cross2(c, a, p) < 0_f32)
+}
+
+/// Triangulate a polygon by ear clipping, so concave faces come out right. Returns
+/// triangles of indices into `points`, wound the same way as the polygon. A convex
+/// polygon gets a fan around its first corner. A polygon with no ears left, e.g. a
+/// self intersecting one, falls back to a fan over the corners that remain.
+fn triangulate_polygon(points: &[[f32; 3]]) -> Vec<[usize; 3]> {
+    let fan = |corners: &[usize]| -> Vec<[usize; 3]> {
+        (1..(corners.len() - 1)).map(|j| [corners[0], corners[j], corners[j + 1]]).collect()
+    };
+    let mut remaining: Vec<usize> = (0..points.len()).collect();
+    if points.len() == 3 {
+        return fan(&remaining);
+    }
+
+    // Newell's method gives the normal of the polygon, even if it is not quite planar.
+    let mut normal = [0_f32; 3];
+    for i in 0..points.len() {
+        let a = points[i];
+        let b = points[(i + 1) % points.len()];
+        normal[0] += (a[1] - b[1]) * (a[2] + b[2]);
+        normal[1] += (a[2] - b[2]) * (a[0] + b[0]);
+        normal[2] += (a[0] - b[0]) * (a[1] + b[1]);
+    }
+    // Project onto the coordinate plane the polygon faces most, mirroring it when it
+    // faces away so the projected polygon winds counter clockwise.
+    let abs = [normal[0].abs(), normal[1].abs(), normal[2].abs()];
+    let (u, v, facing) = if abs[2] >= abs[0] && abs[2] >= abs[1] {
+        (0, 1, normal[2])
+    } else if abs[0] >= abs[1] {
+        (1, 2, normal[0])
+    } else {
+        (2, 0, normal[1])
+    };
+    if facing == 0_f32 {
+        return fan(&remaining);
+    }
+    let sign = facing.signum();
+    let projected: Vec<[f32; 2]> = points.iter().map(|p| [p[u] * sign, p[v]]).collect();
+
+    let mut triangles = Vec::with_capacity(points.len() - 2);
+    // Start at the second corner, so a convex polygon clips into a fan around the first.
+    let mut i = 1;
+    let mut misses = 0;
+    while remaining.len() > 3 {
+        let count = remaining.len();
+        if misses >= count {
+            triangles.extend(fan(&remaining));
+            return triangles;
+        }
+        let prev = remaining[(i + count - 1) % count];
+        let cur = remaining[i];
+        let next = remaining[(i + 1) % count];
+        if is_ear(&projected, &remaining, prev, cur, next) {
+            triangles.push([prev, cur, next]);
+            remaining.remove(i);
+            if i >= remaining.len() {
+                i = 0;
+            }
+            misses = 0;
+        } else {
+            i = (i + 1) % count;
+            misses += 1;
+        }
+    }
+    triangles.push([remaining[0], remaining[1], remaining[2]]);
+
+    triangles
+}
+
+/// Parse the text of an OBJ file. Returns the model without materials and the
+/// names of the material libraries the file references.
+pub fn parse_obj(text: &str, file_name: &str) -> Result<(ObjModel, Vec<String>), ObjError> {
+    let error = |line: usize, reason: String| {
+        ObjError::CouldNotParseObj(String::from(file_name), line, reason)
+    };
+
+    let mut data = ObjData { points: vec![], tex_coords: vec![], normals: vec![] };
+    let mut material_libraries = vec![];
+    let mut meshes = vec![];
+    let mut builder = MeshBuilder::new("default", "default", None);
+    for (i, line) in text.lines().enumerate() {
+        let line_number = i + 1;
+        let line = match line.find('#') {
+            Some(comment) => &line[..comment],
+            None => line,
+        };
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.is_empty() {
+            continue;
+        }
+
+        let args = &fields[1..];
+        match fields[0] {
+            "v" => {
+                // Some exporters append a vertex color to the position. We ignore it.
+                let values = parse_floats(args, 3, 7).map_err(|e| error(line_number, e))?;
+                data.points.push([values[0], values[1], values[2]]);
+            }
+            "vt" => {
+                let values = parse_floats(args, 1, 3).map_err(|e| error(line_number, e))?;
+                data.tex_coords.push([values[0], *values.get(1).unwrap_or(&0.0)]);
+            }
+            "vn" => {
+                let values = parse_floats(args, 3, 3).map_err(|e| error(line_number, e))?;
+                data.normals.push([values[0], values[1], values[2]]);
+            }
+            "f" => {
+                if args.len() < 3 {
+                    return Err(error(line_number, format!("a face needs at least 3 vertices, got {}", args.len())));
+                }
+                let mut face = Vec::with_capacity(args.len());
+                for arg in args.iter() {
+                    let key = parse_face_vertex(arg, &data).map_err(|e| error(line_number, e))?;
+                    face.push(builder.vertex(key, &data));
+                }
+                let corners: Vec<[f32; 3]> = face.iter().map(|&index| builder.points[index as usize]).collect();
+                for triangle in triangulate_polygon(&corners).iter() {
+                    builder.indices.extend_from_slice(&[face[triangle[0]], face[triangle[1]], face[triangle[2]]]);
+                }
+            }
+            "o" | "g" | "usemtl" => {
+                let mut object = builder.object.clone();
+                let mut group = builder.group.clone();
+                let mut material = builder.material.clone();
+                match fields[0] {
+                    "o" => {
+                        object = statement_name(args, "default");
+                        group = String::from("default");
+                    }
+                    "g" => group = statement_name(args, "default"),
+                    _ => material = Some(statement_name(args, "")),
+                }
+                let next = MeshBuilder::new(&object, &group, material.as_ref().map(|s| s.as_str()));
+                let finished = ::std::mem::replace(&mut builder, next);
+                if !finished.indices.is_empty() {
+                    meshes.push(finished.build());
+                }
+            }
+            "mtllib" => {
+                if args.is_empty() {
+                    return Err(error(line_number, String::from("mtllib needs a file name")));
+                }
+                material_libraries.push(args.join(" "));
+            }
+            "s" | "l" | "p" | "vp" => {
+                // Smoothing groups, lines, points and parameter space vertices do not
+                // contribute to triangle meshes.
+            }
+            other => {
+                info!("{}:{}: ignoring unsupported OBJ statement `{}`", file_name, line_number, other);
+            }
+        }
+    }
+    if !builder.indices.is_empty() {
+        meshes.push(builder.build());
+    }
+
+    Ok((ObjModel { meshes: meshes, materials: vec![] }, material_libraries))
+}
+
+/// Parse the file name of a texture map statement. The options before the name,
+/// e.g. `-s 1 1 1`, do not change which file the material uses, so we skip them.
+fn parse_map(args: &[&str], dir: &Path) -> Result<PathBuf, String> {
+    match args.last() {
+        Some(file_name) => Ok(dir.join(file_name)),
+        None => Err(String::from("a texture map needs a file name")),
+    }
+}
+
+fn parse_color(args: &[&str]) -> Result<[f32; 3], String> {
+    // A single value sets all three channels.
+    let values = parse_floats(args, 1, 3)?;
+    if values.len() == 2 {
+        return Err(String::from("expected 1 or 3 color components"));
+    }
+    let g = *values.get(1).unwrap_or(&values[0]);
+    let b = *values.get(2).unwrap_or(&values[0]);
+
+    Ok([values[0], g, b])
+}
+
+fn parse_scalar(args: &[&str]) -> Result<f32, String> {
+    parse_floats(args, 1, 1).map(|values| values[0])
+}
+
+/// Parse the text of an MTL file. Texture map paths are resolved against `dir`.
+pub fn parse_mtl(text: &str, file_name: &str, dir: &Path) -> Result<Vec<Material>, ObjError> {
+    let error = |line: usize, reason: String| {
+        ObjError::CouldNotParseMtl(String::from(file_name), line, reason)
+    };
+
+    let mut materials: Vec<Material> = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let line_number = i + 1;
+        let line = match line.find('#') {
+            Some(comment) => &line[..comment],
+            None => line,
+        };
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.is_empty() {
+            continue;
+        }
+
+        let args = &fields[1..];
+        if fields[0] == "newmtl" {
+            if args.is_empty() {
+                return Err(error(line_number, String::from("newmtl needs a material name")));
+            }
+            materials.push(Material::new(&args.join(" ")));
+            continue;
+        }
+        let material = match materials.last_mut() {
+            Some(val) => val,
+            None => return Err(error(line_number, format!("`{}` comes before any newmtl", fields[0]))),
+        };
+        let result = match fields[0] {
+            "Ka" => parse_color(args).map(|c| material.ambient = c),
+            "Kd" => parse_color(args).map(|c| material.diffuse = c),
+            "Ks" => parse_color(args).map(|c| material.specular = c),
+            "Ke" => parse_color(args).map(|c| material.emissive = c),
+            "Ns" => parse_scalar(args).map(|v| material.shininess = v),
+            "Ni" => parse_scalar(args).map(|v| material.optical_density = v),
+            "d" => parse_scalar(args).map(|v| material.dissolve = v),
+            "Tr" => parse_scalar(args).map(|v| material.dissolve = 1.0 - v),
+            "illum" => {
+                args.first().and_then(|arg| arg.parse::<u32>().ok())
+                    .map(|v| material.illumination_model = v)
+                    .ok_or_else(|| String::from("illum needs an illumination model number"))
+            }
+            "map_Ka" => parse_map(args, dir).map(|p| material.ambient_map = Some(p)),
+            "map_Kd" => parse_map(args, dir).map(|p| material.diffuse_map = Some(p)),
+            "map_Ks" => parse_map(args, dir).map(|p| material.specular_map = Some(p)),
+            "map_Ns" => parse_map(args, dir).map(|p| material.shininess_map = Some(p)),
+            "map_d" => parse_map(args, dir).map(|p| material.dissolve_map = Some(p)),
+            "map_bump" | "map_Bump" | "bump" => parse_map(args, dir).map(|p| material.bump_map = Some(p)),
+            "norm" | "map_Kn" => parse_map(args, dir).map(|p| material.normal_map = Some(p)),
+            "disp" => parse_map(args, dir).map(|p| material.displacement_map = Some(p)),
+            other => {
+                info!("{}:{}: ignoring unsupported MTL statement `{}`", file_name, line_number, other);
+                Ok(())
+            }
+        };
+        result.map_err(|e| error(line_number, e))?;
+    }
+
+    Ok(materials)
+}
+
+/// Load an OBJ file and the material libraries it references. Library paths are
+/// relative to the OBJ file. A missing library is logged and skipped, and the
+/// meshes that use its materials keep their material names.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<ObjModel, ObjError> {
+    let disp = path.as_ref().display().to_string();
+    let text = match fs::read_to_string(&path) {
+        Ok(val) => val,
+        Err(_) => return Err(ObjError::ObjNotFound(disp)),
+    };
+    let (mut model, material_libraries) = parse_obj(&text, &disp)?;
+
+    let dir = path.as_ref().parent().unwrap_or(Path::new(""));
+    for library in material_libraries.iter() {
+        let library_path = dir.join(library);
+        let library_disp = library_path.display().to_string();
+        let library_text = match fs::read_to_string(&library_path) {
+            Ok(val) => val,
+            Err(_) => {
+                warn!("Could not open the material library {} of {}", library_disp, disp);
+                continue;
+            }
+        };
+        let library_dir = library_path.parent().unwrap_or(Path::new(""));
+        let materials = parse_mtl(&library_text, &library_disp, library_dir)?;
+        model.materials.extend(materials);
+    }
+
+    Ok(model)
+}
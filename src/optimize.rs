@@ -0,0 +1,391 @@
+/*
+ *  GB-Prototype is a renderer prototype demo.
+ *  Copyright (C) 2018,2019,2020  Christopher Blanchard
+ *
+ *  This program is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  This program is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+#![allow(dead_code)]
+//! Optimisation passes for mesh data: welding duplicate vertices, reordering
+//! triangles for the post-transform vertex cache, and reordering vertices for
+//! fetch locality.
+use crate::mesh::{AttributeData, Indices, Mesh};
+
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::fmt;
+
+use log::info;
+
+
+/// The size of the FIFO cache used to measure the average cache miss ratio.
+pub const ACMR_CACHE_SIZE: usize = 32;
+
+/// The size of the LRU cache the triangle order is optimised for.
+const CACHE_SIZE: usize = 32;
+const CACHE_DECAY_POWER: f32 = 1.5;
+const LAST_TRIANGLE_SCORE: f32 = 0.75;
+const VALENCE_BOOST_SCALE: f32 = 2.0;
+const VALENCE_BOOST_POWER: f32 = 0.5;
+
+/// The effect of `Mesh::optimize` on a mesh.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct OptimizeReport {
+    pub vertices_before: usize,
+    pub vertices_after: usize,
+    pub acmr_before: f32,
+    pub acmr_after: f32,
+}
+
+impl fmt::Display for OptimizeReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "vertices {} -> {}, ACMR {:.3} -> {:.3}",
+            self.vertices_before, self.vertices_after, self.acmr_before, self.acmr_after
+        )
+    }
+}
+
+/// The values of a vertex across every attribute as a hashable key. Float
+/// components are snapped to multiples of `tolerance` so that nearly equal
+/// values compare equal. Integer components are compared exactly.
+fn vertex_key(data: &[&AttributeData], vertex: usize, tolerance: f32) -> Vec<i64> {
+    let snap = |x: f32| {
+        if tolerance > 0_f32 {
+            (x / tolerance).round() as i64
+        } else {
+            // Without a tolerance, compare the bits, treating 0 and -0 as equal.
+            (x + 0_f32).to_bits() as i64
+        }
+    };
+
+    let mut key = vec![];
+    for attribute in data.iter() {
+        match **attribute {
+            AttributeData::F32x1(ref inner) => key.push(snap(inner[vertex])),
+            AttributeData::F32x2(ref inner) => key.extend(inner[vertex].iter().map(|&x| snap(x))),
+            AttributeData::F32x3(ref inner) => key.extend(inner[vertex].iter().map(|&x| snap(x))),
+            AttributeData::F32x4(ref inner) => key.extend(inner[vertex].iter().map(|&x| snap(x))),
+            _ => {
+                let size = attribute.format().size();
+                let bytes = &attribute.as_bytes()[vertex * size..(vertex + 1) * size];
+                key.extend(bytes.iter().map(|&b| b as i64));
+            }
+        }
+    }
+
+    key
+}
+
+/// The score of a vertex for the next triangle to draw, following Tom Forsyth's
+/// linear speed vertex cache optimisation. Vertices in the cache score high, and
+/// vertices with few triangles left score high so that they get finished off.
+fn vertex_score(cache_position: Option<usize>, remaining_triangles: usize) -> f32 {
+    if remaining_triangles == 0 {
+        return -1_f32;
+    }
+
+    let cache_score = match cache_position {
+        None => 0_f32,
+        // The vertices of the last triangle get a fixed score, so that the order
+        // they were used in does not matter.
+        Some(position) if position < 3 => LAST_TRIANGLE_SCORE,
+        Some(position) => {
+            let scale = 1_f32 / (CACHE_SIZE - 3) as f32;
+            f32::powf(1_f32 - (position - 3) as f32 * scale, CACHE_DECAY_POWER)
+        }
+    };
+    let valence_boost = VALENCE_BOOST_SCALE * f32::powf(remaining_triangles as f32, -VALENCE_BOOST_POWER);
+
+    cache_score + valence_boost
+}
+
+/// Compute the average cache miss ratio of a triangle list: the number of vertices
+/// transformed per triangle with a FIFO post-transform cache of `cache_size`
+/// vertices. It ranges from 3 for no reuse down to about 0.5 for a regular grid.
+pub fn acmr(indices: &[u32], cache_size: usize) -> f32 {
+    let triangle_count = indices.len() / 3;
+    if triangle_count == 0 {
+        return 0_f32;
+    }
+
+    let mut cache = VecDeque::with_capacity(cache_size);
+    let mut misses = 0;
+    for &index in indices.iter() {
+        if !cache.contains(&index) {
+            misses += 1;
+            if cache.len() == cache_size {
+                cache.pop_front();
+            }
+            cache.push_back(index);
+        }
+    }
+
+    misses as f32 / triangle_count as f32
+}
+
+/// Reorder the triangles of a triangle list for the post-transform vertex cache.
+/// Returns the new index list.
+pub fn optimize_triangle_order(indices: &[u32], vertex_count: usize) -> Vec<u32> {
+    let triangle_count = indices.len() / 3;
+    let mut vertex_triangles: Vec<Vec<usize>> = vec![vec![]; vertex_count];
+    for triangle in 0..triangle_count {
+        for &index in indices[3 * triangle..3 * triangle + 3].iter() {
+            vertex_triangles[index as usize].push(triangle);
+        }
+    }
+
+    let mut cache_positions: Vec<Option<usize>> = vec![None; vertex_count];
+    let mut scores: Vec<f32> = vertex_triangles.iter().map(|triangles| vertex_score(None, triangles.len())).collect();
+    let triangle_score = |triangle: usize, scores: &[f32]| {
+        indices[3 * triangle..3 * triangle + 3].iter().map(|&i| scores[i as usize]).sum::<f32>()
+    };
+    let mut triangle_scores: Vec<f32> = (0..triangle_count).map(|t| triangle_score(t, &scores)).collect();
+    let mut added = vec![false; triangle_count];
+
+    let mut cache: Vec<u32> = Vec::with_capacity(CACHE_SIZE + 3);
+    let mut output = Vec::with_capacity(triangle_count * 3);
+    let mut next_unadded = 0;
+    let mut best = None;
+    for _ in 0..triangle_count {
+        // Without a candidate next to the cache, start again from the first
+        // triangle that has not been drawn.
+        let triangle = match best {
+            Some(val) => val,
+            None => {
+                while added[next_unadded] {
+                    next_unadded += 1;
+                }
+                next_unadded
+            }
+        };
+        added[triangle] = true;
+        let corners = &indices[3 * triangle..3 * triangle + 3];
+        output.extend_from_slice(corners);
+
+        for &index in corners.iter() {
+            let triangles = &mut vertex_triangles[index as usize];
+            if let Some(position) = triangles.iter().position(|&t| t == triangle) {
+                triangles.swap_remove(position);
+            }
+        }
+
+        // Move the vertices of the triangle to the front of the cache. The
+        // vertices pushed out of the cache still need new scores.
+        let mut new_cache: Vec<u32> = corners.to_vec();
+        new_cache.extend(cache.iter().filter(|index| !corners.contains(index)));
+        for (position, &index) in new_cache.iter().enumerate() {
+            cache_positions[index as usize] = if position < CACHE_SIZE { Some(position) } else { None };
+            scores[index as usize] = vertex_score(cache_positions[index as usize], vertex_triangles[index as usize].len());
+        }
+
+        best = None;
+        let mut best_score = -1_f32;
+        for &index in new_cache.iter() {
+            for &other in vertex_triangles[index as usize].iter() {
+                triangle_scores[other] = triangle_score(other, &scores);
+                if triangle_scores[other] > best_score {
+                    best_score = triangle_scores[other];
+                    best = Some(other);
+                }
+            }
+        }
+
+        new_cache.truncate(CACHE_SIZE);
+        cache = new_cache;
+    }
+
+    output
+}
+
+impl Mesh {
+    /// Merge vertices whose attributes all match within `tolerance`. The same
+    /// tolerance applies to every float attribute, not just positions: texture
+    /// coordinates, normals, colors and skin weights must match within it too.
+    /// Float components are compared after snapping them to multiples of the
+    /// tolerance, so values closer than the tolerance can still fall either side
+    /// of a step. Triangles that lose their area because two of their corners
+    /// merge are removed. A mesh without indices gets an index array. Returns the
+    /// number of vertices removed.
+    pub fn weld(&mut self, tolerance: f32) -> usize {
+        let vertex_count = self.len();
+        let corners = self.triangle_corners();
+        let remap: Vec<u32> = {
+            let data: Vec<&AttributeData> = self.attributes().iter().map(|attribute| &attribute.data).collect();
+            let mut unique = HashMap::new();
+            (0..vertex_count).map(|vertex| {
+                let next = unique.len() as u32;
+                *unique.entry(vertex_key(&data, vertex, tolerance)).or_insert(next)
+            }).collect()
+        };
+
+        // The first vertex of each group stands for the group.
+        let welded_count = remap.iter().map(|&i| i as usize + 1).max().unwrap_or(0);
+        let mut sources = vec![0; welded_count];
+        for vertex in (0..vertex_count).rev() {
+            sources[remap[vertex] as usize] = vertex as u32;
+        }
+        let new_corners: Vec<u32> = corners.chunks(3)
+            .map(|triangle| [remap[triangle[0] as usize], remap[triangle[1] as usize], remap[triangle[2] as usize]])
+            .filter(|t| t[0] != t[1] && t[1] != t[2] && t[2] != t[0])
+            .flat_map(|t| t.to_vec())
+            .collect();
+        self.gather_vertices(&sources);
+        self.indices = Some(Indices::new(&new_corners));
+
+        vertex_count - welded_count
+    }
+
+    /// Reorder the triangles of the mesh so that consecutive triangles share
+    /// vertices while they are still in the post-transform vertex cache.
+    pub fn optimize_vertex_cache(&mut self) {
+        let corners = self.triangle_corners();
+        let ordered = optimize_triangle_order(&corners, self.len());
+        self.indices = Some(Indices::new(&ordered));
+    }
+
+    /// Reorder the vertices of the mesh in the order the triangles first use them,
+    /// so that vertex fetches walk through memory. Vertices that no triangle uses
+    /// are dropped.
+    pub fn optimize_vertex_fetch(&mut self) {
+        let corners = self.triangle_corners();
+        let mut remap: Vec<Option<u32>> = vec![None; self.len()];
+        let mut sources = vec![];
+        let new_corners: Vec<u32> = corners.iter().map(|&index| {
+            *remap[index as usize].get_or_insert_with(|| {
+                sources.push(index);
+                (sources.len() - 1) as u32
+            })
+        }).collect();
+        self.gather_vertices(&sources);
+        self.indices = Some(Indices::new(&new_corners));
+    }
+
+    /// The average cache miss ratio of drawing the mesh, with a FIFO vertex cache
+    /// of `ACMR_CACHE_SIZE` vertices.
+    pub fn acmr(&self) -> f32 {
+        acmr(&self.triangle_corners(), ACMR_CACHE_SIZE)
+    }
+
+    /// Run every optimisation pass: weld vertices within `tolerance`, reorder the
+    /// triangles for the vertex cache, and reorder the vertices for fetching.
+    pub fn optimize(&mut self, tolerance: f32) -> OptimizeReport {
+        let vertices_before = self.len();
+        let acmr_before = self.acmr();
+        self.weld(tolerance);
+        self.optimize_vertex_cache();
+        self.optimize_vertex_fetch();
+
+        let report = OptimizeReport {
+            vertices_before: vertices_before,
+            vertices_after: self.len(),
+            acmr_before: acmr_before,
+            acmr_after: self.acmr(),
+        };
+        info!("Optimized mesh: {}", report);
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives;
+
+
+    /// The triangles of a mesh by the bits of their corner positions. Each triangle
+    /// is rotated to start at its smallest corner, which keeps its winding, and the
+    /// triangles are sorted, so that meshes with the same triangles compare equal.
+    fn triangle_set(mesh: &Mesh) -> Vec<[[u32; 3]; 3]> {
+        let points = mesh.points();
+        let bits = |i: u32| {
+            let p = points[i as usize];
+            [p[0].to_bits(), p[1].to_bits(), p[2].to_bits()]
+        };
+        let mut triangles: Vec<[[u32; 3]; 3]> = mesh.triangle_corners().chunks(3).map(|corner| {
+            let t = [bits(corner[0]), bits(corner[1]), bits(corner[2])];
+            let first = (0..3).min_by_key(|&i| t[i]).unwrap();
+            [t[first], t[(first + 1) % 3], t[(first + 2) % 3]]
+        }).collect();
+        triangles.sort();
+
+        triangles
+    }
+
+    #[test]
+    fn test_weld_merges_vertices_within_tolerance() {
+        // Two triangles sharing an edge, with the shared corners nudged apart by
+        // less than the tolerance, and a sliver whose corners all fall together.
+        let points = [
+            [0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0],
+            [0.00001, 0.0, 0.0], [1.0, 1.00001, 0.0], [0.0, 1.0, 0.0],
+            [0.5, 0.5, 1.0], [0.50001, 0.5, 1.0], [0.5, 0.50001, 1.0],
+        ];
+        let mut mesh = Mesh::from_points(&points);
+        let removed = mesh.weld(0.001);
+
+        // The sliver collapses to one vertex that no triangle uses any more.
+        assert_eq!(removed, 4);
+        assert_eq!(mesh.len(), 5);
+        assert_eq!(mesh.triangle_corners(), vec![0, 1, 2, 0, 2, 3]);
+        assert_eq!(mesh.points()[..3], points[..3]);
+        assert_eq!(mesh.points()[3], [0.0, 1.0, 0.0]);
+        assert_eq!(mesh.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_weld_keeps_vertices_past_tolerance() {
+        let points = [
+            [0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0],
+            [0.01, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0],
+        ];
+        let mut mesh = Mesh::from_points(&points);
+
+        assert_eq!(mesh.weld(0.001), 1);
+        assert_eq!(mesh.triangle_corners(), vec![0, 1, 2, 3, 2, 4]);
+    }
+
+    #[test]
+    fn test_optimize_does_not_raise_acmr() {
+        let grids = [(1, 1), (4, 4), (16, 16), (64, 8), (3, 50), (100, 100)];
+        for &(columns, rows) in grids.iter() {
+            let mut mesh = primitives::plane_grid(10_f32, 10_f32, columns, rows);
+            let triangles = triangle_set(&mesh);
+            let report = mesh.optimize(0_f32);
+
+            assert!(report.acmr_after <= report.acmr_before, "{}x{} grid: {}", columns, rows, report);
+            assert_eq!(report.vertices_after, report.vertices_before);
+            assert_eq!(triangle_set(&mesh), triangles);
+        }
+    }
+
+    #[test]
+    fn test_optimize_vertex_fetch_keeps_triangles() {
+        let mut mesh = primitives::uv_sphere(1_f32, 24, 12);
+        mesh.optimize_vertex_cache();
+        let triangles = triangle_set(&mesh);
+        mesh.optimize_vertex_fetch();
+
+        assert_eq!(triangle_set(&mesh), triangles);
+        assert_eq!(mesh.validate(), Ok(()));
+        // Each vertex is first used right after the vertices before it.
+        let mut next = 0;
+        for index in mesh.triangle_corners() {
+            assert!(index <= next);
+            if index == next {
+                next += 1;
+            }
+        }
+        assert_eq!(next as usize, mesh.len());
+    }
+}
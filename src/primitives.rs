@@ -0,0 +1,510 @@
+/*
+ *  GB-Prototype is a renderer prototype demo.
+ *  Copyright (C) 2018,2019,2020  Christopher Blanchard
+ *
+ *  This program is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  This program is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+#![allow(dead_code)]
+//! Generators for primitive meshes. Every generator produces positions, texture
+//! coordinates, normals and indices, with counter-clockwise front faces and the
+//! shape centered on the origin with +Y up.
+use crate::mesh::{AttributeData, Mesh, Semantic};
+
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+
+#[inline]
+fn add(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+#[inline]
+fn scale(a: [f32; 3], s: f32) -> [f32; 3] {
+    [a[0] * s, a[1] * s, a[2] * s]
+}
+
+#[inline]
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+#[inline]
+fn normalize(a: [f32; 3]) -> [f32; 3] {
+    let length = f32::sqrt(a[0] * a[0] + a[1] * a[1] + a[2] * a[2]);
+    scale(a, 1_f32 / length)
+}
+
+/// A ring of a surface of revolution. The normal is given in the plane of the
+/// profile, as a radial and a vertical component.
+#[derive(Copy, Clone, Debug)]
+struct ProfilePoint {
+    radius: f32,
+    y: f32,
+    normal_radial: f32,
+    normal_y: f32,
+    v: f32,
+}
+
+/// Accumulates the vertices and triangles of a mesh under construction.
+struct MeshBuilder {
+    points: Vec<[f32; 3]>,
+    tex_coords: Vec<[f32; 2]>,
+    normals: Vec<[f32; 3]>,
+    indices: Vec<u32>,
+}
+
+impl MeshBuilder {
+    fn new() -> MeshBuilder {
+        MeshBuilder {
+            points: vec![],
+            tex_coords: vec![],
+            normals: vec![],
+            indices: vec![],
+        }
+    }
+
+    fn vertex(&mut self, point: [f32; 3], normal: [f32; 3], tex_coord: [f32; 2]) -> u32 {
+        self.points.push(point);
+        self.normals.push(normal);
+        self.tex_coords.push(tex_coord);
+
+        (self.points.len() - 1) as u32
+    }
+
+    /// Add the triangles between a row major grid of vertices starting at `base`
+    /// with `columns + 1` vertices per row. Rows collapsed to a point are marked in
+    /// `pinched` and get no degenerate triangles.
+    fn grid_indices(&mut self, base: u32, columns: u32, rows: u32, pinched: &dyn Fn(u32) -> bool) {
+        for row in 0..rows {
+            for column in 0..columns {
+                let a = base + row * (columns + 1) + column;
+                let b = a + 1;
+                let c = b + columns + 1;
+                let d = a + columns + 1;
+                if !pinched(row) {
+                    self.indices.extend_from_slice(&[a, b, c]);
+                }
+                if !pinched(row + 1) {
+                    self.indices.extend_from_slice(&[a, c, d]);
+                }
+            }
+        }
+    }
+
+    /// Add a flat grid spanning `origin + s * u + t * v` for `s` and `t` in `[0, 1]`.
+    /// The grid faces along `u x v`, which must point the same way as `normal`. The
+    /// normal is passed in so that a grid with a side of zero length still has one.
+    fn plane(&mut self, origin: [f32; 3], u: [f32; 3], v: [f32; 3], normal: [f32; 3], columns: u32, rows: u32) {
+        let base = self.points.len() as u32;
+        for row in 0..=rows {
+            let t = row as f32 / rows as f32;
+            for column in 0..=columns {
+                let s = column as f32 / columns as f32;
+                self.vertex(add(origin, add(scale(u, s), scale(v, t))), normal, [s, t]);
+            }
+        }
+        self.grid_indices(base, columns, rows, &|_| false);
+    }
+
+    /// Add a surface of revolution around the Y axis. The profile runs from the
+    /// bottom of the surface to the top, so that the front faces point outwards.
+    fn revolve(&mut self, profile: &[ProfilePoint], segments: u32) {
+        let base = self.points.len() as u32;
+        for ring in profile.iter() {
+            for segment in 0..=segments {
+                let u = segment as f32 / segments as f32;
+                let (sin, cos) = f32::sin_cos(2_f32 * PI * u);
+                self.vertex(
+                    [ring.radius * sin, ring.y, ring.radius * cos],
+                    [ring.normal_radial * sin, ring.normal_y, ring.normal_radial * cos],
+                    [u, ring.v]
+                );
+            }
+        }
+        let pinched = |row: u32| profile[row as usize].radius == 0_f32;
+        self.grid_indices(base, segments, profile.len() as u32 - 1, &pinched);
+    }
+
+    /// Add a flat disc at height `y` facing up or down.
+    fn disc(&mut self, y: f32, radius: f32, segments: u32, up: bool) {
+        let normal = if up { [0_f32, 1_f32, 0_f32] } else { [0_f32, -1_f32, 0_f32] };
+        let center = self.vertex([0_f32, y, 0_f32], normal, [0.5, 0.5]);
+        for segment in 0..=segments {
+            let (sin, cos) = f32::sin_cos(2_f32 * PI * segment as f32 / segments as f32);
+            self.vertex([radius * sin, y, radius * cos], normal, [0.5 + 0.5 * sin, 0.5 + 0.5 * cos]);
+        }
+        for segment in 0..segments {
+            let a = center + 1 + segment;
+            if up {
+                self.indices.extend_from_slice(&[center, a, a + 1]);
+            } else {
+                self.indices.extend_from_slice(&[center, a + 1, a]);
+            }
+        }
+    }
+
+    fn build(self) -> Mesh {
+        let mut mesh = Mesh::new_indexed(&self.points, &self.tex_coords, &self.indices)
+            .expect("The generated indices refer to generated points");
+        mesh.set_attribute(Semantic::Normal, AttributeData::F32x3(self.normals));
+
+        mesh
+    }
+}
+
+/// Generate a quad in the XY plane facing +Z, split into `subdivisions` cells
+/// along each side.
+pub fn quad(width: f32, height: f32, subdivisions: u32) -> Mesh {
+    let subdivisions = u32::max(subdivisions, 1);
+    let mut builder = MeshBuilder::new();
+    builder.plane(
+        [-width / 2_f32, -height / 2_f32, 0_f32], [width, 0_f32, 0_f32], [0_f32, height, 0_f32],
+        [0_f32, 0_f32, 1_f32], subdivisions, subdivisions
+    );
+
+    builder.build()
+}
+
+/// Generate a grid in the XZ plane facing +Y with `x_subdivisions` by
+/// `z_subdivisions` cells. A grid with a width or depth of zero has no area, so
+/// `Mesh::validate` rejects its triangles as degenerate.
+pub fn plane_grid(width: f32, depth: f32, x_subdivisions: u32, z_subdivisions: u32) -> Mesh {
+    let mut builder = MeshBuilder::new();
+    builder.plane(
+        [-width / 2_f32, 0_f32, depth / 2_f32], [width, 0_f32, 0_f32], [0_f32, 0_f32, -depth],
+        [0_f32, 1_f32, 0_f32], u32::max(x_subdivisions, 1), u32::max(z_subdivisions, 1)
+    );
+
+    builder.build()
+}
+
+/// Generate a cube with sides of length `size`. Each face is split into
+/// `subdivisions` cells along each side and has its own vertices, so the normals
+/// are flat and each face maps the whole texture.
+pub fn cube(size: f32, subdivisions: u32) -> Mesh {
+    let subdivisions = u32::max(subdivisions, 1);
+    let half = size / 2_f32;
+    // The directions of the `u` and `v` axes of each face, chosen so that `u x v`
+    // points outwards.
+    let faces = [
+        ([0_f32, 0_f32, -1_f32], [0_f32, 1_f32, 0_f32]),
+        ([0_f32, 0_f32, 1_f32], [0_f32, 1_f32, 0_f32]),
+        ([1_f32, 0_f32, 0_f32], [0_f32, 0_f32, -1_f32]),
+        ([1_f32, 0_f32, 0_f32], [0_f32, 0_f32, 1_f32]),
+        ([1_f32, 0_f32, 0_f32], [0_f32, 1_f32, 0_f32]),
+        ([-1_f32, 0_f32, 0_f32], [0_f32, 1_f32, 0_f32]),
+    ];
+
+    let mut builder = MeshBuilder::new();
+    for &(u_direction, v_direction) in faces.iter() {
+        let normal = cross(u_direction, v_direction);
+        let (u, v) = (scale(u_direction, size), scale(v_direction, size));
+        let origin = add(scale(normal, half), add(scale(u, -0.5), scale(v, -0.5)));
+        builder.plane(origin, u, v, normal, subdivisions, subdivisions);
+    }
+
+    builder.build()
+}
+
+/// Generate a sphere from `segments` meridians and `rings` bands of latitude. The
+/// texture wraps once around the equator.
+pub fn uv_sphere(radius: f32, segments: u32, rings: u32) -> Mesh {
+    let segments = u32::max(segments, 3);
+    let rings = u32::max(rings, 2);
+    let profile: Vec<ProfilePoint> = (0..=rings).map(|ring| {
+        let v = ring as f32 / rings as f32;
+        let (sin, cos) = f32::sin_cos(PI * (v - 0.5));
+        // Pin the poles to the axis so that they are recognised as pinched.
+        let cos = if ring == 0 || ring == rings { 0_f32 } else { cos };
+        ProfilePoint { radius: radius * cos, y: radius * sin, normal_radial: cos, normal_y: sin, v: v }
+    }).collect();
+
+    let mut builder = MeshBuilder::new();
+    builder.revolve(&profile, segments);
+
+    builder.build()
+}
+
+/// Generate a sphere by subdividing an icosahedron `subdivisions` times. The
+/// triangles are close to equal in size, unlike those of a UV sphere. Texture
+/// coordinates use an equirectangular mapping, with vertices duplicated along the
+/// seam.
+pub fn icosphere(radius: f32, subdivisions: u32) -> Mesh {
+    let t = (1_f32 + f32::sqrt(5_f32)) / 2_f32;
+    let mut directions: Vec<[f32; 3]> = [
+        [-1_f32, t, 0_f32], [1_f32, t, 0_f32], [-1_f32, -t, 0_f32], [1_f32, -t, 0_f32],
+        [0_f32, -1_f32, t], [0_f32, 1_f32, t], [0_f32, -1_f32, -t], [0_f32, 1_f32, -t],
+        [t, 0_f32, -1_f32], [t, 0_f32, 1_f32], [-t, 0_f32, -1_f32], [-t, 0_f32, 1_f32],
+    ].iter().map(|&p| normalize(p)).collect();
+    let mut triangles: Vec<[u32; 3]> = vec![
+        [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+        [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+        [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+        [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+    ];
+
+    // Split every triangle into four, sharing the midpoint of each edge between
+    // the two triangles on either side of it.
+    for _ in 0..subdivisions {
+        let mut midpoints = HashMap::new();
+        let mut midpoint = |a: u32, b: u32, directions: &mut Vec<[f32; 3]>| {
+            let key = (u32::min(a, b), u32::max(a, b));
+            *midpoints.entry(key).or_insert_with(|| {
+                let (pa, pb) = (directions[a as usize], directions[b as usize]);
+                directions.push(normalize(scale(add(pa, pb), 0.5)));
+                (directions.len() - 1) as u32
+            })
+        };
+        let mut subdivided = Vec::with_capacity(triangles.len() * 4);
+        for &[a, b, c] in triangles.iter() {
+            let ab = midpoint(a, b, &mut directions);
+            let bc = midpoint(b, c, &mut directions);
+            let ca = midpoint(c, a, &mut directions);
+            subdivided.extend_from_slice(&[[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]);
+        }
+        triangles = subdivided;
+    }
+
+    let tex_coord = |d: [f32; 3]| {
+        [0.5 + f32::atan2(d[0], d[2]) / (2_f32 * PI), 0.5 + f32::asin(d[1]) / PI]
+    };
+    let mut builder = MeshBuilder::new();
+    for &direction in directions.iter() {
+        builder.vertex(scale(direction, radius), direction, tex_coord(direction));
+    }
+
+    // A triangle that crosses the seam has texture coordinates on both sides of
+    // it. Give the vertices on the low side copies shifted by a whole turn.
+    let mut wrapped: HashMap<u32, u32> = HashMap::new();
+    for triangle in triangles.iter_mut() {
+        let us: Vec<f32> = triangle.iter().map(|&i| builder.tex_coords[i as usize][0]).collect();
+        let min = us.iter().cloned().fold(f32::INFINITY, f32::min);
+        let max = us.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+        if max - min <= 0.5 {
+            continue;
+        }
+        for index in triangle.iter_mut() {
+            if builder.tex_coords[*index as usize][0] < 0.5 {
+                let original = *index;
+                *index = *wrapped.entry(original).or_insert_with(|| {
+                    let [u, v] = builder.tex_coords[original as usize];
+                    let (point, normal) = (builder.points[original as usize], builder.normals[original as usize]);
+                    builder.vertex(point, normal, [u + 1_f32, v])
+                });
+            }
+        }
+    }
+    for triangle in triangles.iter() {
+        builder.indices.extend_from_slice(triangle);
+    }
+
+    builder.build()
+}
+
+/// Generate a capped cylinder along the Y axis with `segments` sides and its
+/// side split into `stacks` bands.
+pub fn cylinder(radius: f32, height: f32, segments: u32, stacks: u32) -> Mesh {
+    let segments = u32::max(segments, 3);
+    let stacks = u32::max(stacks, 1);
+    let profile: Vec<ProfilePoint> = (0..=stacks).map(|stack| {
+        let v = stack as f32 / stacks as f32;
+        ProfilePoint { radius: radius, y: height * (v - 0.5), normal_radial: 1_f32, normal_y: 0_f32, v: v }
+    }).collect();
+
+    let mut builder = MeshBuilder::new();
+    builder.revolve(&profile, segments);
+    builder.disc(-height / 2_f32, radius, segments, false);
+    builder.disc(height / 2_f32, radius, segments, true);
+
+    builder.build()
+}
+
+/// Generate a cone along the Y axis with its base centered at `-height / 2` and
+/// its apex at `height / 2`. The side has `segments` sides and is split into
+/// `stacks` bands.
+pub fn cone(radius: f32, height: f32, segments: u32, stacks: u32) -> Mesh {
+    let segments = u32::max(segments, 3);
+    let stacks = u32::max(stacks, 1);
+    let slant = f32::sqrt(radius * radius + height * height);
+    let profile: Vec<ProfilePoint> = (0..=stacks).map(|stack| {
+        let v = stack as f32 / stacks as f32;
+        ProfilePoint {
+            radius: if stack == stacks { 0_f32 } else { radius * (1_f32 - v) },
+            y: height * (v - 0.5),
+            normal_radial: height / slant,
+            normal_y: radius / slant,
+            v: v,
+        }
+    }).collect();
+
+    let mut builder = MeshBuilder::new();
+    builder.revolve(&profile, segments);
+    builder.disc(-height / 2_f32, radius, segments, false);
+
+    builder.build()
+}
+
+/// Generate a torus around the Y axis. `major_radius` is the distance from the
+/// center to the middle of the tube and `minor_radius` is the radius of the tube.
+pub fn torus(major_radius: f32, minor_radius: f32, major_segments: u32, minor_segments: u32) -> Mesh {
+    let major_segments = u32::max(major_segments, 3);
+    let minor_segments = u32::max(minor_segments, 3);
+    let profile: Vec<ProfilePoint> = (0..=minor_segments).map(|segment| {
+        let v = segment as f32 / minor_segments as f32;
+        let (sin, cos) = f32::sin_cos(2_f32 * PI * v);
+        ProfilePoint {
+            radius: major_radius + minor_radius * cos,
+            y: minor_radius * sin,
+            normal_radial: cos,
+            normal_y: sin,
+            v: v,
+        }
+    }).collect();
+
+    let mut builder = MeshBuilder::new();
+    builder.revolve(&profile, major_segments);
+
+    builder.build()
+}
+
+/// Generate a capsule along the Y axis: a cylinder of `height` capped by two
+/// hemispheres, each with `rings` bands of latitude. The total height is
+/// `height + 2 * radius`. The texture runs along the whole length. A capsule with
+/// a height of zero is a sphere, with no cylinder between the hemispheres.
+pub fn capsule(radius: f32, height: f32, segments: u32, rings: u32) -> Mesh {
+    let segments = u32::max(segments, 3);
+    let rings = u32::max(rings, 1);
+    let height = f32::max(height, 0_f32);
+    let length = height + PI * radius;
+    let ring_at = |angle: f32, center_y: f32, arc: f32, is_pole: bool| {
+        let (sin, cos) = f32::sin_cos(angle);
+        let cos = if is_pole { 0_f32 } else { cos };
+        ProfilePoint {
+            radius: radius * cos,
+            y: center_y + radius * sin,
+            normal_radial: cos,
+            normal_y: sin,
+            v: arc / length,
+        }
+    };
+
+    // The bottom hemisphere runs from the pole to the equator, and the top one from
+    // the equator to the pole. The band between the two equators is the cylinder.
+    let mut profile = Vec::with_capacity(2 * rings as usize + 2);
+    for ring in 0..=rings {
+        let angle = (PI / 2_f32) * (ring as f32 / rings as f32 - 1_f32);
+        profile.push(ring_at(angle, -height / 2_f32, radius * (angle + PI / 2_f32), ring == 0));
+    }
+    // Without a cylinder the equators coincide, and the band between them would be
+    // all degenerate triangles, so the top hemisphere starts at its first band.
+    let first_top_ring = if height > 0_f32 { 0 } else { 1 };
+    for ring in first_top_ring..=rings {
+        let angle = (PI / 2_f32) * ring as f32 / rings as f32;
+        let arc = radius * PI / 2_f32 + height + radius * angle;
+        profile.push(ring_at(angle, height / 2_f32, arc, ring == rings));
+    }
+
+    let mut builder = MeshBuilder::new();
+    builder.revolve(&profile, segments);
+
+    builder.build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::MeshError;
+
+
+    /// Every generator, with typical subdivisions and with subdivisions below the
+    /// minimum, which are clamped.
+    fn all_primitives() -> Vec<(&'static str, Mesh)> {
+        vec![
+            ("quad", quad(2_f32, 1_f32, 4)),
+            ("quad minimum", quad(2_f32, 1_f32, 0)),
+            ("plane_grid", plane_grid(4_f32, 3_f32, 8, 6)),
+            ("plane_grid minimum", plane_grid(4_f32, 3_f32, 0, 0)),
+            ("cube", cube(1_f32, 4)),
+            ("cube minimum", cube(1_f32, 0)),
+            ("uv_sphere", uv_sphere(1_f32, 32, 16)),
+            ("uv_sphere minimum", uv_sphere(1_f32, 0, 0)),
+            ("icosphere", icosphere(1_f32, 3)),
+            ("icosphere minimum", icosphere(1_f32, 0)),
+            ("cylinder", cylinder(0.5, 2_f32, 32, 4)),
+            ("cylinder minimum", cylinder(0.5, 2_f32, 0, 0)),
+            ("cone", cone(0.5, 1_f32, 32, 4)),
+            ("cone minimum", cone(0.5, 1_f32, 0, 0)),
+            ("torus", torus(1_f32, 0.25, 32, 16)),
+            ("torus minimum", torus(1_f32, 0.25, 0, 0)),
+            ("capsule", capsule(0.5, 1_f32, 32, 8)),
+            ("capsule minimum", capsule(0.5, 1_f32, 0, 0)),
+            ("capsule without height", capsule(0.5, 0_f32, 32, 8)),
+            ("capsule without height minimum", capsule(0.5, 0_f32, 0, 0)),
+        ]
+    }
+
+    #[test]
+    fn test_primitives_are_valid() {
+        for (name, mesh) in all_primitives() {
+            assert_eq!(mesh.validate(), Ok(()), "{} is not valid", name);
+            assert!(mesh.is_indexed(), "{} has no indices", name);
+            assert_eq!(mesh.tex_coords().len(), mesh.len(), "{} is missing texture coordinates", name);
+        }
+    }
+
+    #[test]
+    fn test_primitive_normals_have_unit_length() {
+        for (name, mesh) in all_primitives() {
+            for (vertex, normal) in mesh.normals().unwrap().iter().enumerate() {
+                let length = f32::sqrt(normal[0] * normal[0] + normal[1] * normal[1] + normal[2] * normal[2]);
+                assert!((length - 1_f32).abs() < 1e-5, "normal {} of {} has length {}", vertex, name, length);
+            }
+        }
+    }
+
+    #[test]
+    fn test_primitive_front_faces_follow_normals() {
+        for (name, mesh) in all_primitives() {
+            let (points, normals) = (mesh.points(), mesh.normals().unwrap());
+            for (triangle, corner) in mesh.triangle_corners().chunks(3).enumerate() {
+                let (a, b, c) = (points[corner[0] as usize], points[corner[1] as usize], points[corner[2] as usize]);
+                let face = cross(add(b, scale(a, -1_f32)), add(c, scale(a, -1_f32)));
+                let vertex_normal = corner.iter().fold([0_f32; 3], |sum, &i| add(sum, normals[i as usize]));
+                let dot = face[0] * vertex_normal[0] + face[1] * vertex_normal[1] + face[2] * vertex_normal[2];
+                assert!(dot > 0_f32, "triangle {} of {} faces away from its normals", triangle, name);
+            }
+        }
+    }
+
+    #[test]
+    fn test_capsule_without_height_is_a_sphere() {
+        let mesh = capsule(0.5, 0_f32, 16, 4);
+        for point in mesh.points().iter() {
+            let radius = f32::sqrt(point[0] * point[0] + point[1] * point[1] + point[2] * point[2]);
+            assert!((radius - 0.5).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_plane_grid_without_size() {
+        // A grid with no area keeps its normals, but every triangle is degenerate.
+        for &(width, depth) in [(0_f32, 3_f32), (4_f32, 0_f32), (0_f32, 0_f32)].iter() {
+            let mesh = plane_grid(width, depth, 2, 2);
+            assert_eq!(mesh.draw_count(), 2 * 2 * 6);
+            assert!(mesh.normals().unwrap().iter().all(|&normal| normal == [0_f32, 1_f32, 0_f32]));
+            assert_eq!(mesh.validate(), Err(MeshError::DegenerateTriangle(0)));
+        }
+    }
+}
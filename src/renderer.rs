@@ -1,189 +1,1084 @@
-use gl_backend::GLState;
+/*
+ *  GB-Prototype is a renderer prototype demo.
+ *  Copyright (C) 2018,2019,2020  Christopher Blanchard
+ *
+ *  This program is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  This program is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+#![allow(dead_code)]
+use crate::bounds::{self, Bounds, Frustum};
+use crate::error::Error;
+use crate::gl;
+use crate::gl::types::{GLenum, GLint, GLintptr, GLsizei, GLsizeiptr, GLuint, GLvoid};
+use crate::gl_backend as glh;
+use crate::gl_backend::{GLFeatures, GLState};
+use crate::gl_objects::{BufferObject, Program, Texture, VertexArray};
+use crate::glsl::GlslType;
+use crate::lod::{self, LodSelector};
+use crate::mesh::AttributeFormat;
 
+use cgmath::Matrix4;
+
+use std::collections::HashMap;
+use std::io;
+use std::ptr;
+
+
+/// The source code of a shader program.
+#[derive(Copy, Clone, Debug)]
+pub struct ShaderSource {
+    pub vert_name: &'static str,
+    pub vert_source: &'static str,
+    pub frag_name: &'static str,
+    pub frag_source: &'static str,
+}
 
 /// Determine whether to create a uniform buffer or a general vertex storage buffer.
-enum BufferStorageType {
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BufferStorageType {
     Storage,
     Uniform,
+    /// An element array buffer holding the indices of an indexed mesh.
+    Index,
+}
+
+impl BufferStorageType {
+    /// The buffer target to bind a buffer of this type to.
+    #[inline]
+    fn target(self) -> GLenum {
+        match self {
+            BufferStorageType::Storage => gl::ARRAY_BUFFER,
+            BufferStorageType::Uniform => gl::UNIFORM_BUFFER,
+            BufferStorageType::Index => gl::ELEMENT_ARRAY_BUFFER,
+        }
+    }
+}
+
+/// How often the contents of a buffer change. The renderer passes this on to the
+/// driver as a usage hint.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BufferUsage {
+    /// Uploaded once and drawn many times.
+    Static,
+    /// Updated now and then, in whole or in part.
+    Dynamic,
+    /// Replaced every frame, e.g. UI or particle geometry.
+    Stream,
+}
+
+impl BufferUsage {
+    /// The GL usage hint for buffers of this usage.
+    #[inline]
+    fn gl_usage(self) -> GLenum {
+        match self {
+            BufferUsage::Static => gl::STATIC_DRAW,
+            BufferUsage::Dynamic => gl::DYNAMIC_DRAW,
+            BufferUsage::Stream => gl::STREAM_DRAW,
+        }
+    }
+}
+
+/// The byte offset of the first element in a buffer.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Offset(pub usize);
+/// The number of bytes between the starts of consecutive elements in a buffer.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Stride(pub usize);
+/// The number of components in each element of a buffer.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Size(pub usize);
+/// The type of each component of an element, e.g. `gl::FLOAT`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Type(pub GLenum);
+
+/// How a shader reads the components of an element.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ComponentKind {
+    /// As floats, converting integer components directly.
+    Float,
+    /// As floats, mapping integer components to [0, 1] or [-1, 1].
+    Normalized,
+    /// As integers. The components must have an integer type.
+    Integer,
+}
+
+impl From<AttributeFormat> for ComponentKind {
+    #[inline]
+    fn from(format: AttributeFormat) -> ComponentKind {
+        if format.is_integer() {
+            ComponentKind::Integer
+        } else if format.is_normalized() {
+            ComponentKind::Normalized
+        } else {
+            ComponentKind::Float
+        }
+    }
 }
 
 /// The shape of the data.
-struct BufferLayout {
-    offset: Offset,
-    stride: Stride,
-    size: Size,
-    ty: Type
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BufferLayout {
+    pub offset: Offset,
+    pub stride: Stride,
+    pub size: Size,
+    pub ty: Type,
+    pub kind: ComponentKind,
 }
 
 /// The entry point into a shader.
-struct EntryPoint {
-    name: String,
-    shader: ShaderIndex,
-    index: ShaderVariableIndex
-}
-/// The association between a buffer slice and an input variable for a shader.
-struct BufferBinding {
-    index: BufferIndex,
-    entry: EntryPoint,
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EntryPoint {
+    pub name: String,
+    pub shader: ShaderIndex,
+    pub index: ShaderVariableIndex,
 }
 
 /// A buffer consists of an index and a layout.
-struct Buffer {
-    index: BufferIndex,
-    ty: BufferStorageType, 
-    layout: BufferLayout,
+#[derive(Debug)]
+pub struct Buffer {
+    pub index: BufferIndex,
+    pub ty: BufferStorageType,
+    pub layout: BufferLayout,
+    pub usage: BufferUsage,
+    object: BufferObject,
+    len_bytes: usize,
 }
+
 /// A descriptor describes the relationship between a buffer or sampler and a shader variable (attribute).
 /// In particular, it associates a shader variable with a buffer or a texture sampler.
-enum DescriptorType {
-    Buffer,
-    Sampler,
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Descriptor {
+    /// Feed a vertex attribute from a buffer using the buffer's layout.
+    Buffer { buffer: BufferIndex, shader_variable: ShaderVariableIndex },
+    /// Bind a sampler to a texture unit.
+    Sampler { sampler: SamplerIndex, unit: u32 },
+    /// Take the indices for indexed draws from a buffer. The type of the indices is
+    /// the type in the buffer's layout, e.g. `gl::UNSIGNED_SHORT`.
+    IndexBuffer { buffer: BufferIndex },
+    /// Feed a vertex attribute from a buffer once per `divisor` instances instead of
+    /// once per vertex, e.g. a model matrix or a color. An attribute wider than four
+    /// components, like a `mat4`, takes consecutive locations from `shader_variable`.
+    /// Batched draws upload their instance data to the first of these in a set.
+    InstanceBuffer { buffer: BufferIndex, shader_variable: ShaderVariableIndex, divisor: u32 },
+    /// Bind a uniform buffer to a uniform block binding point, e.g. a joint palette.
+    /// Attach the block of the shader to the same point with `set_uniform_block_binding`.
+    UniformBlock { buffer: BufferIndex, binding: u32 },
 }
 
-struct Descriptor {
-    ty: DescriptorType,
-    buffer_index: BufferIndex,
-    shader_variable: ShaderIndex,
-}
 /// A DescriptorSet describes the relationship between a set of buffers and a set of shader attributes.
-struct DescriptorSet {
-    index: DescriptorSetIndex,
-    descriptors: HashMap<Descriptor>,
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DescriptorSet {
+    pub shader: ShaderIndex,
+    pub descriptors: Vec<Descriptor>,
 }
 
 
 /// An index to the texture sample entry.
-struct SamplerIndex(u32);
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SamplerIndex(u32);
 /// An index to the buffer entry.
-struct BufferIndex(u32);
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BufferIndex(u32);
 /// An index to the shader entry.
-struct ShaderIndex(u32);
-/// An index to the shader vertex entry.
-struct ShaderVariableIndex(u32);
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ShaderIndex(u32);
+/// An index to the shader vertex entry. This is the attribute location in the shader.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ShaderVariableIndex(pub u32);
 /// An index to a set of vertex attibute objects associated together
 /// from a DescriptorSet. (vao). These are generated by the renderer.
-struct DescriptorSetIndex(u32);
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DescriptorSetIndex(u32);
+/// An index to an object drawn at several levels of detail. The renderer remembers
+/// the level it last drew the object at.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct LodIndex(u32);
 
 
-enum Command {
-    CompileShader,
-    LoadBuffer,
-    Draw,
-    CreateBuffer,
-    LoadSampler,
-    LoadDescriptorSet,
+pub enum Command {
+    LoadBuffer(LoadBufferCommand),
+    UpdateBuffer(UpdateBufferCommand),
+    Draw(DrawCommand),
+    DrawIndexed(DrawIndexedCommand),
+    DrawLod(DrawLodCommand),
+    DrawInstance(DrawInstanceCommand),
 }
 
-struct CompilerShaderCommand {
-    source: ShaderSource,
+pub struct LoadBufferCommand {
+    pub buffer: BufferIndex,
+    pub data: Vec<u8>,
 }
 
-struct CreateBufferCommand {
-    ty: BufferStorageType, 
-    layout: BufferLayout,
+/// Overwrite part of a loaded buffer, starting `offset` bytes in. With `orphan`
+/// set the buffer gets fresh storage first, so the update does not wait for draws
+/// still reading the old contents, and everything outside the range is undefined.
+pub struct UpdateBufferCommand {
+    pub buffer: BufferIndex,
+    pub offset: usize,
+    pub data: Vec<u8>,
+    pub orphan: bool,
 }
 
-struct LoadBufferCommand {
-    data: &[u8],
-    width: u32,
-    height: u32,
-    buffer: BufferIndex,
+pub struct DrawCommand {
+    pub index: DescriptorSetIndex,
+    pub first: u32,
+    pub count: u32,
+    /// The number of instances to draw. One draws without instancing.
+    pub instance_count: u32,
+    /// The world space bounds of what the draw covers, or `None` to never cull it.
+    pub bounds: Option<Bounds>,
 }
 
-struct LoadSamplerCommand {
-    buffer: BufferIndex,
+/// Draw `count` indices starting at index `first` from the index buffer of a
+/// descriptor set. `base_vertex` is added to each index, for drawing one of several
+/// meshes packed into a shared vertex buffer.
+pub struct DrawIndexedCommand {
+    pub index: DescriptorSetIndex,
+    pub first: u32,
+    pub count: u32,
+    pub base_vertex: u32,
+    /// The number of instances to draw. One draws without instancing.
+    pub instance_count: u32,
+    /// The world space bounds of what the draw covers, or `None` to never cull it.
+    pub bounds: Option<Bounds>,
 }
 
-struct LoadDescriptorSetCommand {
-    set: DescriptorSet,
+/// Draw one of several levels of detail of an object, picked by the projected size
+/// of its bounds. `levels` runs from the most to the least detailed, and the bounds
+/// of the individual levels are ignored.
+pub struct DrawLodCommand {
+    pub lod: LodIndex,
+    pub levels: Vec<DrawIndexedCommand>,
+    /// The world space bounds of the object.
+    pub bounds: Bounds,
 }
 
-struct DrawCommand {
+/// Draw one instance of a mesh, with `instance` holding the bytes of its instance
+/// attributes. Consecutive instance draws of the same range of the same descriptor
+/// set are batched into one instanced draw, whose instance data goes to the set's
+/// instance buffer. The set is drawn indexed if it has an index buffer.
+pub struct DrawInstanceCommand {
+    pub index: DescriptorSetIndex,
+    pub first: u32,
+    pub count: u32,
+    pub base_vertex: u32,
+    pub instance: Vec<u8>,
+    /// The world space bounds of the instance, or `None` to never cull it.
+    pub bounds: Option<Bounds>,
+}
+
+/// The draws that instance draws with the same key are merged into.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+struct BatchKey {
     index: DescriptorSetIndex,
+    first: u32,
+    count: u32,
+    base_vertex: u32,
+}
+
+/// The number of draws that passed and failed frustum culling in a frame.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CullStats {
+    pub visible: u32,
+    pub culled: u32,
 }
 
 
-struct Renderer {
-    queue: Vec<Command>,
-    shaders: HashMap<ShaderIndex, Shader>,
-    buffers: HashMap<BufferIndex, Buffer>,
-    bindings: HashMap<BufferIndex, EntryPoint>,
-    descriptors: HashMap<DescriptorSetIndex, DescriptorSet>,
-    backend: RendererBackend,
+/// An attribute of a compiled shader program.
+#[derive(Clone, Debug)]
+struct GLEntryPoint {
+    name: String,
+    index: ShaderVariableIndex,
+    ty: GlslType,
+    array_size: GLint,
 }
 
-impl Renderer {
-    fn enqueue(&mut self, command: Command) {
-        self.queue.push(command);
-    }
+/// A uniform of a compiled shader program.
+#[derive(Clone, Debug)]
+struct GLUniformEntryPoint {
+    name: String,
+    location: GLint,
+    ty: GlslType,
+    array_size: GLint,
+}
 
-    fn clear_command_queue(&mut self) {
-        self.queue.clear();
-    }
+struct GLShader {
+    program: Program,
+    attributes: HashMap<String, GLEntryPoint>,
+    uniforms: HashMap<String, GLUniformEntryPoint>,
+}
+
+struct GLDescriptorSet {
+    set: DescriptorSet,
+    vao: VertexArray,
+}
+
+/// The level of detail state of an object.
+struct GLLodObject {
+    selector: LodSelector,
+    level: Option<usize>,
+}
+
+/// Fill a buffer without binding it.
+#[cfg(not(feature = "gles"))]
+unsafe fn named_buffer_data(buffer: GLuint, data: &[u8], usage: GLenum) {
+    gl::NamedBufferData(buffer, data.len() as GLsizeiptr, data.as_ptr() as *const GLvoid, usage);
+}
+
+/// OpenGL ES has no direct state access, so its feature tier never selects this.
+#[cfg(feature = "gles")]
+unsafe fn named_buffer_data(_buffer: GLuint, _data: &[u8], _usage: GLenum) {
+    unreachable!("OpenGL ES has no direct state access");
+}
+
+/// Overwrite part of a buffer without binding it.
+#[cfg(not(feature = "gles"))]
+unsafe fn named_buffer_sub_data(buffer: GLuint, offset: usize, data: &[u8]) {
+    gl::NamedBufferSubData(buffer, offset as GLintptr, data.len() as GLsizeiptr, data.as_ptr() as *const GLvoid);
+}
+
+/// OpenGL ES has no direct state access, so its feature tier never selects this.
+#[cfg(feature = "gles")]
+unsafe fn named_buffer_sub_data(_buffer: GLuint, _offset: usize, _data: &[u8]) {
+    unreachable!("OpenGL ES has no direct state access");
+}
+
+/// Draw indexed triangles, adding `base_vertex` to each index.
+#[cfg(not(feature = "gles"))]
+unsafe fn draw_elements_base_vertex(count: GLsizei, index_type: GLenum, offset: usize, base_vertex: u32) {
+    gl::DrawElementsBaseVertex(
+        gl::TRIANGLES, count, index_type, offset as *const GLvoid, base_vertex as GLint
+    );
+}
 
-    fn compile_shader(&mut self, commmand: CompilerShaderCommand) ->  {
-        self.backend.compile_shader()
+/// OpenGL ES 3.0 has no base vertex draws. Shared buffers for it need their base
+/// vertices baked into the indices, see `PackedMeshes::bake_base_vertices`.
+#[cfg(feature = "gles")]
+unsafe fn draw_elements_base_vertex(count: GLsizei, index_type: GLenum, offset: usize, base_vertex: u32) {
+    debug_assert_eq!(base_vertex, 0, "OpenGL ES 3.0 has no base vertex draws");
+    gl::DrawElements(gl::TRIANGLES, count, index_type, offset as *const GLvoid);
+}
+
+/// Draw instanced indexed triangles, adding `base_vertex` to each index.
+#[cfg(not(feature = "gles"))]
+unsafe fn draw_elements_instanced_base_vertex(
+    count: GLsizei, index_type: GLenum, offset: usize, base_vertex: u32, instance_count: u32) {
+
+    gl::DrawElementsInstancedBaseVertex(
+        gl::TRIANGLES, count, index_type, offset as *const GLvoid, instance_count as GLsizei, base_vertex as GLint
+    );
+}
+
+/// OpenGL ES 3.0 has no base vertex draws. Shared buffers for it need their base
+/// vertices baked into the indices, see `PackedMeshes::bake_base_vertices`.
+#[cfg(feature = "gles")]
+unsafe fn draw_elements_instanced_base_vertex(
+    count: GLsizei, index_type: GLenum, offset: usize, base_vertex: u32, instance_count: u32) {
+
+    debug_assert_eq!(base_vertex, 0, "OpenGL ES 3.0 has no base vertex draws");
+    gl::DrawElementsInstanced(gl::TRIANGLES, count, index_type, offset as *const GLvoid, instance_count as GLsizei);
+}
+
+/// The size in bytes of a vertex attribute component of a type such as `gl::FLOAT`.
+#[inline]
+fn component_size(ty: GLenum) -> usize {
+    match ty {
+        gl::BYTE | gl::UNSIGNED_BYTE => 1,
+        gl::SHORT | gl::UNSIGNED_SHORT | gl::HALF_FLOAT => 2,
+        _ => 4,
     }
+}
 
-    fn render(&mut self) {
-        for command in self.queue.iter() {
-            match command {
-                Command::CompilerShader(c) => {
-                    self.compile_shader(c)
-                }
+/// Point the vertex attribute at `location` of the bound vertex array at the buffer
+/// bound to `gl::ARRAY_BUFFER`. An attribute wider than four components takes one
+/// location per four components. A nonzero divisor makes it a per instance attribute.
+unsafe fn enable_vertex_attribute(location: GLuint, layout: BufferLayout, divisor: u32) {
+    let columns = usize::max((layout.size.0 + 3) / 4, 1);
+    for column in 0..columns {
+        let size = usize::min(layout.size.0 - column * 4, 4);
+        let offset = layout.offset.0 + column * 4 * component_size(layout.ty.0);
+        let column_location = location + column as GLuint;
+        let stride = layout.stride.0 as GLsizei;
+        let offset = offset as *const GLvoid;
+        // Integer attributes need the integer pointer function, or the shader
+        // reads them as floats.
+        match layout.kind {
+            ComponentKind::Integer => {
+                gl::VertexAttribIPointer(column_location, size as GLint, layout.ty.0, stride, offset);
+            }
+            ComponentKind::Normalized => {
+                gl::VertexAttribPointer(column_location, size as GLint, layout.ty.0, gl::TRUE, stride, offset);
+            }
+            ComponentKind::Float => {
+                gl::VertexAttribPointer(column_location, size as GLint, layout.ty.0, gl::FALSE, stride, offset);
             }
         }
+        gl::EnableVertexAttribArray(column_location);
+        if divisor != 0 {
+            gl::VertexAttribDivisor(column_location, divisor);
+        }
     }
 }
 
+/// The size in bytes of an index of type `gl::UNSIGNED_BYTE`, `gl::UNSIGNED_SHORT`
+/// or `gl::UNSIGNED_INT`.
+#[inline]
+fn index_size(index_type: GLenum) -> usize {
+    match index_type {
+        gl::UNSIGNED_BYTE => 1,
+        gl::UNSIGNED_SHORT => 2,
+        _ => 4,
+    }
+}
 
-struct GLBufferIndex(GLuint);
-struct GLShaderIndex(GLuint);
-struct GLShaderVariableIndex(GLuint);
-struct GLShaderUniformIndex(GLuint);
-
-struct GLShader {
-    shader_index: GLShaderIndex,
-    attributes: HashMap<GLShaderVariableIndex, GLEntryPoint>,
-    uniforms: HashMap<GLShaderUniformIndex, GLUniformEntryPoint>,
+/// Read the name of an active attribute or uniform out of a GL name buffer.
+fn active_variable_name(name_data: &[i8], length: GLsizei) -> String {
+    let bytes: Vec<u8> = name_data[0..length as usize].iter().map(|&c| c as u8).collect();
+    String::from_utf8_lossy(&bytes).into_owned()
 }
 
-struct GLEntryPoint {
-    name: String,
-    index: GLShaderVariableIndex,
+/// Query the active attributes of a linked shader program.
+fn active_attributes(sp: GLuint) -> Result<HashMap<String, GLEntryPoint>, Error> {
+    let mut count = 0;
+    let mut max_length = 0;
+    unsafe {
+        gl::GetProgramiv(sp, gl::ACTIVE_ATTRIBUTES, &mut count);
+        gl::GetProgramiv(sp, gl::ACTIVE_ATTRIBUTE_MAX_LENGTH, &mut max_length);
+    }
+    let mut name_data: Vec<i8> = vec![0; usize::max(max_length as usize, 1)];
+    let mut attributes = HashMap::new();
+    for i in 0..count {
+        let mut array_size: GLint = 0;
+        let mut ty: GLenum = 0;
+        let mut actual_length: GLsizei = 0;
+        unsafe {
+            gl::GetActiveAttrib(
+                sp, i as GLuint, name_data.len() as GLsizei,
+                &mut actual_length, &mut array_size, &mut ty, &mut name_data[0]
+            );
+        }
+        let name = active_variable_name(&name_data, actual_length);
+        let location = unsafe { gl::GetAttribLocation(sp, glh::gl_str(&name).as_ptr()) };
+        // Built in attributes like gl_VertexID have no location.
+        if location < 0 {
+            continue;
+        }
+        attributes.insert(name.clone(), GLEntryPoint {
+            name: name,
+            index: ShaderVariableIndex(location as u32),
+            ty: GlslType::from_gl(ty)?,
+            array_size: array_size,
+        });
+    }
+
+    Ok(attributes)
 }
 
-struct GLUniformEntryPoint {
-    name: String,
-    index: GLShaderUniformIndex,
+/// Query the active uniforms of a linked shader program.
+fn active_uniforms(sp: GLuint) -> Result<HashMap<String, GLUniformEntryPoint>, Error> {
+    let mut count = 0;
+    let mut max_length = 0;
+    unsafe {
+        gl::GetProgramiv(sp, gl::ACTIVE_UNIFORMS, &mut count);
+        gl::GetProgramiv(sp, gl::ACTIVE_UNIFORM_MAX_LENGTH, &mut max_length);
+    }
+    let mut name_data: Vec<i8> = vec![0; usize::max(max_length as usize, 1)];
+    let mut uniforms = HashMap::new();
+    for i in 0..count {
+        let mut array_size: GLint = 0;
+        let mut ty: GLenum = 0;
+        let mut actual_length: GLsizei = 0;
+        unsafe {
+            gl::GetActiveUniform(
+                sp, i as GLuint, name_data.len() as GLsizei,
+                &mut actual_length, &mut array_size, &mut ty, &mut name_data[0]
+            );
+        }
+        let name = active_variable_name(&name_data, actual_length);
+        let location = unsafe { gl::GetUniformLocation(sp, glh::gl_str(&name).as_ptr()) };
+        uniforms.insert(name.clone(), GLUniformEntryPoint {
+            name: name,
+            location: location,
+            ty: GlslType::from_gl(ty)?,
+            array_size: array_size,
+        });
+    }
+
+    Ok(uniforms)
 }
 
-struct RendererBackend {
-    context: glh::GLState,
+
+/// The renderer owns the GPU resources of a scene. Every resource is an owned GL
+/// object, so removing it from the renderer, or dropping the renderer, frees it.
+pub struct Renderer {
+    features: GLFeatures,
+    queue: Vec<Command>,
+    next_index: u32,
+    shaders: HashMap<ShaderIndex, GLShader>,
+    buffers: HashMap<BufferIndex, Buffer>,
+    samplers: HashMap<SamplerIndex, Texture>,
+    descriptors: HashMap<DescriptorSetIndex, GLDescriptorSet>,
+    lod_objects: HashMap<LodIndex, GLLodObject>,
+    /// The view projection matrix levels of detail are picked with.
+    view_projection: Option<[f32; 16]>,
+    /// The view frustum draws are culled against, if culling is on.
+    frustum: Option<Frustum>,
+    cull_stats: CullStats,
 }
 
-impl RendererBackend {
-    fn compiler_shader(&mut self) -> GLShader {
+impl Renderer {
+    /// Create a renderer for a context with the given capabilities. The renderer
+    /// picks its code paths from them.
+    pub fn new(features: GLFeatures) -> Renderer {
+        Renderer {
+            features: features,
+            queue: vec![],
+            next_index: 0,
+            shaders: HashMap::new(),
+            buffers: HashMap::new(),
+            samplers: HashMap::new(),
+            descriptors: HashMap::new(),
+            lod_objects: HashMap::new(),
+            view_projection: None,
+            frustum: None,
+            cull_stats: CullStats::default(),
+        }
+    }
+
+    fn allocate_index(&mut self) -> u32 {
+        let index = self.next_index;
+        self.next_index += 1;
+
+        index
+    }
+
+    pub fn enqueue(&mut self, command: Command) {
+        self.queue.push(command);
+    }
+
+    pub fn clear_command_queue(&mut self) {
+        self.queue.clear();
+    }
+
+    /// Cull draws outside the frustum of a view projection matrix from now on, and
+    /// pick levels of detail by the projected sizes under it.
+    pub fn set_view_projection(&mut self, view_projection: &Matrix4) {
+        self.frustum = Some(Frustum::from_view_projection(view_projection));
+        self.view_projection = Some(bounds::matrix_elements(view_projection));
+    }
+
+    /// Stop culling draws. Levels of detail are still picked with the last view
+    /// projection matrix.
+    pub fn disable_culling(&mut self) {
+        self.frustum = None;
+    }
+
+    /// The number of draws culled and drawn by the last call to `render`.
+    #[inline]
+    pub fn cull_stats(&self) -> CullStats {
+        self.cull_stats
+    }
+
+    /// Determine whether to submit a draw with the given bounds, and count it.
+    fn cull(&mut self, bounds: Option<Bounds>) -> bool {
+        let visible = match (self.frustum, bounds) {
+            (Some(frustum), Some(bounds)) => frustum.intersects(&bounds),
+            _ => true,
+        };
+        if visible {
+            self.cull_stats.visible += 1;
+        } else {
+            self.cull_stats.culled += 1;
+        }
+
+        visible
+    }
+
+    /// Compile and link a shader program, and index its attributes and uniforms.
+    pub fn compile_shader(
+        &mut self, context: &GLState, source: &ShaderSource) -> Result<ShaderIndex, Error> {
+
         let mut vert_reader = io::Cursor::new(source.vert_source);
         let mut frag_reader = io::Cursor::new(source.frag_source);
-        let sp = gl_backend::create_program_from_reader(
-            &mut self.context,
+        let program = glh::create_program_from_reader(
+            context,
             &mut vert_reader, source.vert_name,
             &mut frag_reader, source.frag_name
-        ).unwrap();
-        debug_assert!(sp > 0);
-    
-        let shader_index = GLShaderIndex(sp);
-
-        // loop through the attributes and index them.
-        let mut attributes = HashMap::new();
-        // loop thorugh the uniforms and index them.
-        let mut uniforms = HashMap::new();
-
-        GLShader {
-            shader_index: shader_index,
+        )?;
+
+        // Loop through the attributes and uniforms and index them.
+        let attributes = active_attributes(program.name())?;
+        let uniforms = active_uniforms(program.name())?;
+
+        let index = ShaderIndex(self.allocate_index());
+        self.shaders.insert(index, GLShader {
+            program: program,
             attributes: attributes,
             uniforms: uniforms,
+        });
+
+        Ok(index)
+    }
+
+    /// Look up the entry point of a shader attribute by name.
+    pub fn entry_point(&self, shader: ShaderIndex, name: &str) -> Option<EntryPoint> {
+        let gl_shader = self.shaders.get(&shader)?;
+        let attribute = gl_shader.attributes.get(name)?;
+
+        Some(EntryPoint {
+            name: attribute.name.clone(),
+            shader: shader,
+            index: attribute.index,
+        })
+    }
+
+    /// Look up the location of a shader uniform by name.
+    pub fn uniform_location(&self, shader: ShaderIndex, name: &str) -> Option<GLint> {
+        let gl_shader = self.shaders.get(&shader)?;
+        gl_shader.uniforms.get(name).map(|uniform| uniform.location)
+    }
+
+    /// Attach a uniform block of a shader to a binding point. Returns `false` if the
+    /// shader has no such block.
+    pub fn set_uniform_block_binding(&self, shader: ShaderIndex, name: &str, binding: u32) -> bool {
+        let gl_shader = match self.shaders.get(&shader) {
+            Some(val) => val,
+            None => return false,
+        };
+        let sp = gl_shader.program.name();
+        let block = unsafe { gl::GetUniformBlockIndex(sp, glh::gl_str(name).as_ptr()) };
+        if block == gl::INVALID_INDEX {
+            return false;
+        }
+        unsafe {
+            gl::UniformBlockBinding(sp, block, binding);
+        }
+
+        true
+    }
+
+    /// The GL program of a shader.
+    pub fn program(&self, shader: ShaderIndex) -> Option<&Program> {
+        self.shaders.get(&shader).map(|gl_shader| &gl_shader.program)
+    }
+
+    /// Create an empty buffer. Fill it with a `LoadBuffer` command.
+    pub fn create_buffer(
+        &mut self, context: &GLState, ty: BufferStorageType, layout: BufferLayout) -> BufferIndex {
+
+        self.create_dynamic_buffer(context, ty, layout, BufferUsage::Static)
+    }
+
+    /// Create an empty buffer whose contents change after loading. Fill it with a
+    /// `LoadBuffer` command and patch it with `UpdateBuffer` commands.
+    pub fn create_dynamic_buffer(
+        &mut self, context: &GLState, ty: BufferStorageType, layout: BufferLayout, usage: BufferUsage) -> BufferIndex {
+
+        let index = BufferIndex(self.allocate_index());
+        self.buffers.insert(index, Buffer {
+            index: index,
+            ty: ty,
+            layout: layout,
+            usage: usage,
+            object: BufferObject::new(context),
+            len_bytes: 0,
+        });
+
+        index
+    }
+
+    /// Hand a texture over to the renderer for use as a sampler.
+    pub fn add_sampler(&mut self, texture: Texture) -> SamplerIndex {
+        let index = SamplerIndex(self.allocate_index());
+        self.samplers.insert(index, texture);
+
+        index
+    }
+
+    /// Create the vertex array object for a descriptor set. Every buffer in the set
+    /// must already exist.
+    pub fn create_descriptor_set(&mut self, context: &GLState, set: DescriptorSet) -> Option<DescriptorSetIndex> {
+        let vao = VertexArray::new(context);
+        vao.bind();
+        for descriptor in set.descriptors.iter() {
+            match *descriptor {
+                Descriptor::Buffer { buffer, shader_variable } => {
+                    let buffer = self.buffers.get(&buffer)?;
+                    buffer.object.bind(gl::ARRAY_BUFFER);
+                    unsafe {
+                        enable_vertex_attribute(shader_variable.0, buffer.layout, 0);
+                    }
+                }
+                Descriptor::InstanceBuffer { buffer, shader_variable, divisor } => {
+                    let buffer = self.buffers.get(&buffer)?;
+                    buffer.object.bind(gl::ARRAY_BUFFER);
+                    unsafe {
+                        enable_vertex_attribute(shader_variable.0, buffer.layout, u32::max(divisor, 1));
+                    }
+                }
+                Descriptor::IndexBuffer { buffer } => {
+                    // The element array buffer binding is part of the vertex array state.
+                    self.buffers.get(&buffer)?.object.bind(gl::ELEMENT_ARRAY_BUFFER);
+                }
+                Descriptor::Sampler { .. } | Descriptor::UniformBlock { .. } => {}
+            }
+        }
+        unsafe {
+            gl::BindVertexArray(0);
+        }
+
+        let index = DescriptorSetIndex(self.allocate_index());
+        self.descriptors.insert(index, GLDescriptorSet { set: set, vao: vao });
+
+        Some(index)
+    }
+
+    /// Register an object drawn at several levels of detail, picked by `selector`.
+    pub fn add_lod_object(&mut self, selector: LodSelector) -> LodIndex {
+        let index = LodIndex(self.allocate_index());
+        self.lod_objects.insert(index, GLLodObject { selector: selector, level: None });
+
+        index
+    }
+
+    /// The level of detail an object was last drawn at, if it has been drawn.
+    pub fn lod_level(&self, index: LodIndex) -> Option<usize> {
+        self.lod_objects.get(&index).and_then(|object| object.level)
+    }
+
+    /// Free a shader program. Returns `false` if there is no such shader.
+    pub fn remove_shader(&mut self, index: ShaderIndex) -> bool {
+        self.shaders.remove(&index).is_some()
+    }
+
+    /// Free a buffer. Returns `false` if there is no such buffer.
+    pub fn remove_buffer(&mut self, index: BufferIndex) -> bool {
+        self.buffers.remove(&index).is_some()
+    }
+
+    /// Free a sampler. Returns `false` if there is no such sampler.
+    pub fn remove_sampler(&mut self, index: SamplerIndex) -> bool {
+        self.samplers.remove(&index).is_some()
+    }
+
+    /// Free a descriptor set. Returns `false` if there is no such descriptor set.
+    pub fn remove_descriptor_set(&mut self, index: DescriptorSetIndex) -> bool {
+        self.descriptors.remove(&index).is_some()
+    }
+
+    /// Forget a level of detail object. Returns `false` if there is no such object.
+    pub fn remove_lod_object(&mut self, index: LodIndex) -> bool {
+        self.lod_objects.remove(&index).is_some()
+    }
+
+    /// Free every GPU resource owned by the renderer, e.g. before loading a new level.
+    pub fn clear(&mut self) {
+        self.queue.clear();
+        self.lod_objects.clear();
+        self.descriptors.clear();
+        self.samplers.clear();
+        self.buffers.clear();
+        self.shaders.clear();
+    }
+
+    /// The capabilities the renderer was created with.
+    #[inline]
+    pub fn features(&self) -> GLFeatures {
+        self.features
+    }
+
+    fn load_buffer(&mut self, command: &LoadBufferCommand) -> Result<(), Error> {
+        self.upload(command.buffer, &command.data)
+    }
+
+    /// Replace the contents of a buffer.
+    fn upload(&mut self, index: BufferIndex, data: &[u8]) -> Result<(), Error> {
+        let direct_state_access = self.features.direct_state_access;
+        if let Some(buffer) = self.buffers.get_mut(&index) {
+            if direct_state_access {
+                unsafe {
+                    named_buffer_data(buffer.object.name(), data, buffer.usage.gl_usage());
+                }
+            } else {
+                // Upload through the copy write target. Binding an index buffer to the
+                // element array target would change the currently bound vertex array.
+                buffer.object.bind(gl::COPY_WRITE_BUFFER);
+                unsafe {
+                    gl::BufferData(
+                        gl::COPY_WRITE_BUFFER, data.len() as GLsizeiptr,
+                        data.as_ptr() as *const GLvoid, buffer.usage.gl_usage()
+                    );
+                }
+            }
+            if glh::take_gl_error() == Some(gl::OUT_OF_MEMORY) {
+                return Err(Error::OutOfMemory(format!("buffer {:?}", index)));
+            }
+            buffer.len_bytes = data.len();
         }
+
+        Ok(())
+    }
+
+    fn update_buffer(&mut self, command: &UpdateBufferCommand) -> Result<(), Error> {
+        let direct_state_access = self.features.direct_state_access;
+        if let Some(buffer) = self.buffers.get_mut(&command.buffer) {
+            match command.offset.checked_add(command.data.len()) {
+                Some(end) if end <= buffer.len_bytes => {}
+                _ => return Err(Error::BufferOverflow(command.offset, command.data.len(), buffer.len_bytes)),
+            }
+            if command.orphan {
+                buffer.object.bind(gl::COPY_WRITE_BUFFER);
+                unsafe {
+                    gl::BufferData(
+                        gl::COPY_WRITE_BUFFER, buffer.len_bytes as GLsizeiptr, ptr::null(), buffer.usage.gl_usage()
+                    );
+                }
+            }
+            if direct_state_access {
+                unsafe {
+                    named_buffer_sub_data(buffer.object.name(), command.offset, &command.data);
+                }
+            } else {
+                buffer.object.bind(gl::COPY_WRITE_BUFFER);
+                unsafe {
+                    gl::BufferSubData(
+                        gl::COPY_WRITE_BUFFER, command.offset as GLintptr, command.data.len() as GLsizeiptr,
+                        command.data.as_ptr() as *const GLvoid
+                    );
+                }
+            }
+            if glh::take_gl_error() == Some(gl::OUT_OF_MEMORY) {
+                return Err(Error::OutOfMemory(format!("buffer {:?}", command.buffer)));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Bind the shader, samplers and vertex array of a descriptor set for drawing.
+    fn bind_descriptor_set(&self, index: DescriptorSetIndex) -> Option<&GLDescriptorSet> {
+        let descriptor_set = self.descriptors.get(&index)?;
+        let shader = self.shaders.get(&descriptor_set.set.shader)?;
+
+        shader.program.bind();
+        for descriptor in descriptor_set.set.descriptors.iter() {
+            match *descriptor {
+                Descriptor::Sampler { sampler, unit } => {
+                    if let Some(texture) = self.samplers.get(&sampler) {
+                        unsafe {
+                            gl::ActiveTexture(gl::TEXTURE0 + unit);
+                        }
+                        texture.bind();
+                    }
+                }
+                Descriptor::UniformBlock { buffer, binding } => {
+                    if let Some(buffer) = self.buffers.get(&buffer) {
+                        unsafe {
+                            gl::BindBufferBase(gl::UNIFORM_BUFFER, binding, buffer.object.name());
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        descriptor_set.vao.bind();
+
+        Some(descriptor_set)
+    }
+
+    fn draw(&self, command: &DrawCommand) {
+        if self.bind_descriptor_set(command.index).is_none() {
+            return;
+        }
+        unsafe {
+            if command.instance_count == 1 {
+                gl::DrawArrays(gl::TRIANGLES, command.first as GLint, command.count as GLsizei);
+            } else {
+                gl::DrawArraysInstanced(
+                    gl::TRIANGLES, command.first as GLint, command.count as GLsizei, command.instance_count as GLsizei
+                );
+            }
+        }
+    }
+
+    fn draw_indexed(&self, command: &DrawIndexedCommand) {
+        let descriptor_set = match self.bind_descriptor_set(command.index) {
+            Some(val) => val,
+            None => return,
+        };
+        let index_buffer = descriptor_set.set.descriptors.iter().filter_map(|descriptor| {
+            match *descriptor {
+                Descriptor::IndexBuffer { buffer } => self.buffers.get(&buffer),
+                _ => None,
+            }
+        }).next();
+        let index_buffer = match index_buffer {
+            Some(val) => val,
+            None => return,
+        };
+
+        let index_type = index_buffer.layout.ty.0;
+        let offset = index_buffer.layout.offset.0 + command.first as usize * index_size(index_type);
+        let count = command.count as GLsizei;
+        unsafe {
+            match (command.base_vertex, command.instance_count) {
+                (0, 1) => gl::DrawElements(gl::TRIANGLES, count, index_type, offset as *const GLvoid),
+                (base_vertex, 1) => draw_elements_base_vertex(count, index_type, offset, base_vertex),
+                (0, instance_count) => gl::DrawElementsInstanced(
+                    gl::TRIANGLES, count, index_type, offset as *const GLvoid, instance_count as GLsizei
+                ),
+                (base_vertex, instance_count) => draw_elements_instanced_base_vertex(
+                    count, index_type, offset, base_vertex, instance_count
+                ),
+            }
+        }
+    }
+
+    /// Merge instance draws with the same key into instanced draws and submit them,
+    /// in the order each key first appears. Empties `draws`.
+    fn draw_batches(&mut self, draws: &mut Vec<&DrawInstanceCommand>) -> Result<(), Error> {
+        let mut batches: Vec<(BatchKey, Vec<u8>, u32)> = vec![];
+        let mut batch_indices: HashMap<BatchKey, usize> = HashMap::new();
+        for draw in draws.drain(..) {
+            let key = BatchKey {
+                index: draw.index,
+                first: draw.first,
+                count: draw.count,
+                base_vertex: draw.base_vertex,
+            };
+            let batch = *batch_indices.entry(key).or_insert_with(|| {
+                batches.push((key, vec![], 0));
+                batches.len() - 1
+            });
+            batches[batch].1.extend_from_slice(&draw.instance);
+            batches[batch].2 += 1;
+        }
+
+        for (key, instance_data, instance_count) in batches {
+            let (instance_buffer, indexed) = match self.descriptors.get(&key.index) {
+                Some(descriptor_set) => {
+                    let descriptors = &descriptor_set.set.descriptors;
+                    let instance_buffer = descriptors.iter().filter_map(|descriptor| {
+                        match *descriptor {
+                            Descriptor::InstanceBuffer { buffer, .. } => Some(buffer),
+                            _ => None,
+                        }
+                    }).next();
+                    let indexed = descriptors.iter().any(|descriptor| {
+                        match *descriptor {
+                            Descriptor::IndexBuffer { .. } => true,
+                            _ => false,
+                        }
+                    });
+
+                    (instance_buffer, indexed)
+                }
+                None => continue,
+            };
+            if let Some(buffer) = instance_buffer {
+                self.upload(buffer, &instance_data)?;
+            }
+            if indexed {
+                self.draw_indexed(&DrawIndexedCommand {
+                    index: key.index,
+                    first: key.first,
+                    count: key.count,
+                    base_vertex: key.base_vertex,
+                    instance_count: instance_count,
+                    bounds: None,
+                });
+            } else {
+                self.draw(&DrawCommand {
+                    index: key.index,
+                    first: key.first,
+                    count: key.count,
+                    instance_count: instance_count,
+                    bounds: None,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Pick the level of detail of an object for this frame and remember it. Without
+    /// a view projection matrix the most detailed level is used.
+    fn select_lod(&mut self, command: &DrawLodCommand) -> Option<usize> {
+        if command.levels.is_empty() {
+            return None;
+        }
+        let view_projection = self.view_projection;
+        let object = self.lod_objects.get_mut(&command.lod)?;
+        let level = match view_projection {
+            Some(ref m) => {
+                let size = lod::projected_size(m, &command.bounds.sphere);
+                object.selector.select(size, object.level)
+            }
+            None => 0,
+        };
+        let level = usize::min(level, command.levels.len() - 1);
+        object.level = Some(level);
+
+        Some(level)
+    }
+
+    /// Execute the queued commands in order and empty the queue. Draws outside the
+    /// view frustum are skipped, and runs of instance draws are batched. Execution
+    /// stops at the first command that fails.
+    pub fn render(&mut self) -> Result<(), Error> {
+        self.cull_stats = CullStats::default();
+        let queue: Vec<Command> = self.queue.drain(..).collect();
+        let mut batch: Vec<&DrawInstanceCommand> = vec![];
+        for command in queue.iter() {
+            if let Command::DrawInstance(ref c) = *command {
+                if self.cull(c.bounds) {
+                    batch.push(c);
+                }
+                continue;
+            }
+            self.draw_batches(&mut batch)?;
+            match *command {
+                Command::LoadBuffer(ref c) => self.load_buffer(c)?,
+                Command::UpdateBuffer(ref c) => self.update_buffer(c)?,
+                Command::Draw(ref c) => {
+                    if self.cull(c.bounds) {
+                        self.draw(c);
+                    }
+                }
+                Command::DrawIndexed(ref c) => {
+                    if self.cull(c.bounds) {
+                        self.draw_indexed(c);
+                    }
+                }
+                Command::DrawLod(ref c) => {
+                    if self.cull(Some(c.bounds)) {
+                        if let Some(level) = self.select_lod(c) {
+                            self.draw_indexed(&c.levels[level]);
+                        }
+                    }
+                }
+                Command::DrawInstance(_) => {}
+            }
+        }
+        self.draw_batches(&mut batch)?;
+
+        Ok(())
+    }
+}
+
+impl Default for Renderer {
+    /// A renderer that only uses the features of the minimum supported context.
+    fn default() -> Renderer {
+        Renderer::new(GLFeatures::default())
     }
 }
@@ -0,0 +1,453 @@
+/*
+ *  GB-Prototype is a renderer prototype demo.
+ *  Copyright (C) 2018,2019,2020  Christopher Blanchard
+ *
+ *  This program is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  This program is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+#![allow(dead_code)]
+use crate::input;
+use crate::input::{Button, Input};
+use glfw;
+use glfw::{Action, Modifiers, WindowEvent};
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::mpsc::Receiver;
+
+use log::{info, warn};
+
+
+/// The first line of every input recording.
+const RECORDING_HEADER: &str = "# gb_prototype input recording";
+
+/// The virtual clock step for recordings that do not say how long they ran.
+const DEFAULT_STEP_SECONDS: f64 = 1.0 / 60.0;
+
+
+#[derive(Clone, Debug)]
+pub enum ReplayError {
+    CouldNotCreateRecording(String),
+    CouldNotWriteRecording(String),
+    RecordingNotFound(String),
+    CouldNotParseRecording(usize, String),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &ReplayError::CouldNotCreateRecording(ref file_name) => {
+                write!(f, "Could not create the input recording file for writing: {}", file_name)
+            }
+            &ReplayError::CouldNotWriteRecording(ref file_name) => {
+                write!(f, "The input recording file was created, but there was an error in writing it: {}", file_name)
+            }
+            &ReplayError::RecordingNotFound(ref file_name) => {
+                write!(f, "Could not open the input recording file for reading: {}", file_name)
+            }
+            &ReplayError::CouldNotParseRecording(line, ref reason) => {
+                write!(f, "The input recording is malformed on line {}: {}", line, reason)
+            }
+        }
+    }
+}
+
+/// A single input event together with the frame it arrived in and its GLFW timestamp.
+#[derive(Clone, Debug)]
+pub struct InputRecord {
+    pub frame: u32,
+    pub time: f64,
+    pub event: WindowEvent,
+}
+
+fn action_name(action: Action) -> &'static str {
+    match action {
+        Action::Press => "press",
+        Action::Release => "release",
+        Action::Repeat => "repeat",
+    }
+}
+
+fn parse_action(name: &str) -> Option<Action> {
+    match name {
+        "press" => Some(Action::Press),
+        "release" => Some(Action::Release),
+        "repeat" => Some(Action::Repeat),
+        _ => None,
+    }
+}
+
+/// Parse the modifier keys of a button event. Recordings written before modifiers
+/// were recorded leave them out, so a missing field means no modifiers.
+fn parse_modifiers(field: Option<&str>) -> Result<Modifiers, String> {
+    match field {
+        Some(bits) => match bits.parse() {
+            Ok(bits) => Ok(Modifiers::from_bits_truncate(bits)),
+            Err(_) => Err(format!("invalid modifiers `{}`", bits)),
+        },
+        None => Ok(Modifiers::empty()),
+    }
+}
+
+/// Format an input event as a line of a recording. Returns `None` for events that
+/// do not carry input, and for keys without a name, which cannot be played back.
+fn format_record(frame: u32, time: f64, event: &WindowEvent) -> Option<String> {
+    let body = match *event {
+        WindowEvent::Key(key, scancode, action, mods) => {
+            let name = match input::button_name(Button::Key(key)) {
+                Some(val) => val,
+                None => {
+                    warn!("Skipping the key with scancode {} in the input recording, since it has no name", scancode);
+                    return None;
+                }
+            };
+            format!("key {} {} {}", name, action_name(action), mods.bits())
+        }
+        WindowEvent::MouseButton(button, action, mods) => {
+            let name = input::button_name(Button::Mouse(button))?;
+            format!("mouse {} {} {}", name, action_name(action), mods.bits())
+        }
+        WindowEvent::CursorPos(x, y) => format!("cursor {} {}", x, y),
+        WindowEvent::Scroll(x, y) => format!("scroll {} {}", x, y),
+        WindowEvent::Char(ch) => format!("char {}", ch as u32),
+        _ => return None,
+    };
+
+    Some(format!("{} {} {}", frame, time, body))
+}
+
+/// Parse an event line of a recording.
+fn parse_record(line: &str) -> Result<InputRecord, String> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.len() < 3 {
+        return Err(String::from("expected a frame, a time, and an event"));
+    }
+    let frame = fields[0].parse::<u32>().map_err(|_| format!("invalid frame `{}`", fields[0]))?;
+    let time = fields[1].parse::<f64>().map_err(|_| format!("invalid time `{}`", fields[1]))?;
+    let arg = |i: usize| -> Result<&str, String> {
+        fields.get(i).cloned().ok_or_else(|| format!("missing argument for `{}` event", fields[2]))
+    };
+    let float_arg = |i: usize| -> Result<f64, String> {
+        let s = arg(i)?;
+        s.parse::<f64>().map_err(|_| format!("invalid number `{}`", s))
+    };
+    let button_event = |i: usize| -> Result<(Button, Action, Modifiers), String> {
+        let button = input::parse_button(arg(i)?).map_err(|e| format!("{}", e))?;
+        let action = parse_action(arg(i + 1)?).ok_or_else(|| format!("invalid action `{}`", fields[i + 1]))?;
+        let mods = parse_modifiers(fields.get(i + 2).cloned())?;
+        Ok((button, action, mods))
+    };
+
+    let event = match fields[2] {
+        "key" => match button_event(3)? {
+            (Button::Key(key), action, mods) => WindowEvent::Key(key, 0, action, mods),
+            _ => return Err(format!("`{}` is not a key", fields[3])),
+        },
+        "mouse" => match button_event(3)? {
+            (Button::Mouse(button), action, mods) => WindowEvent::MouseButton(button, action, mods),
+            _ => return Err(format!("`{}` is not a mouse button", fields[3])),
+        },
+        "cursor" => WindowEvent::CursorPos(float_arg(3)?, float_arg(4)?),
+        "scroll" => WindowEvent::Scroll(float_arg(3)?, float_arg(4)?),
+        "char" => {
+            let code = arg(3)?.parse::<u32>().ok().and_then(std::char::from_u32);
+            match code {
+                Some(ch) => WindowEvent::Char(ch),
+                None => return Err(format!("invalid character code `{}`", fields[3])),
+            }
+        }
+        other => return Err(format!("unknown event `{}`", other)),
+    };
+
+    Ok(InputRecord {
+        frame: frame,
+        time: time,
+        event: event,
+    })
+}
+
+/// Writes every input event of a run to a recording as it happens.
+pub struct InputRecorder<W: Write = BufWriter<File>> {
+    file_name: String,
+    writer: W,
+    start_time: Option<f64>,
+    last_time: f64,
+}
+
+impl InputRecorder<BufWriter<File>> {
+    /// Create a new recording file, replacing any existing file.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<InputRecorder, ReplayError> {
+        let disp = path.as_ref().display().to_string();
+        let file = match File::create(&path) {
+            Ok(val) => val,
+            Err(_) => return Err(ReplayError::CouldNotCreateRecording(disp)),
+        };
+        let recorder = InputRecorder::new(BufWriter::new(file), &disp)?;
+        info!("Recording input to {}", disp);
+
+        Ok(recorder)
+    }
+}
+
+impl<W: Write> InputRecorder<W> {
+    /// Start a recording on any writer. The name is used in error messages.
+    pub fn new(mut writer: W, name: &str) -> Result<InputRecorder<W>, ReplayError> {
+        if writeln!(writer, "{}", RECORDING_HEADER).is_err() {
+            return Err(ReplayError::CouldNotWriteRecording(String::from(name)));
+        }
+
+        Ok(InputRecorder {
+            file_name: String::from(name),
+            writer: writer,
+            start_time: None,
+            last_time: 0.0,
+        })
+    }
+
+    /// Mark the start of a frame at the given time.
+    pub fn begin_frame(&mut self, time: f64) {
+        if self.start_time.is_none() {
+            self.start_time = Some(time);
+        }
+        self.last_time = time;
+    }
+
+    /// Append an event to the recording. Events that do not carry input are skipped,
+    /// and so are keys without a name, with a warning.
+    pub fn record(&mut self, frame: u32, time: f64, event: &WindowEvent) -> Result<(), ReplayError> {
+        if let Some(line) = format_record(frame, time, event) {
+            if writeln!(self.writer, "{}", line).is_err() {
+                return Err(ReplayError::CouldNotWriteRecording(self.file_name.clone()));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Finish the recording. The frame count and the running time are written at the
+    /// end, so playback can run the same number of frames with the same average step.
+    /// Returns the writer the recording went to.
+    pub fn finish(mut self, frames: u32) -> Result<W, ReplayError> {
+        let duration = self.last_time - self.start_time.unwrap_or(self.last_time);
+        let result = writeln!(self.writer, "end {} {}", frames, duration)
+            .and_then(|_| self.writer.flush());
+        if result.is_err() {
+            return Err(ReplayError::CouldNotWriteRecording(self.file_name));
+        }
+        info!("Recorded {} frames of input to {}", frames, self.file_name);
+
+        Ok(self.writer)
+    }
+}
+
+/// Plays back a recording in place of the events from GLFW.
+pub struct InputPlayback {
+    records: Vec<InputRecord>,
+    next: usize,
+    frames: Option<u32>,
+    step_seconds: f64,
+}
+
+impl InputPlayback {
+    /// Load a recording file written by an `InputRecorder`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<InputPlayback, ReplayError> {
+        let disp = path.as_ref().display().to_string();
+        let text = match fs::read_to_string(&path) {
+            Ok(val) => val,
+            Err(_) => return Err(ReplayError::RecordingNotFound(disp)),
+        };
+
+        InputPlayback::parse(&text, &disp)
+    }
+
+    /// Parse a recording written by an `InputRecorder`. The name is used in log messages.
+    pub fn parse(text: &str, name: &str) -> Result<InputPlayback, ReplayError> {
+        let mut records = vec![];
+        let mut frames = None;
+        let mut step_seconds = DEFAULT_STEP_SECONDS;
+        for (i, line) in text.lines().enumerate() {
+            let line_number = i + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with("end") {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                let frame_count = fields.get(1).and_then(|s| s.parse::<u32>().ok());
+                let duration = fields.get(2).and_then(|s| s.parse::<f64>().ok());
+                match (frame_count, duration) {
+                    (Some(frame_count), Some(duration)) => {
+                        frames = Some(frame_count);
+                        if frame_count > 1 && duration > 0.0 {
+                            step_seconds = duration / (frame_count - 1) as f64;
+                        }
+                    }
+                    _ => {
+                        let reason = String::from("expected `end <frames> <seconds>`");
+                        return Err(ReplayError::CouldNotParseRecording(line_number, reason));
+                    }
+                }
+                continue;
+            }
+            match parse_record(line) {
+                Ok(record) => records.push(record),
+                Err(reason) => return Err(ReplayError::CouldNotParseRecording(line_number, reason)),
+            }
+        }
+        info!("Loaded {} input events from {}", records.len(), name);
+
+        Ok(InputPlayback {
+            records: records,
+            next: 0,
+            frames: frames,
+            step_seconds: step_seconds,
+        })
+    }
+
+    /// The step of the virtual clock that drives the playback.
+    #[inline]
+    pub fn step_seconds(&self) -> f64 {
+        self.step_seconds
+    }
+
+    /// Take the recorded events for a frame. Frames must be requested in order.
+    pub fn events_for_frame(&mut self, frame: u32) -> Vec<WindowEvent> {
+        let mut events = vec![];
+        while self.next < self.records.len() && self.records[self.next].frame <= frame {
+            events.push(self.records[self.next].event.clone());
+            self.next += 1;
+        }
+
+        events
+    }
+
+    /// Determine whether the playback has run out after `frames` frames.
+    pub fn is_finished(&self, frames: u32) -> bool {
+        match self.frames {
+            Some(total) => frames >= total,
+            None => self.next >= self.records.len(),
+        }
+    }
+}
+
+/// Where the input for a run comes from.
+pub enum InputSource {
+    /// Read input from GLFW.
+    Live,
+    /// Read input from GLFW and write it to a recording.
+    Record(InputRecorder),
+    /// Read input from a recording and discard the events from GLFW.
+    Playback(InputPlayback),
+}
+
+impl InputSource {
+    /// Start a new input frame and feed it the events for this frame.
+    pub fn update(
+        &mut self,
+        input: &mut Input, events: &Receiver<(f64, WindowEvent)>,
+        frame: u32, time: f64) -> Result<(), ReplayError> {
+
+        input.state.begin_frame();
+        match self {
+            &mut InputSource::Live => {
+                input.state.drain_events(events);
+            }
+            &mut InputSource::Record(ref mut recorder) => {
+                recorder.begin_frame(time);
+                for (event_time, event) in glfw::flush_messages(events) {
+                    recorder.record(frame, event_time, &event)?;
+                    input.state.process_event(&event);
+                }
+            }
+            &mut InputSource::Playback(ref mut playback) => {
+                // The window still produces events, so drain them to keep the queue empty.
+                for _ in glfw::flush_messages(events) {}
+                for event in playback.events_for_frame(frame).iter() {
+                    input.state.process_event(event);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Determine whether the input source has run out after `frames` frames.
+    pub fn is_finished(&self, frames: u32) -> bool {
+        match self {
+            &InputSource::Playback(ref playback) => playback.is_finished(frames),
+            _ => false,
+        }
+    }
+
+    /// Finish the input source after `frames` frames, completing any recording.
+    pub fn finish(self, frames: u32) -> Result<(), ReplayError> {
+        match self {
+            InputSource::Record(recorder) => recorder.finish(frames).map(|_| ()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glfw::{Key, MouseButton};
+
+
+    #[test]
+    fn test_record_then_playback() {
+        let frames: Vec<(u32, f64, WindowEvent)> = vec![
+            (0, 0.0, WindowEvent::Key(Key::W, 0, Action::Press, Modifiers::empty())),
+            (0, 0.001, WindowEvent::CursorPos(12.5, 300.0)),
+            (2, 0.034, WindowEvent::MouseButton(MouseButton::Button1, Action::Press, Modifiers::Shift)),
+            (2, 0.035, WindowEvent::Scroll(0.0, -1.0)),
+            (3, 0.05, WindowEvent::Char('q')),
+            (5, 0.084, WindowEvent::Key(Key::W, 0, Action::Release, Modifiers::Control | Modifiers::Alt)),
+        ];
+
+        let mut recorder = InputRecorder::new(Vec::new(), "memory").unwrap();
+        for frame in 0..6 {
+            recorder.begin_frame(frame as f64 / 60.0);
+            for &(event_frame, time, ref event) in frames.iter().filter(|r| r.0 == frame) {
+                recorder.record(event_frame, time, event).unwrap();
+            }
+            // Events without input are not recorded.
+            recorder.record(frame, 0.0, &WindowEvent::Focus(true)).unwrap();
+        }
+        let bytes = recorder.finish(6).unwrap();
+        let text = String::from_utf8(bytes).unwrap();
+
+        let mut playback = InputPlayback::parse(&text, "memory").unwrap();
+        assert_eq!(playback.step_seconds(), (5.0 / 60.0) / 5.0);
+        for frame in 0..6 {
+            assert!(!playback.is_finished(frame));
+            let expected: Vec<WindowEvent> = frames.iter()
+                .filter(|r| r.0 == frame)
+                .map(|r| r.2.clone())
+                .collect();
+            assert_eq!(playback.events_for_frame(frame), expected);
+        }
+        assert!(playback.is_finished(6));
+    }
+
+    #[test]
+    fn test_parse_malformed_recording() {
+        let text = format!("{}\n0 0 key W press 0\n1 0.5 key NotAKey press 0\n", RECORDING_HEADER);
+        match InputPlayback::parse(&text, "memory") {
+            Err(ReplayError::CouldNotParseRecording(line, _)) => assert_eq!(line, 3),
+            other => panic!("expected a malformed recording, got {:?}", other.err()),
+        }
+    }
+}
@@ -0,0 +1,246 @@
+/*
+ *  GB-Prototype is a renderer prototype demo.
+ *  Copyright (C) 2018,2019,2020  Christopher Blanchard
+ *
+ *  This program is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  This program is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+#![allow(dead_code)]
+//! Interleaved vertex buffers. A `VertexLayout` places every attribute of a mesh
+//! side by side in one buffer, and the packers fill buffers in that layout for
+//! one mesh or for several meshes sharing a buffer.
+use crate::gl;
+use crate::gl::types::{GLint, GLsizei, GLsizeiptr, GLuint, GLvoid};
+use crate::gl_backend as glh;
+use crate::gl_objects::{BufferObject, VertexArray};
+use crate::mesh::{AttributeFormat, Indices, Mesh, Semantic};
+use crate::renderer::{BufferLayout, ComponentKind, Offset, Size, Stride, Type};
+
+use std::fmt;
+
+use log::info;
+
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PackError {
+    /// There were no meshes to pack.
+    NoMeshes,
+    /// A mesh has different attributes than the first mesh in the buffer. It
+    /// carries the position of the mesh.
+    LayoutMismatch(usize),
+}
+
+impl fmt::Display for PackError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &PackError::NoMeshes => {
+                write!(f, "There are no meshes to pack into a vertex buffer")
+            }
+            &PackError::LayoutMismatch(mesh) => {
+                write!(f, "Mesh {} does not have the same attributes as the first mesh in the vertex buffer", mesh)
+            }
+        }
+    }
+}
+
+/// The place of one attribute in an interleaved vertex buffer.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AttributeLayout {
+    pub semantic: Semantic,
+    pub format: AttributeFormat,
+    pub layout: BufferLayout,
+}
+
+/// The layout of an interleaved vertex buffer. Each vertex takes `stride` bytes,
+/// holding the attributes in order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VertexLayout {
+    pub attributes: Vec<AttributeLayout>,
+    pub stride: usize,
+}
+
+impl VertexLayout {
+    /// Lay out the attributes of a mesh in the order they were added to it. Every
+    /// attribute format is a multiple of four bytes, so every attribute starts on
+    /// a four byte boundary.
+    pub fn from_mesh(mesh: &Mesh) -> VertexLayout {
+        let stride: usize = mesh.attributes().iter().map(|attribute| attribute.format().size()).sum();
+        let mut offset = 0;
+        let mut attributes = vec![];
+        for attribute in mesh.attributes().iter() {
+            let format = attribute.format();
+            attributes.push(AttributeLayout {
+                semantic: attribute.semantic.clone(),
+                format: format,
+                layout: BufferLayout {
+                    offset: Offset(offset),
+                    stride: Stride(stride),
+                    size: Size(format.component_count()),
+                    ty: Type(format.gl_type()),
+                    kind: ComponentKind::from(format),
+                },
+            });
+            offset += format.size();
+        }
+
+        VertexLayout {
+            attributes: attributes,
+            stride: stride,
+        }
+    }
+
+    /// Look up the place of an attribute.
+    #[inline]
+    pub fn attribute(&self, semantic: &Semantic) -> Option<&AttributeLayout> {
+        self.attributes.iter().find(|attribute| &attribute.semantic == semantic)
+    }
+
+    /// Determine whether a mesh has exactly the attributes of this layout.
+    pub fn matches(&self, mesh: &Mesh) -> bool {
+        mesh.attributes().len() == self.attributes.len() && mesh.attributes().iter().all(|attribute| {
+            self.attribute(&attribute.semantic).map_or(false, |layout| layout.format == attribute.format())
+        })
+    }
+}
+
+/// Interleave the attributes of a mesh into the bytes of a vertex buffer. The mesh
+/// must match the layout, e.g. the layout is made from the mesh.
+pub fn pack_vertices(mesh: &Mesh, layout: &VertexLayout) -> Vec<u8> {
+    debug_assert!(layout.matches(mesh));
+    let vertex_count = mesh.len();
+    let mut data = vec![0_u8; vertex_count * layout.stride];
+    for attribute in layout.attributes.iter() {
+        let source = match mesh.attribute(&attribute.semantic) {
+            Some(val) => val.data.as_bytes(),
+            None => continue,
+        };
+        let size = attribute.format.size();
+        let offset = attribute.layout.offset.0;
+        for (vertex, value) in source.chunks(size).take(vertex_count).enumerate() {
+            let start = vertex * layout.stride + offset;
+            data[start..start + size].copy_from_slice(value);
+        }
+    }
+
+    data
+}
+
+/// The part of a shared buffer that holds one mesh. Draw it with `index_count`
+/// indices from `first_index`, adding `base_vertex` to each index.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MeshRange {
+    pub base_vertex: u32,
+    pub vertex_count: u32,
+    pub first_index: u32,
+    pub index_count: u32,
+}
+
+/// Several meshes packed into one vertex buffer and one index buffer.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PackedMeshes {
+    pub layout: VertexLayout,
+    pub vertices: Vec<u8>,
+    pub indices: Indices,
+    /// The range of each mesh, in the order the meshes were packed.
+    pub ranges: Vec<MeshRange>,
+}
+
+/// Pack meshes with the same attributes into one shared vertex buffer and index
+/// buffer. The indices of each mesh stay relative to its first vertex, so they fit
+/// in 16 bits as long as every mesh does. Meshes without an index array get one.
+pub fn pack_meshes(meshes: &[&Mesh]) -> Result<PackedMeshes, PackError> {
+    let layout = match meshes.first() {
+        Some(mesh) => VertexLayout::from_mesh(mesh),
+        None => return Err(PackError::NoMeshes),
+    };
+    if let Some(mismatch) = meshes.iter().position(|mesh| !layout.matches(mesh)) {
+        return Err(PackError::LayoutMismatch(mismatch));
+    }
+
+    let mut vertices = vec![];
+    let mut indices = vec![];
+    let mut ranges = vec![];
+    for mesh in meshes.iter() {
+        let corners = mesh.triangle_corners();
+        ranges.push(MeshRange {
+            base_vertex: (vertices.len() / usize::max(layout.stride, 1)) as u32,
+            vertex_count: mesh.len() as u32,
+            first_index: indices.len() as u32,
+            index_count: corners.len() as u32,
+        });
+        vertices.extend(pack_vertices(mesh, &layout));
+        indices.extend(corners);
+    }
+
+    Ok(PackedMeshes {
+        layout: layout,
+        vertices: vertices,
+        indices: Indices::new(&indices),
+        ranges: ranges,
+    })
+}
+
+impl PackedMeshes {
+    /// Add the base vertex of each mesh to its indices, for drawing without base
+    /// vertex support, e.g. on OpenGL ES 3.0. The indices widen to 32 bits if a
+    /// baked index no longer fits in 16 bits.
+    pub fn bake_base_vertices(&mut self) {
+        let mut indices: Vec<u32> = self.indices.iter().collect();
+        for range in self.ranges.iter_mut() {
+            let first = range.first_index as usize;
+            for index in indices[first..first + range.index_count as usize].iter_mut() {
+                *index += range.base_vertex;
+            }
+            range.base_vertex = 0;
+        }
+        self.indices = Indices::new(&indices);
+    }
+}
+
+/// Load an interleaved vertex buffer and point the attributes of a vertex array at
+/// it. Each attribute feeds the shader input named after its semantic, and the
+/// shader program ignores attributes it has no input for.
+pub fn setup_vertex_array(vao: &VertexArray, vbo: &BufferObject, sp: GLuint, layout: &VertexLayout, data: &[u8]) {
+    vao.bind();
+    vbo.bind(gl::ARRAY_BUFFER);
+    unsafe {
+        gl::BufferData(gl::ARRAY_BUFFER, data.len() as GLsizeiptr, data.as_ptr() as *const GLvoid, gl::STATIC_DRAW);
+    }
+    for attribute in layout.attributes.iter() {
+        let name = attribute.semantic.shader_name();
+        let location = unsafe {
+            gl::GetAttribLocation(sp, glh::gl_str(&name).as_ptr())
+        };
+        if location < 0 {
+            info!("The shader program {} has no input {}", sp, name);
+            continue;
+        }
+        let location = location as GLuint;
+
+        let format = attribute.format;
+        let size = attribute.layout.size.0 as GLint;
+        let stride = attribute.layout.stride.0 as GLsizei;
+        let offset = attribute.layout.offset.0 as *const GLvoid;
+        unsafe {
+            // Integer attributes need the integer pointer function, or the shader
+            // reads them as floats.
+            if format.is_integer() {
+                gl::VertexAttribIPointer(location, size, attribute.layout.ty.0, stride, offset);
+            } else {
+                let normalized = if format.is_normalized() { gl::TRUE } else { gl::FALSE };
+                gl::VertexAttribPointer(location, size, attribute.layout.ty.0, normalized, stride, offset);
+            }
+            gl::EnableVertexAttribArray(location);
+        }
+    }
+}